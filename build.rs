@@ -8,7 +8,7 @@ fn main() {
     if !commit_hash.is_empty() {
         crate_version = format!("{} {}", crate_version, commit_hash);
     } else {
-        println!("cargo:warning={}", "Git commit not found!")
+        println!("cargo:warning=Git commit not found!")
     }
 
     println!("cargo:rustc-env=CRATE_VERSION={}", crate_version);
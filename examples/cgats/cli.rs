@@ -20,7 +20,31 @@ pub fn build_cli() -> App<'static, 'static> {
             .alias("avg")
             .about("Average 2 or more CGATS color files")
             .arg(cgats_files(true))
-            .arg(output()))
+            .arg(output())
+            .arg(Arg::with_name("AVGMETHOD")
+                .value_name("METHOD")
+                .takes_value(true)
+                .short("m")
+                .long("method")
+                .help("Average method: mean, median, trimmed[:FRACTION] or weighted (inverse-variance) \
+                    [default: mean]"))
+            .arg(Arg::with_name("REJECT")
+                .value_name("DE")
+                .takes_value(true)
+                .short("r")
+                .long("reject")
+                .help("Reject sheets whose DE2000 to the preliminary mean of a sample exceeds <DE>"))
+            .arg(Arg::with_name("STDEV")
+                .takes_value(false)
+                .short("s")
+                .long("stdev")
+                .help("Write the standard deviation of LAB, XYZ and DE as STDEV_* columns"))
+            .arg(Arg::with_name("COLORIMETRIC")
+                .takes_value(false)
+                .short("c")
+                .long("colorimetric")
+                .help("Average XYZ and reflectance, deriving LAB and density from the result"))
+            .arg(allow_mismatch()))
         .subcommand(SubCommand::with_name("cat")
             .aliases(&["concatenate", "concat", "append"])
             .about("Concatenate 2 or more CGATS color files")
@@ -31,18 +55,223 @@ pub fn build_cli() -> App<'static, 'static> {
             .about("Calculate the Delta E between each sample in two CGATS files")
             .arg(cgats_files(true))
             .arg(output())
-            .arg(Arg::with_name("DEMETHOD")
-                .value_name("DE_METHOD")
+            .arg(de_method())
+            .arg(Arg::with_name("DECOLUMNS")
+                .value_name("FIELDS")
                 .takes_value(true)
-                .short("m")
-                .long("method")
-                .help("Delta E method to use in the calculations")
-                .possible_values(&["2000", "1994", "1994t", "cmc1", "cmc2", "1976"]))
+                .short("c")
+                .long("columns")
+                .use_delimiter(true)
+                .multiple(true)
+                .help("Difference columns to write, e.g. DE_2000,DELTA_L,DELTA_C,DELTA_H,DE2000_L"))
             .arg(Arg::with_name("DEREPORT")
                 .takes_value(false)
                 .short("r")
                 .long("report")
-                .help("Print Delta E statistical report")))
+                .help("Print Delta E statistical report"))
+            .arg(Arg::with_name("REPORTFORMAT")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .long("report-format")
+                .possible_values(&["text", "json", "csv", "cgats"])
+                .help("Format of the Delta E report; implies --report [default: text]"))
+            .arg(report_list("PERCENTILES", "percentiles", "p",
+                "Percentiles to include in the report, e.g. 50,95,99"))
+            .arg(report_list("SPLITS", "split", "s",
+                "Split points for the best/worst summaries in the report [default: 90]"))
+            .arg(report_list("HISTOGRAM", "histogram", "H",
+                "Histogram bin edges to include in the report, e.g. 1,2,3,4,5"))
+            .arg(Arg::with_name("WORST")
+                .value_name("N")
+                .takes_value(true)
+                .short("w")
+                .long("worst")
+                .help("List the N worst samples in the report"))
+            .arg(allow_mismatch()))
+        .subcommand(SubCommand::with_name("verify")
+            .about("Verify a measured CGATS file against a reference with a tolerance profile")
+            .arg(cgats_files(true))
+            .arg(output())
+            .arg(profile())
+            .arg(tolerances()))
+        .subcommand(SubCommand::with_name("report")
+            .about("Write an HTML report comparing a measured CGATS file with a reference")
+            .arg(cgats_files(true))
+            .arg(output())
+            .arg(de_method())
+            .arg(profile())
+            .arg(tolerances())
+            .arg(Arg::with_name("TITLE")
+                .value_name("TITLE")
+                .takes_value(true)
+                .long("title")
+                .help("Title of the report")))
+        .subcommand(SubCommand::with_name("convert")
+            .about("Convert CGATS, CSV/TSV, Argyll or CxF files to another format")
+            .arg(cgats_files(true))
+            .arg(output())
+            .arg(Arg::with_name("TO")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .short("t")
+                .long("to")
+                .possible_values(CONVERT_FORMATS)
+                .help("Format to write [default: cgats]"))
+            .arg(Arg::with_name("DECIMALCOMMA")
+                .takes_value(false)
+                .long("decimal-comma")
+                .help("Write CSV/TSV numbers with a decimal comma; CSV is then separated by semicolons")))
+        .subcommand(target())
+        .subcommand(SubCommand::with_name("randomize")
+            .about("Shuffle the patches of a target into a reproducible random order, or restore it")
+            .arg(cgats_files(true).help("CGATS file; with --restore, optionally followed by the randomized target"))
+            .arg(output())
+            .arg(Arg::with_name("SEED")
+                .value_name("SEED")
+                .takes_value(true)
+                .short("s")
+                .long("seed")
+                .required_unless("RESTORE")
+                .help("Seed of the random order; the same seed gives the same order"))
+            .arg(Arg::with_name("PAPERWHITE")
+                .takes_value(false)
+                .short("w")
+                .long("paper-white")
+                .help("Put a paper white patch in every row"))
+            .arg(Arg::with_name("ROWLENGTH")
+                .value_name("PATCHES")
+                .takes_value(true)
+                .short("l")
+                .long("row-length")
+                .help("Patches per row of the chart [default: the layout of the file]"))
+            .arg(Arg::with_name("RESTORE")
+                .takes_value(false)
+                .short("r")
+                .long("restore")
+                .conflicts_with("SEED")
+                .help("Restore the canonical order from the RANDOMIZED_ORDER of the file or of the randomized target")))
+}
+
+fn target() -> App<'static, 'static> {
+    let target = SubCommand::with_name("target")
+        .about("Generate a test target of device values")
+        .arg(cgats_files(false).help("CGATS files with device values to include"))
+        .arg(output())
+        .arg(Arg::with_name("SPACE")
+            .value_name("SPACE")
+            .takes_value(true)
+            .short("s")
+            .long("space")
+            .possible_values(&["cmyk", "rgb"])
+            .help("Device color space [default: cmyk]"))
+        .arg(Arg::with_name("GRID")
+            .value_name("STEPS")
+            .takes_value(true)
+            .short("g")
+            .long("grid")
+            .help("Add every combination of <STEPS> levels of each channel"))
+        .arg(Arg::with_name("RAMPS")
+            .value_name("STEPS")
+            .takes_value(true)
+            .short("r")
+            .long("ramps")
+            .help("Add a ramp of <STEPS> levels for each channel and a neutral ramp"))
+        .arg(Arg::with_name("ISO12642")
+            .takes_value(false)
+            .long("iso12642")
+            .help("Add a characterization subset built from the ISO 12642-2 tone levels"))
+        .arg(Arg::with_name("ROWLENGTH")
+            .value_name("PATCHES")
+            .takes_value(true)
+            .short("l")
+            .long("row-length")
+            .help("Patches per row of the chart [default: square root of the patch count]"));
+
+    #[cfg(feature = "render")]
+    let target = target
+        .arg(Arg::with_name("IMAGE")
+            .value_name("IMAGE")
+            .takes_value(true)
+            .short("i")
+            .long("image")
+            .help("Render the chart to a PNG or TIFF <IMAGE>; extra pages are numbered"))
+        .arg(Arg::with_name("PATCHSIZE")
+            .value_name("PIXELS")
+            .takes_value(true)
+            .long("patch-size")
+            .help("Width and height of a patch [default: 72]"))
+        .arg(Arg::with_name("GAP")
+            .value_name("PIXELS")
+            .takes_value(true)
+            .long("gap")
+            .help("Paper between the patches [default: 0]"))
+        .arg(Arg::with_name("ROWSPERPAGE")
+            .value_name("ROWS")
+            .takes_value(true)
+            .long("rows-per-page")
+            .help("Split the chart into pages of at most <ROWS> rows"))
+        .arg(Arg::with_name("DEPTH")
+            .value_name("BITS")
+            .takes_value(true)
+            .long("depth")
+            .possible_values(&["8", "16"])
+            .help("Bits per channel of the image [default: 8]"));
+
+    target
+}
+
+#[cfg(not(feature = "cxf"))]
+const CONVERT_FORMATS: &[&str] = &["cgats", "csv", "tsv", "ti1", "ti2", "ti3"];
+#[cfg(feature = "cxf")]
+const CONVERT_FORMATS: &[&str] = &["cgats", "csv", "tsv", "ti1", "ti2", "ti3", "cxf"];
+
+fn de_method() -> Arg<'static, 'static> {
+    Arg::with_name("DEMETHOD")
+        .value_name("DE_METHOD")
+        .takes_value(true)
+        .short("m")
+        .long("method")
+        .help("Delta E method to use in the calculations: \
+            2000, 1994, 1994t, cmc1, cmc2, 1976, itp, cam16ucs. \
+            CIEDE2000 takes parametric factors as 2000:kL:kC:kH")
+}
+
+fn allow_mismatch() -> Arg<'static, 'static> {
+    Arg::with_name("ALLOWMISMATCH")
+        .takes_value(false)
+        .long("allow-mismatch")
+        .help("Warn instead of failing when the files were measured under different \
+            conditions (M0-M3), illuminants or observers")
+}
+
+fn profile() -> Arg<'static, 'static> {
+    Arg::with_name("PROFILE")
+        .value_name("NAME")
+        .takes_value(true)
+        .short("p")
+        .long("profile")
+        .help("Tolerance profile to verify against: ISO12647-7, ISO12647-2, \
+            or a profile in the tolerance file [default: ISO12647-7]")
+}
+
+fn tolerances() -> Arg<'static, 'static> {
+    Arg::with_name("TOLERANCES")
+        .value_name("FILE")
+        .takes_value(true)
+        .short("t")
+        .long("tolerances")
+        .help("Read user-defined tolerance profiles from <FILE>")
+}
+
+fn report_list(name: &'static str, long: &'static str, short: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .value_name("VALUES")
+        .takes_value(true)
+        .short(short)
+        .long(long)
+        .use_delimiter(true)
+        .multiple(true)
+        .help(help)
 }
 
 fn output() -> Arg<'static, 'static> {
@@ -2,7 +2,6 @@ use cgats::*;
 use clap::ArgMatches;
 use std::str::FromStr;
 use std::io::{self, Write, stderr, stdout, Stdout, BufWriter};
-use deltae::DEMethod;
 use crate::DeReport;
 use std::fs::File;
 use std::path::Path;
@@ -10,29 +9,25 @@ use super::cli;
 
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Default)]
 pub enum Command {
+    #[default]
     Display, // Default
     Print,
     Average,
     Cat,
     Delta,
+    Verify,
+    Report,
+    Convert,
+    Target,
+    Randomize,
     // Merge
-    // Convert,
-}
-
-impl Default for Command {
-    fn default() -> Self {
-        Command::Display
-    }
 }
 
 impl Command {
     pub fn from_string(s: &str) -> Self {
-        match Command::from_str(s) {
-            Ok(cmd) => cmd,
-            Err(_) => Command::default()
-        }
+        Command::from_str(s).unwrap_or_default()
     }
 }
 
@@ -45,6 +40,11 @@ impl FromStr for Command {
             "average" | "avg" => Ok(Command::Average),
             "concatenate" | "cat" | "append" => Ok(Command::Cat),
             "delta" | "deltae" | "de" => Ok(Command::Delta),
+            "verify" => Ok(Command::Verify),
+            "report" => Ok(Command::Report),
+            "convert" => Ok(Command::Convert),
+            "target" => Ok(Command::Target),
+            "randomize" | "shuffle" => Ok(Command::Randomize),
             _ => Err(io::Error::from(io::ErrorKind::InvalidInput))
         }
     }
@@ -93,8 +93,12 @@ impl std::io::Write for CgatsWriter {
 #[derive(Debug)]
 pub struct Config<'a> {
     command: Command,
-    de_method: DEMethod,
+    de_method: DeltaEFormula,
+    de_columns: Vec<Field>,
     de_report: bool,
+    report_format: ReportFormat,
+    report_options: DeReportOptions,
+    profile: ToleranceProfile,
     files: Vec<String>,
     matches: ArgMatches<'a>,
     output: CgatsWriter,
@@ -108,9 +112,15 @@ impl<'a> Config<'a> {
         let submatches = matches.subcommand_matches(subcommand);
         let command = Command::from_string(subcommand);
 
-        let (de_method, de_report, output) = if let Some(subcmd) = submatches {
-            let method = DEMethod::from_str(subcmd.value_of("DEMETHOD").unwrap_or("DE2000")).unwrap_or_default();
-            let report = subcmd.is_present("DEREPORT");
+        let (de_method, de_columns, de_report, report_format, report_options, profile, output) = if let Some(subcmd) = submatches {
+            let method = DeltaEFormula::from_str(subcmd.value_of("DEMETHOD").unwrap_or("DE2000"))?;
+            let columns = subcmd.values_of("DECOLUMNS").unwrap_or_default()
+                .map(Field::from_str)
+                .collect::<Result<Vec<_>>>()?;
+            let report = subcmd.is_present("DEREPORT") || subcmd.is_present("REPORTFORMAT");
+            let format = ReportFormat::from_str(subcmd.value_of("REPORTFORMAT").unwrap_or("text"))?;
+            let options = report_options(subcmd)?;
+            let profile = tolerance_profile(subcmd)?;
             let file = subcmd.value_of("OUTPUTFILE");
 
             let out = if let Some(file) = file {
@@ -119,9 +129,12 @@ impl<'a> Config<'a> {
                 CgatsWriter::stdout()
             };
 
-            (method, report, out)
+            (method, columns, report, format, options, profile, out)
         } else {
-            (DEMethod::default(), false, CgatsWriter::stdout())
+            (
+                DeltaEFormula::default(), Vec::new(), false, ReportFormat::default(), DeReportOptions::default(),
+                ToleranceProfile::iso_12647_7(), CgatsWriter::stdout(),
+            )
         };
 
 
@@ -135,19 +148,117 @@ impl<'a> Config<'a> {
                 .collect::<Vec<_>>(),
         };
 
-        Ok(Self { command, de_method, de_report, files, matches, output})
+        Ok(Self { command, de_method, de_columns, de_report, report_format, report_options, profile, files, matches, output})
     }
 
     pub fn usage(&self) -> String {
         self.matches.usage().to_owned()
     }
 
+    fn subcommand_value(&self, name: &str) -> Option<String> {
+        self.matches.subcommand_matches(self.matches.subcommand_name()?)?
+            .value_of(name)
+            .map(String::from)
+    }
+
+    fn average_options(&self) -> Result<Option<AverageOptions>> {
+        let method = self.subcommand_value("AVGMETHOD");
+        let reject = self.subcommand_value("REJECT");
+        let flag = |name| self.matches.subcommand_matches("average")
+            .map(|subcmd| subcmd.is_present(name))
+            .unwrap_or(false);
+        let (stdev, colorimetric) = (flag("STDEV"), flag("COLORIMETRIC"));
+
+        if method.is_none() && reject.is_none() && !stdev && !colorimetric {
+            return Ok(None);
+        }
+
+        let mut options = AverageOptions::new().stdev(stdev).colorimetric(colorimetric);
+        if let Some(method) = method {
+            options = options.method(AverageMethod::from_str(&method)?);
+        }
+        if let Some(reject) = reject {
+            let threshold = reject.parse::<f32>()
+                .map_err(|_| Error::Other(format!("Invalid number: '{}'", reject)))?;
+            options = options.reject_outliers(threshold, DeltaEFormula::default());
+        }
+
+        Ok(Some(options))
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.files.is_empty()
+        // A target can be generated without input files
+        self.files.is_empty() && self.command != Command::Target
+    }
+
+    fn subcommand_number(&self, name: &str) -> Result<Option<usize>> {
+        self.subcommand_value(name)
+            .map(|value| value.parse::<usize>()
+                .map_err(|_| Error::Other(format!("Invalid number: '{}'", value))))
+            .transpose()
+    }
+
+    fn target(&mut self, cgv: &CgatsVec) -> Result<()> {
+        let space = DeviceSpace::from_str(&self.subcommand_value("SPACE").unwrap_or_else(|| "cmyk".into()))?;
+        let mut generator = TargetGenerator::new(space);
+
+        for cgats in &cgv.collection {
+            generator = generator.values(TargetGenerator::from_cgats(cgats)?.device_values());
+        }
+        if let Some(steps) = self.subcommand_number("GRID")? {
+            generator = generator.grid(steps);
+        }
+        if let Some(steps) = self.subcommand_number("RAMPS")? {
+            generator = generator.ramps(steps);
+        }
+        if self.matches.subcommand_matches("target").is_some_and(|subcmd| subcmd.is_present("ISO12642")) {
+            generator = generator.iso_12642();
+        }
+        if let Some(row_length) = self.subcommand_number("ROWLENGTH")? {
+            generator = generator.row_length(row_length);
+        }
+
+        let target = generator.build()?;
+        write!(self.output, "{}", target)?;
+
+        #[cfg(feature = "render")]
+        if let Some(image) = self.subcommand_value("IMAGE") {
+            let mut options = RenderOptions::new().format(ImageFormat::from_str(
+                Path::new(&image).extension().and_then(|ext| ext.to_str()).unwrap_or("tif")
+            )?);
+            if let Some(size) = self.subcommand_number("PATCHSIZE")? {
+                options = options.patch_size(size as u32);
+            }
+            if let Some(gap) = self.subcommand_number("GAP")? {
+                options = options.gap(gap as u32);
+            }
+            if let Some(rows) = self.subcommand_number("ROWSPERPAGE")? {
+                options = options.rows_per_page(rows);
+            }
+            if self.subcommand_value("DEPTH").as_deref() == Some("16") {
+                options = options.depth(BitDepth::Sixteen);
+            }
+
+            for file in target.render_to_files(&image, &options)? {
+                writeln!(stderr(), "Wrote {}", file.display())?;
+            }
+        }
+
+        Ok(())
     }
 
     pub fn execute(&mut self) -> Result<()> {
-        let cgv = CgatsVec::from_files(&self.files);
+        let allow_mismatch = self.matches.subcommand_name()
+            .and_then(|name| self.matches.subcommand_matches(name))
+            .map(|subcmd| subcmd.is_present("ALLOWMISMATCH"))
+            .unwrap_or(false);
+        let cgv = CgatsVec::from_files(&self.files).allow_mismatch(allow_mismatch);
+
+        if allow_mismatch {
+            if let Err(mismatch) = cgv.check_conditions() {
+                writeln!(stderr(), "Warning: {}", mismatch)?;
+            }
+        }
 
         match self.command {
             Command::Display => {
@@ -163,36 +274,191 @@ impl<'a> Config<'a> {
             }
 
             Command::Average => {
-                write!(self.output, "{}", cgv.average()?)?;
-                
+                match self.average_options()? {
+                    Some(options) => {
+                        let average = cgv.average_with(&options)?;
+                        write!(self.output, "{}", average.cgats)?;
+                        for rejection in &average.rejections {
+                            writeln!(stderr(), "{}", rejection)?;
+                        }
+                    },
+                    None => write!(self.output, "{}", cgv.average()?)?,
+                }
             },
 
             Command::Delta => {
-                let cgd = cgv.deltae(self.de_method)?;
+                let cgd = if self.de_columns.is_empty() {
+                    cgv.deltae_formula(self.de_method)?
+                } else {
+                    cgv.delta_with(&self.de_columns, self.de_method)?
+                };
 
                 writeln!(self.output, "{}", &cgd)?;
                 self.output.flush()?;
 
                 if self.de_report {
-                    write!(stderr(), "{}", DeReport::new(&cgd)?)?;
+                    let report = DeReport::with_options(&cgd, &self.report_options)?
+                        .with_samples(&cgv.collection[0], &cgv.collection[1]);
+                    write!(stderr(), "{}", report.format(self.report_format))?;
+                }
+            },
+
+            Command::Verify => {
+                if cgv.collection.len() != 2 {
+                    return Err(Error::CannotCompare);
                 }
+                let verification = self.profile.verify(&cgv.collection[0], &cgv.collection[1])?;
+                write!(self.output, "{}", verification)?;
+            },
+
+            Command::Report => {
+                if cgv.collection.len() != 2 {
+                    return Err(Error::CannotCompare);
+                }
+                let (reference, sample) = (&cgv.collection[0], &cgv.collection[1]);
+                let verify = self.subcommand_value("PROFILE").is_some() || self.subcommand_value("TOLERANCES").is_some();
+
+                // A verified report follows the profile's formula unless one is given
+                let formula = if verify && self.subcommand_value("DEMETHOD").is_none() {
+                    self.profile.formula()
+                } else {
+                    self.de_method
+                };
+
+                let mut report = HtmlReport::new(reference, sample, formula)?;
+
+                if let Some(title) = self.subcommand_value("TITLE") {
+                    report = report.with_title(&title);
+                }
+
+                if verify {
+                    report = report.with_verification(&self.profile)?;
+                }
+
+                write!(self.output, "{}", report)?;
             },
 
             Command::Cat => {
                 write!(self.output, "{}", cgv.concatenate()?)?;
             }
+
+            Command::Convert => {
+                let format = self.subcommand_value("TO").unwrap_or_else(|| "cgats".into());
+                let decimal_comma = self.matches.subcommand_matches("convert")
+                    .map(|subcmd| subcmd.is_present("DECIMALCOMMA"))
+                    .unwrap_or(false);
+
+                for file in &self.files {
+                    let cgats = read_any(file)?;
+                    let converted = match format.as_str() {
+                        "csv" => cgats.to_delimited(Delimiter::comma().decimal_comma(decimal_comma)),
+                        "tsv" => cgats.to_delimited(Delimiter::tab().decimal_comma(decimal_comma)),
+                        "ti1" | "ti2" | "ti3" => ArgyllTarget::from_cgats(ArgyllKind::from_str(&format)?, &cgats).format(),
+                        #[cfg(feature = "cxf")]
+                        "cxf" => cgats.to_cxf()?,
+                        _ => cgats.format(),
+                    };
+                    write!(self.output, "{}", converted)?;
+                }
+            }
+
+            Command::Target => self.target(&cgv)?,
+
+            Command::Randomize => {
+                let restore = self.matches.subcommand_matches("randomize")
+                    .map(|subcmd| subcmd.is_present("RESTORE"))
+                    .unwrap_or(false);
+
+                let cgats = if restore {
+                    match cgv.collection.as_slice() {
+                        [randomized] => randomized.derandomize()?,
+                        [measured, target] => measured.derandomize_with(target)?,
+                        _ => return Err(Error::Other("Restore one file with an optional randomized target!".to_string())),
+                    }
+                } else {
+                    let seed = self.subcommand_value("SEED").unwrap_or_default();
+                    let seed = seed.parse::<u64>()
+                        .map_err(|_| Error::Other(format!("Invalid seed: '{}'", seed)))?;
+                    let mut options = RandomizeOptions::new(seed).paper_white_per_row(
+                        self.matches.subcommand_matches("randomize").is_some_and(|subcmd| subcmd.is_present("PAPERWHITE"))
+                    );
+                    if let Some(row_length) = self.subcommand_number("ROWLENGTH")? {
+                        options = options.row_length(row_length);
+                    }
+                    cgv.collection.first().ok_or(Error::NoData)?.randomize(&options)?
+                };
+
+                write!(self.output, "{}", cgats)?;
+            },
         }
 
         Ok(())
     }
 }
 
+fn read_any(file: &str) -> Result<Cgats> {
+    // CGATS and Argyll files first, then CxF3 XML or a spreadsheet with a header row
+    let is_cxf = file.to_lowercase().ends_with(".cxf");
+    match is_cxf {
+        #[cfg(feature = "cxf")]
+        true => Cgats::from_cxf_file(file),
+        _ => Cgats::from_file(file).or_else(|_| Cgats::from_delimited_file(file)),
+    }
+}
+
+fn report_options(matches: &ArgMatches) -> Result<DeReportOptions> {
+    let floats = |name| matches.values_of(name).unwrap_or_default()
+        .map(|value| value.parse::<f32>()
+            .map_err(|_| Error::Other(format!("Invalid number: '{}'", value))))
+        .collect::<Result<Vec<_>>>();
+
+    let mut options = DeReportOptions::new()
+        .percentiles(&floats("PERCENTILES")?)
+        .histogram(&floats("HISTOGRAM")?);
+
+    let splits = floats("SPLITS")?;
+    if !splits.is_empty() {
+        options = options.splits(&splits);
+    }
+
+    if let Some(worst) = matches.value_of("WORST") {
+        let worst = worst.parse::<usize>()
+            .map_err(|_| Error::Other(format!("Invalid number: '{}'", worst)))?;
+        options = options.worst(worst);
+    }
+
+    Ok(options)
+}
+
+fn tolerance_profile(matches: &ArgMatches) -> Result<ToleranceProfile> {
+    let name = matches.value_of("PROFILE").unwrap_or("ISO12647-7");
+
+    let user_profiles = match matches.value_of("TOLERANCES") {
+        Some(file) => ToleranceProfile::from_file(file)?,
+        None => Vec::new(),
+    };
+
+    // A profile from the tolerance file may be named without --profile
+    if matches.value_of("PROFILE").is_none() && user_profiles.len() == 1 {
+        return Ok(user_profiles[0].clone());
+    }
+
+    user_profiles.into_iter()
+        .find(|profile| profile.name() == name)
+        .or_else(|| ToleranceProfile::builtin(name))
+        .ok_or_else(|| Error::Other(format!("Unknown tolerance profile: '{}'", name)))
+}
+
 impl<'a> Default for Config<'a> {
     fn default() -> Self {
         Config {
             command: Command::default(),
-            de_method: DEMethod::default(),
+            de_method: DeltaEFormula::default(),
+            de_columns: Vec::new(),
             de_report: false,
+            report_format: ReportFormat::default(),
+            report_options: DeReportOptions::default(),
+            profile: ToleranceProfile::iso_12647_7(),
             files: Vec::new(),
             matches: ArgMatches::default(),
             output: CgatsWriter::Stdout(BufWriter::new(stdout())),
@@ -0,0 +1,467 @@
+use super::*;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+// Keywords that Argyll knows without a KEYWORD declaration
+const STANDARD_KEYWORDS: &[&str] = &[
+    "ORIGINATOR", "DESCRIPTOR", "CREATED", "MANUFACTURER", "PROD_DATE", "SERIAL",
+    "MATERIAL", "INSTRUMENTATION", "MEASUREMENT_SOURCE", "PRINT_CONDITIONS",
+];
+
+// Keywords written by the table layout itself
+const LAYOUT_KEYWORDS: &[&str] = &["KEYWORD", "NUMBER_OF_FIELDS", "NUMBER_OF_SETS"];
+
+// Keywords derived from the spectral fields of a table
+const SPECTRAL_KEYWORDS: &[&str] = &[
+    "SPECTRAL_BANDS", "SPECTRAL_START_NM", "SPECTRAL_END_NM", "SPECTRAL_NORM",
+];
+
+// Fields that only Argyll uses, which must be declared with KEYWORD
+const ARGYLL_FIELDS: &[Field] = &[Field::SAMPLE_LOC, Field::RGB_I, Field::CMYK_I];
+
+/// The kind of Argyll CMS target file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgyllKind {
+    /// Device values of a target (.ti1)
+    Ti1,
+    /// Device values with chart layout (.ti2)
+    Ti2,
+    /// Device values with measurements (.ti3)
+    Ti3,
+}
+
+impl ArgyllKind {
+    pub fn identifier(self) -> &'static str {
+    //! The identifier on the first line of the file
+        match self {
+            ArgyllKind::Ti1 => "CTI1",
+            ArgyllKind::Ti2 => "CTI2",
+            ArgyllKind::Ti3 => "CTI3",
+        }
+    }
+
+    fn number(self) -> u8 {
+        match self {
+            ArgyllKind::Ti1 => 1,
+            ArgyllKind::Ti2 => 2,
+            ArgyllKind::Ti3 => 3,
+        }
+    }
+}
+
+impl FromStr for ArgyllKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ArgyllKind> {
+        match s.trim().trim_start_matches('.').to_uppercase().as_ref() {
+            "CTI1" | "TI1" => Ok(ArgyllKind::Ti1),
+            "CTI2" | "TI2" => Ok(ArgyllKind::Ti2),
+            "CTI3" | "TI3" => Ok(ArgyllKind::Ti3),
+            _ => Err(Error::Other(format!("Unknown Argyll file type: '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for ArgyllKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.identifier())
+    }
+}
+
+/// The spectral sampling declared by SPECTRAL_BANDS, SPECTRAL_START_NM,
+/// SPECTRAL_END_NM and SPECTRAL_NORM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectralBands {
+    pub bands: usize,
+    pub start_nm: Float,
+    pub end_nm: Float,
+    pub norm: Float,
+}
+
+/// An Argyll CMS .ti1, .ti2 or .ti3 file: the main table followed by any
+/// number of extra tables, such as the CAL calibration table of a .ti3
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgyllTarget {
+    kind: ArgyllKind,
+    tables: Vec<Cgats>,
+}
+
+impl ArgyllTarget {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ArgyllTarget> {
+    //! Read an Argyll target file
+        ArgyllTarget::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<ArgyllTarget> {
+    //! Read Argyll target text
+        let raw = DataVec::from_reader(reader)?;
+        let identifier = raw.lines.first()
+            .and_then(|line| line.raw_samples.first())
+            .ok_or(Error::UnknownVendor)?;
+        let kind = ArgyllKind::from_str(identifier)?;
+
+        let tables = raw.split_tables().into_iter()
+            .map(|table| {
+                let mut cgats = Cgats::from_data_vec(table)?;
+                cgats.vendor = Vendor::Argyll;
+                // Declarations are rebuilt when writing
+                cgats.meta.lines.retain(|line| line.raw_samples[0] != "KEYWORD");
+                Ok(cgats)
+            })
+            .collect::<Result<Vec<Cgats>>>()?;
+
+        Ok(ArgyllTarget { kind, tables })
+    }
+
+    pub fn from_cgats(kind: ArgyllKind, cgats: &Cgats) -> ArgyllTarget {
+    //! Convert a CGATS object to an Argyll target. COLOR_REP is derived from
+    //! the device and PCS fields, and every sample gets a SAMPLE_ID.
+        let mut table = Cgats::new_with_vendor(Vendor::Argyll);
+        table.meta.insert(0, kind.identifier());
+        table.set_keyword("DESCRIPTOR",
+            &format!("Argyll Calibration Target chart information {}", kind.number()));
+        table.set_keyword("ORIGINATOR", "cgats");
+
+        // Carry over the keywords of the source, skipping its identifier line
+        for line in cgats.meta.lines.iter().skip(1) {
+            let keyword = line.raw_samples[0].as_str();
+            if line.raw_samples.len() < 2 || LAYOUT_KEYWORDS.contains(&keyword) {
+                continue;
+            }
+            table.set_keyword(keyword, &line.raw_samples[1..].join(" "));
+        }
+
+        table.fields = cgats.fields.clone();
+        table.data_map = cgats.data_map.clone();
+
+        if !table.fields.contains(&Field::SAMPLE_ID) {
+            table.fields.insert(0, Field::SAMPLE_ID);
+            for (key, sample) in table.data_map.iter_mut() {
+                sample.values.insert(0, CgatsValue::from_float((key + 1) as Float));
+            }
+        }
+
+        if table.keyword("COLOR_REP").is_none() {
+            if let Some(rep) = color_rep(&table.fields) {
+                table.set_keyword("COLOR_REP", &rep);
+            }
+        }
+
+        if kind == ArgyllKind::Ti3 && table.keyword("DEVICE_CLASS").is_none() {
+            table.set_keyword("DEVICE_CLASS", "OUTPUT");
+        }
+
+        ArgyllTarget { kind, tables: vec![table] }
+    }
+
+    pub fn kind(&self) -> ArgyllKind {
+        self.kind
+    }
+
+    pub fn cgats(&self) -> &Cgats {
+    //! The main table
+        &self.tables[0]
+    }
+
+    pub fn cgats_mut(&mut self) -> &mut Cgats {
+        &mut self.tables[0]
+    }
+
+    pub fn tables(&self) -> &[Cgats] {
+    //! All tables, starting with the main table
+        &self.tables
+    }
+
+    pub fn table(&self, identifier: &str) -> Option<&Cgats> {
+    //! The first extra table with an identifier line, e.g. "CAL"
+        self.tables.iter()
+            .skip(1)
+            .find(|table| table_identifier(table).is_some_and(|id| id.eq_ignore_ascii_case(identifier)))
+    }
+
+    pub fn calibration(&self) -> Option<&Cgats> {
+    //! The calibration table that Argyll appends to a .ti3
+        self.table("CAL")
+    }
+
+    pub fn push_table(&mut self, identifier: &str, mut cgats: Cgats) {
+    //! Append an extra table, replacing the identifier line of its metadata
+        cgats.vendor = Vendor::Argyll;
+        if table_identifier(&cgats).is_some() {
+            cgats.meta.lines.remove(0);
+        }
+        cgats.meta.insert(0, identifier);
+        self.tables.push(cgats);
+    }
+
+    pub fn keyword(&self, name: &str) -> Option<String> {
+    //! The unquoted value of a keyword in the main table
+        self.cgats().keyword(name)
+    }
+
+    pub fn set_keyword(&mut self, name: &str, value: &str) {
+    //! Set a keyword in the main table, replacing any previous value
+        self.cgats_mut().set_keyword(name, value)
+    }
+
+    pub fn color_rep(&self) -> Option<(String, Option<String>)> {
+    //! The device and measurement color spaces of COLOR_REP, e.g. "CMYK_XYZ"
+        let rep = self.keyword("COLOR_REP")?;
+        let mut parts = rep.splitn(2, '_');
+        let device = parts.next()?.to_string();
+        Some((device, parts.next().map(String::from)))
+    }
+
+    pub fn device_class(&self) -> Option<String> {
+    //! DEVICE_CLASS: OUTPUT, DISPLAY or INPUT
+        self.keyword("DEVICE_CLASS")
+    }
+
+    pub fn accurate_expected_values(&self) -> bool {
+    //! Whether the expected values of a .ti1/.ti2 are accurate enough to use as a profile
+        self.keyword("ACCURATE_EXPECTED_VALUES")
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    pub fn spectral_bands(&self) -> Option<SpectralBands> {
+    //! The spectral sampling of the main table, from its keywords or its SPECTRAL fields
+        let table = self.cgats();
+        let derived = spectral_bands(&table.fields);
+        let parse = |name: &str| self.keyword(name)?.parse::<Float>().ok();
+
+        Some(SpectralBands {
+            bands: parse("SPECTRAL_BANDS").map(|b| b as usize).or(derived.map(|d| d.bands))?,
+            start_nm: parse("SPECTRAL_START_NM").or(derived.map(|d| d.start_nm))?,
+            end_nm: parse("SPECTRAL_END_NM").or(derived.map(|d| d.end_nm))?,
+            norm: parse("SPECTRAL_NORM").unwrap_or(100.0),
+        })
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<()> {
+    //! Write the target to an Argyll file
+        let mut buf = BufWriter::new(File::create(file)?);
+        write!(buf, "{}", self.format())?;
+        Ok(())
+    }
+
+    pub fn format(&self) -> String {
+    //! Format every table to Argyll text, separated by blank lines
+        self.tables.iter()
+            .enumerate()
+            .map(|(index, table)| {
+                let identifier = match index {
+                    0 => self.kind.identifier(),
+                    _ => table_identifier(table).unwrap_or("CAL"),
+                };
+                format_table(identifier, table)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for ArgyllTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format())
+    }
+}
+
+fn table_identifier(table: &Cgats) -> Option<&str> {
+    let line = table.meta.lines.first()?;
+    match line.raw_samples.len() {
+        1 => Some(&line.raw_samples[0]),
+        _ => None,
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", unquote(s))
+}
+
+fn unquote(s: &str) -> String {
+    s.trim().trim_matches('"').to_string()
+}
+
+fn color_rep(fields: &DataFormat) -> Option<String> {
+    let device = Field::device_fields(fields)?;
+    let device = match device[0] {
+        Field::CMYK_C => "CMYK".to_string(),
+        Field::RGB_R => "RGB".to_string(),
+        _ => format!("{}CLR", device.len()),
+    };
+
+    let pcs = if Field::xyz_indexes(fields).is_some() {
+        Some("XYZ")
+    } else if Field::lab_indexes(fields).is_some() {
+        Some("LAB")
+    } else {
+        None
+    };
+
+    Some(match pcs {
+        Some(pcs) => format!("{}_{}", device, pcs),
+        None => device,
+    })
+}
+
+fn spectral_bands(fields: &DataFormat) -> Option<SpectralBands> {
+    let nms = fields.iter()
+        .filter_map(|field| field.spectral_nm())
+        .collect::<Vec<u32>>();
+
+    Some(SpectralBands {
+        bands: nms.len(),
+        start_nm: *nms.iter().min()? as Float,
+        end_nm: *nms.iter().max()? as Float,
+        norm: 100.0,
+    })
+}
+
+fn format_field(field: &Field) -> String {
+    match field.spectral_nm() {
+        Some(nm) => format!("SPEC_{}", nm),
+        None => field.to_string(),
+    }
+}
+
+fn format_value(value: &CgatsValue) -> String {
+    let s = value.to_string();
+    if s.contains(char::is_whitespace) && !s.starts_with('"') {
+        quote(&s)
+    } else {
+        s
+    }
+}
+
+fn declare(s: &mut String, keyword: &str) {
+    if !STANDARD_KEYWORDS.contains(&keyword) {
+        s.push_str(&format!("KEYWORD \"{}\"\n", keyword));
+    }
+}
+
+fn format_table(identifier: &str, table: &Cgats) -> String {
+    let mut s = format!("{}\n\n", identifier);
+
+    // Keywords, skipping the identifier line
+    let skip = table_identifier(table).map_or(0, |_| 1);
+    for line in table.meta.lines.iter().skip(skip) {
+        let keyword = line.raw_samples[0].as_str();
+        if keyword.starts_with('#') {
+            s.push_str(&format!("{}\n", keyword));
+            continue;
+        }
+        if LAYOUT_KEYWORDS.contains(&keyword) || SPECTRAL_KEYWORDS.contains(&keyword) {
+            continue;
+        }
+        declare(&mut s, keyword);
+        s.push_str(&format!("{} {}\n", keyword, quote(&line.raw_samples[1..].join(" "))));
+    }
+
+    if let Some(spectral) = spectral_bands(&table.fields) {
+        let norm = table.keyword("SPECTRAL_NORM")
+            .unwrap_or_else(|| format!("{:.6}", spectral.norm));
+        let spectral_keywords = [
+            ("SPECTRAL_BANDS", spectral.bands.to_string()),
+            ("SPECTRAL_START_NM", format!("{:.6}", spectral.start_nm)),
+            ("SPECTRAL_END_NM", format!("{:.6}", spectral.end_nm)),
+            ("SPECTRAL_NORM", norm),
+        ];
+        for (keyword, value) in spectral_keywords {
+            declare(&mut s, keyword);
+            s.push_str(&format!("{} {}\n", keyword, quote(&value)));
+        }
+    }
+
+    s.push('\n');
+    for field in table.fields.iter().filter(|field| ARGYLL_FIELDS.contains(*field)) {
+        declare(&mut s, &field.to_string());
+    }
+
+    s.push_str(&format!("NUMBER_OF_FIELDS {}\n", table.fields.len()));
+    s.push_str("BEGIN_DATA_FORMAT\n");
+    s.push_str(&table.fields.iter().map(format_field).collect::<Vec<String>>().join(" "));
+    s.push_str("\nEND_DATA_FORMAT\n\n");
+
+    s.push_str(&format!("NUMBER_OF_SETS {}\n", table.sample_count()));
+    s.push_str("BEGIN_DATA\n");
+    for sample in table.data_map.values() {
+        s.push_str(&sample.values.iter().map(format_value).collect::<Vec<String>>().join(" "));
+        s.push('\n');
+    }
+    s.push_str("END_DATA\n");
+
+    s
+}
+
+#[test]
+fn read_ti3() -> Result<()> {
+    let ti3 = ArgyllTarget::from_file("test_files/argyll0.ti3")?;
+
+    assert_eq!(ti3.kind(), ArgyllKind::Ti3);
+    assert_eq!(ti3.tables().len(), 2);
+    assert_eq!(ti3.color_rep(), Some(("CMYK".to_string(), Some("XYZ".to_string()))));
+    assert_eq!(ti3.device_class(), Some("OUTPUT".to_string()));
+    assert_eq!(ti3.keyword("TOTAL_INK_LIMIT"), Some("300.0".to_string()));
+    assert!(ti3.accurate_expected_values());
+    assert_eq!(ti3.spectral_bands(), Some(SpectralBands {
+        bands: 36, start_nm: 380.0, end_nm: 730.0, norm: 100.0,
+    }));
+
+    let main = ti3.cgats();
+    assert_eq!(main.sample_count(), 6);
+    assert_eq!(main.fields[8], Field::SPECTRAL_380);
+    assert_eq!(main.get_float(1, &Field::CMYK_C), Some(100.0));
+
+    let cal = ti3.calibration().expect("CAL table");
+    assert_eq!(cal.fields[0], Field::CMYK_I);
+    assert_eq!(cal.sample_count(), 5);
+
+    // Plain CGATS reading uses the main table
+    assert_eq!(Cgats::from_file("test_files/argyll0.ti3")?.data_map, main.data_map);
+
+    Ok(())
+}
+
+#[test]
+fn roundtrip_ti3() -> Result<()> {
+    let ti3 = ArgyllTarget::from_file("test_files/argyll0.ti3")?;
+    let formatted = ti3.format();
+
+    assert!(formatted.starts_with("CTI3\n"));
+    assert!(formatted.contains("KEYWORD \"COLOR_REP\"\nCOLOR_REP \"CMYK_XYZ\"\n"));
+    assert!(formatted.contains("KEYWORD \"CMYK_I\"\nNUMBER_OF_FIELDS 5\n"));
+    assert!(formatted.contains("\nCAL\n"));
+    assert!(formatted.contains(" SPEC_380 "));
+    assert!(!formatted.contains("KEYWORD \"DESCRIPTOR\""));
+
+    let reread = ArgyllTarget::from_reader(formatted.as_bytes())?;
+    assert_eq!(reread.format(), formatted);
+    assert_eq!(reread.spectral_bands(), ti3.spectral_bands());
+
+    Ok(())
+}
+
+#[test]
+fn from_cgats() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/colorburst0.txt")?;
+    let ti1 = ArgyllTarget::from_cgats(ArgyllKind::Ti1, &cgats);
+    assert_eq!(ti1.color_rep(), None);
+    assert_eq!(ti1.cgats().fields[0], Field::SAMPLE_ID);
+    assert_eq!(ti1.cgats().get_float(0, &Field::SAMPLE_ID), Some(1.0));
+
+    let cgats = Cgats::from_file("test_files/cgats1.tsv")?;
+    let ti3 = ArgyllTarget::from_cgats(ArgyllKind::Ti3, &cgats);
+    assert_eq!(ti3.color_rep(), Some(("CMYK".to_string(), None)));
+    assert_eq!(ti3.device_class(), Some("OUTPUT".to_string()));
+    assert_eq!(ti3.spectral_bands(), None);
+
+    let reread = ArgyllTarget::from_reader(ti3.format().as_bytes())?;
+    assert_eq!(reread.kind(), ArgyllKind::Ti3);
+    assert_eq!(reread.cgats().data_map.len(), cgats.sample_count());
+
+    Ok(())
+}
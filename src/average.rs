@@ -0,0 +1,602 @@
+use super::*;
+
+use deltae::color::LabValue;
+use std::fmt;
+use std::str::FromStr;
+
+// Smallest variance used for inverse-variance weights, so identical sheets don't divide by zero
+const MIN_VARIANCE: Float = 1e-6;
+
+/// How the values of each sample are combined across sheets
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AverageMethod {
+    /// Arithmetic mean
+    #[default]
+    Mean,
+    /// Middle value, or the mean of the two middle values
+    Median,
+    /// Mean after dropping a fraction (0.0...0.5) of the lowest and of the highest values
+    TrimmedMean(Float),
+    /// Mean weighted by the inverse of each sheet's variance from the preliminary mean
+    InverseVariance,
+}
+
+impl FromStr for AverageMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<AverageMethod> {
+    //! Parse a method name. The trimmed fraction follows the name: `trimmed:0.2`
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim().to_lowercase().replace(['_', '-', ' '], "");
+
+        match (name.as_str(), parts.next()) {
+            ("mean" | "average" | "avg", None) => Ok(AverageMethod::Mean),
+            ("median", None) => Ok(AverageMethod::Median),
+            ("trimmed" | "trimmedmean", None) => Ok(AverageMethod::TrimmedMean(0.25)),
+            ("trimmed" | "trimmedmean", Some(fraction)) => match fraction.trim().parse::<Float>() {
+                Ok(fraction) if (0.0..0.5).contains(&fraction) => Ok(AverageMethod::TrimmedMean(fraction)),
+                _ => Err(Error::Other(format!("Trimmed fraction must be from 0 up to 0.5: '{}'", s))),
+            },
+            ("weighted" | "inversevariance", None) => Ok(AverageMethod::InverseVariance),
+            _ => Err(Error::Other(format!("Unknown average method: '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for AverageMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AverageMethod::Mean => write!(f, "Average"),
+            AverageMethod::Median => write!(f, "Median"),
+            AverageMethod::TrimmedMean(fraction) => write!(f, "Trimmed Mean ({})", fraction),
+            AverageMethod::InverseVariance => write!(f, "Weighted Average"),
+        }
+    }
+}
+
+/// How to average a collection of sheets
+#[derive(Debug, Clone, PartialEq)]
+pub struct AverageOptions {
+    method:    AverageMethod,
+    threshold:    Option<Float>,
+    formula:      DeltaEFormula,
+    stdev:        bool,
+    colorimetric: bool,
+}
+
+impl Default for AverageOptions {
+    fn default() -> AverageOptions {
+        AverageOptions {
+            method:       AverageMethod::Mean,
+            threshold:    None,
+            formula:      DeltaEFormula::default(),
+            stdev:        true,
+            colorimetric: false,
+        }
+    }
+}
+
+impl AverageOptions {
+    pub fn new() -> AverageOptions {
+    //! The arithmetic mean of every sheet, with STDEV_* columns
+        AverageOptions::default()
+    }
+
+    pub fn method(mut self, method: AverageMethod) -> AverageOptions {
+        self.method = method;
+        self
+    }
+
+    pub fn reject_outliers(mut self, threshold: Float, formula: DeltaEFormula) -> AverageOptions {
+    //! Leave a sheet out of a sample's average when its Delta E to the preliminary
+    //! mean of all sheets is larger than `threshold`
+        self.threshold = Some(threshold);
+        self.formula = formula;
+        self
+    }
+
+    pub fn stdev(mut self, stdev: bool) -> AverageOptions {
+    //! Write the standard deviation of LAB, XYZ and DE as STDEV_* columns
+        self.stdev = stdev;
+        self
+    }
+
+    pub fn colorimetric(mut self, colorimetric: bool) -> AverageOptions {
+    //! Average in linear spaces: spectral reflectance, XYZ (or LAB converted to XYZ),
+    //! and reflectance instead of density. LAB, LCH, xyY and density are derived
+    //! from the averages. With SPECTRAL fields, XYZ (D50, 2°), LAB and D_VIS are
+    //! computed from the averaged reflectance. Device values and other fields are
+    //! averaged as they are.
+        self.colorimetric = colorimetric;
+        self
+    }
+}
+
+/// A sheet that was left out of the average of one sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub key:       usize,
+    pub sample_id: Option<String>,
+    pub sheet:     usize,
+    pub de:        Float,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = self.sample_id.clone().unwrap_or_else(|| self.key.to_string());
+        write!(f, "Sample {}: rejected sheet {} (DE {:0.4})", id, self.sheet, self.de)
+    }
+}
+
+/// The averaged data set and the sheets rejected for each sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct RobustAverage {
+    pub cgats:      Cgats,
+    pub rejections: Vec<Rejection>,
+}
+
+impl CgatsVec {
+    pub fn average_with(&self, options: &AverageOptions) -> Result<RobustAverage> {
+    //! Average all the values in a collection of CGATS with an AverageMethod,
+    //! optionally rejecting outliers and adding STDEV_* columns.
+    //! Returns an Error if the DATA_FORMATS or NUMBER_OF_SAMPLES don't match,
+    //! or if outliers are rejected without LAB (or XYZ) in every sheet.
+        self.can_compare()?;
+
+        let sheets = &self.collection;
+        let prime = &sheets[0];
+        let float_indexes: Vec<usize> = prime.fields.iter()
+            .enumerate()
+            .filter(|(_, field)| field.is_float())
+            .map(|(index, _)| index)
+            .collect();
+
+        let value = |sheet: &Cgats, key: &usize, index: usize| -> Option<Float> {
+            sheet.data_map.get(key)?.values.get(index)?.float
+        };
+
+        // The values to average: linear values for colorimetric averages
+        let linear: Vec<Cgats> = if options.colorimetric {
+            sheets.iter().map(to_linear).collect()
+        } else {
+            Vec::new()
+        };
+        let sources = if options.colorimetric { &linear } else { sheets };
+        let norm = spectral_norm(prime);
+
+        let mean_of = |key: &usize, index: usize, retained: &[usize]| -> Option<Float> {
+            let values: Vec<Float> = retained.iter().filter_map(|s| value(&sources[*s], key, index)).collect();
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.iter().sum::<Float>() / values.len() as Float)
+            }
+        };
+
+        let all: Vec<usize> = (0..sheets.len()).collect();
+
+        // Variance of each sheet from the preliminary mean, for each field
+        let variances: Vec<Vec<Float>> = if options.method == AverageMethod::InverseVariance {
+            sources.iter().map(|sheet| {
+                (0..prime.fields.len()).map(|index| {
+                    let deviations: Vec<Float> = prime.data_map.keys()
+                        .filter_map(|key| Some(value(sheet, key, index)? - mean_of(key, index, &all)?))
+                        .collect();
+                    let variance = deviations.iter().map(|d| d * d).sum::<Float>() / deviations.len().max(1) as Float;
+                    variance.max(MIN_VARIANCE)
+                }).collect()
+            }).collect()
+        } else {
+            Vec::new()
+        };
+
+        let has_lab = sheets.iter().all(|sheet|
+            Field::lab_indexes(&sheet.fields).is_some() || Field::xyz_indexes(&sheet.fields).is_some()
+        );
+        let add_stdev = options.stdev && prime.vendor != Vendor::ColorBurst;
+        let stdev_fields: Vec<(usize, Field)> = if add_stdev {
+            prime.fields.iter()
+                .enumerate()
+                .filter_map(|(index, field)| Some((index, field.stdev_field()?)))
+                .filter(|(_, stdev)| !prime.fields.contains(stdev))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let stdev_de = add_stdev && has_lab && !prime.fields.contains(&Field::STDEV_DE);
+
+        let mut cgats = prime.derive();
+        cgats.fields.extend(stdev_fields.iter().map(|(_, field)| *field));
+        if stdev_de {
+            cgats.fields.push(Field::STDEV_DE);
+        }
+
+        let mut rejections = Vec::new();
+
+        for (key, prime_sample) in prime.data_map.iter() {
+            let retained = match options.threshold {
+                Some(threshold) => {
+                    let labs = sheets.iter()
+                        .map(|sheet| sheet.sample_lab(*key))
+                        .collect::<Option<Vec<LabValue>>>()
+                        .ok_or(Error::IncompleteData)?;
+                    let mean = mean_lab(&labs);
+                    let des: Vec<Float> = labs.iter().map(|lab| options.formula.delta_e(&mean, lab)).collect();
+
+                    let mut retained: Vec<usize> = all.iter().copied().filter(|s| des[*s] <= threshold).collect();
+                    if retained.is_empty() {
+                        let closest = all.iter().copied()
+                            .min_by(|a, b| des[*a].partial_cmp(&des[*b]).expect("Delta E is NaN!"))
+                            .expect("No sheets!");
+                        retained.push(closest);
+                    }
+
+                    for sheet in all.iter().filter(|s| !retained.contains(s)) {
+                        rejections.push(Rejection {
+                            key: *key,
+                            sample_id: prime.field_index(&Field::SAMPLE_ID)
+                                .and_then(|index| prime_sample.values.get(index))
+                                .map(|id| id.string.clone()),
+                            sheet: *sheet,
+                            de: des[*sheet],
+                        });
+                    }
+
+                    retained
+                },
+                None => all.clone(),
+            };
+
+            let mut sample = prime_sample.clone();
+            for index in &float_indexes {
+                let values: Vec<(usize, Float)> = retained.iter()
+                    .filter_map(|s| Some((*s, value(&sources[*s], key, *index)?)))
+                    .collect();
+                if values.is_empty() {
+                    continue;
+                }
+
+                let average = match options.method {
+                    AverageMethod::Mean => mean(&values),
+                    AverageMethod::Median => median(&values),
+                    AverageMethod::TrimmedMean(fraction) => trimmed_mean(&values, fraction),
+                    AverageMethod::InverseVariance => {
+                        let weights: Vec<Float> = values.iter().map(|(s, _)| 1.0 / variances[*s][*index]).collect();
+                        values.iter().zip(&weights).map(|((_, v), w)| v * w).sum::<Float>()
+                            / weights.iter().sum::<Float>()
+                    },
+                };
+                sample.values[*index] = CgatsValue::from_float(average);
+            }
+
+            if options.colorimetric {
+                from_linear(&prime.fields, norm, &mut sample);
+            }
+
+            for (index, _) in &stdev_fields {
+                let values: Vec<Float> = retained.iter().filter_map(|s| value(&sheets[*s], key, *index)).collect();
+                let mean = values.iter().sum::<Float>() / values.len().max(1) as Float;
+                let squares: Vec<Float> = values.iter().map(|v| (v - mean).powi(2)).collect();
+                sample.values.push(CgatsValue::from_float(sample_stdev(&squares)));
+            }
+
+            if stdev_de {
+                let labs: Vec<LabValue> = retained.iter().filter_map(|s| sheets[*s].sample_lab(*key)).collect();
+                let mean = mean_lab(&labs);
+                let squares: Vec<Float> = labs.iter()
+                    .map(|lab| (lab.l - mean.l).powi(2) + (lab.a - mean.a).powi(2) + (lab.b - mean.b).powi(2))
+                    .collect();
+                sample.values.push(CgatsValue::from_float(sample_stdev(&squares)));
+            }
+
+            cgats.data_map.insert(*key, sample);
+        }
+
+        if let Some(line) = cgats.meta.lines.first_mut() {
+            line.raw_samples.push(format!("{} of {}", options.method, sheets.len()));
+        }
+        cgats.meta.meta_renumber_fields(cgats.fields.len());
+
+        Ok(RobustAverage { cgats, rejections })
+    }
+}
+
+const DENSITY_FIELDS: [Field; 4] = [Field::D_RED, Field::D_GREEN, Field::D_BLUE, Field::D_VIS];
+
+fn to_linear(sheet: &Cgats) -> Cgats {
+//! Replace densities with reflectance, and LAB with XYZ (0.0...1.0) when there is no XYZ
+    let mut linear = sheet.clone();
+    let densities: Vec<usize> = DENSITY_FIELDS.iter().filter_map(|field| sheet.field_index(field)).collect();
+    let lab = Field::lab_indexes(&sheet.fields).filter(|_| Field::xyz_indexes(&sheet.fields).is_none());
+
+    for sample in linear.data_map.values_mut() {
+        for index in &densities {
+            if let Some(density) = sample.values[*index].float {
+                sample.values[*index] = CgatsValue::from_float((10.0 as Float).powf(-density));
+            }
+        }
+
+        if let Some(indexes) = lab {
+            if let Some(lab) = sample.to_lab(&indexes) {
+                for (index, value) in indexes.iter().zip(&color::lab_to_xyz(&lab)) {
+                    sample.values[*index] = CgatsValue::from_float(*value);
+                }
+            }
+        }
+    }
+
+    linear
+}
+
+fn spectral_norm(sheet: &Cgats) -> Float {
+//! The value of full reflectance in the SPECTRAL fields: SPECTRAL_NORM when it is given,
+//! otherwise 100.0 when any value is above 2.0 (percent), and 1.0 when none is
+    if let Some(norm) = sheet.keyword("SPECTRAL_NORM").and_then(|norm| norm.trim().parse().ok()) {
+        return norm;
+    }
+
+    let spectral: Vec<usize> = sheet.fields.iter()
+        .enumerate()
+        .filter(|(_, field)| field.spectral_nm().is_some())
+        .map(|(index, _)| index)
+        .collect();
+
+    let percent = sheet.data_map.values()
+        .flat_map(|sample| spectral.iter().filter_map(move |index| sample.values.get(*index)?.float))
+        .any(|value| value > 2.0);
+
+    if percent { 100.0 } else { 1.0 }
+}
+
+fn from_linear(fields: &DataFormat, norm: Float, sample: &mut Sample) {
+//! Derive density, LAB, LCH and xyY from averaged linear values. With SPECTRAL fields,
+//! XYZ (D50, 2°) is computed from the averaged reflectance, and XYZ, LAB and D_VIS follow from it.
+    let index = |field: &Field| fields.iter().position(|f| f == field);
+
+    for density in DENSITY_FIELDS.iter().filter_map(index) {
+        if let Some(reflectance) = sample.values[density].float {
+            sample.values[density] = CgatsValue::from_float(color::density(reflectance));
+        }
+    }
+
+    let spectrum: Vec<(u32, Float)> = fields.iter()
+        .zip(&sample.values)
+        .filter_map(|(field, value)| Some((field.spectral_nm()?, value.float? / norm)))
+        .collect();
+    let spectral = color::spectral_to_xyz(&spectrum);
+
+    let xyz = match (spectral, Field::xyz_indexes(fields), Field::lab_indexes(fields)) {
+        (Some(xyz), _, _) => Some(xyz),
+        (None, Some(indexes), _) => sample.to_xyz(&indexes),
+        (None, None, Some(indexes)) => sample.to_lab(&indexes).map(|lab| [lab.l, lab.a, lab.b]),
+        (None, None, None) => None,
+    };
+
+    let xyz = match xyz {
+        Some(xyz) => xyz,
+        None => return,
+    };
+
+    if spectral.is_some() {
+        let measured = [
+            (Field::XYZ_X, xyz[0] * 100.0), (Field::XYZ_Y, xyz[1] * 100.0), (Field::XYZ_Z, xyz[2] * 100.0),
+            (Field::D_VIS, color::density(xyz[1])),
+        ];
+        for (field, value) in measured.iter() {
+            if let Some(index) = index(field) {
+                sample.values[index] = CgatsValue::from_float(*value);
+            }
+        }
+    }
+
+    let lab = color::xyz_to_lab(xyz);
+    let (c, h) = (lab.a.hypot(lab.b), lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0));
+    let sum = xyz[0] + xyz[1] + xyz[2];
+
+    let derived = [
+        (Field::LAB_L, lab.l), (Field::LAB_A, lab.a), (Field::LAB_B, lab.b),
+        (Field::LAB_C, c), (Field::LAB_H, h),
+        (Field::XYY_X, if sum > 0.0 { xyz[0] / sum } else { 0.0 }),
+        (Field::XYY_Y, if sum > 0.0 { xyz[1] / sum } else { 0.0 }),
+        (Field::XYY_CAPY, xyz[1] * 100.0),
+    ];
+
+    for (field, value) in derived.iter() {
+        if let Some(index) = index(field) {
+            sample.values[index] = CgatsValue::from_float(*value);
+        }
+    }
+}
+
+fn mean(values: &[(usize, Float)]) -> Float {
+    values.iter().map(|(_, v)| v).sum::<Float>() / values.len() as Float
+}
+
+fn median(values: &[(usize, Float)]) -> Float {
+    let mut sorted: Vec<Float> = values.iter().map(|(_, v)| *v).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).expect("Value is NaN!"));
+
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+fn trimmed_mean(values: &[(usize, Float)], fraction: Float) -> Float {
+//! Falls back to the median when trimming would leave nothing
+    let trim = (values.len() as Float * fraction).floor() as usize;
+    if 2 * trim >= values.len() {
+        return median(values);
+    }
+
+    let mut sorted: Vec<(usize, Float)> = values.to_vec();
+    sorted.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("Value is NaN!"));
+    mean(&sorted[trim..sorted.len() - trim])
+}
+
+fn sample_stdev(squares: &[Float]) -> Float {
+//! The sample standard deviation from squared deviations
+    if squares.len() <= 1 {
+        0.0
+    } else {
+        (squares.iter().sum::<Float>() / (squares.len() - 1) as Float).sqrt()
+    }
+}
+
+fn mean_lab(labs: &[LabValue]) -> LabValue {
+    let n = labs.len().max(1) as Float;
+    LabValue {
+        l: labs.iter().map(|lab| lab.l).sum::<Float>() / n,
+        a: labs.iter().map(|lab| lab.a).sum::<Float>() / n,
+        b: labs.iter().map(|lab| lab.b).sum::<Float>() / n,
+    }
+}
+
+#[cfg(test)]
+fn sheets() -> Result<CgatsVec> {
+    let sheet = |values: &[[Float; 3]; 3]| Cgats::from_reader(format!(
+        "CGATS.17\nNUMBER_OF_FIELDS 4\nBEGIN_DATA_FORMAT\nSAMPLE_ID\tLAB_L\tLAB_A\tLAB_B\nEND_DATA_FORMAT\n\
+        NUMBER_OF_SETS 3\nBEGIN_DATA\n{}END_DATA\n",
+        values.iter().enumerate()
+            .map(|(id, lab)| format!("{}\t{}\t{}\t{}\n", id + 1, lab[0], lab[1], lab[2]))
+            .collect::<String>()
+    ).as_bytes());
+
+    Ok(CgatsVec::from(vec![
+        sheet(&[[50.0, 10.0, -10.0], [90.0, 0.0, 2.0], [30.0, 60.0, 40.0]])?,
+        sheet(&[[51.0, 10.5, -10.0], [90.2, 0.1, 2.0], [30.0, 61.0, 40.0]])?,
+        sheet(&[[49.0,  9.5, -10.0], [89.8, 0.0, 2.2], [30.5, 60.0, 40.5]])?,
+        sheet(&[[50.0, 10.0, -10.0], [70.0, 8.0, 9.0], [29.5, 59.0, 39.5]])?,
+    ]))
+}
+
+#[test]
+fn average_methods() -> Result<()> {
+    let cgv = sheets()?;
+
+    let mean = cgv.average_with(&AverageOptions::new())?;
+    let plain = cgv.average()?;
+    for key in plain.data_map.keys() {
+        for field in &[Field::LAB_L, Field::LAB_A, Field::LAB_B] {
+            let difference = mean.cgats.get_float(*key, field).expect("No LAB!")
+                - plain.get_float(*key, field).expect("No LAB!");
+            assert!(difference.abs() < 0.001);
+        }
+    }
+    assert!(mean.rejections.is_empty());
+    assert_eq!(mean.cgats.fields.len(), 8);
+    assert!(mean.cgats.get_float(1, &Field::STDEV_L).expect("No STDEV_L!") > 9.0);
+    assert_eq!(mean.cgats.get_float(0, &Field::STDEV_B), Some(0.0));
+
+    let median = cgv.average_with(&AverageOptions::new().method(AverageMethod::Median))?.cgats;
+    assert_eq!(median.get_float(1, &Field::LAB_L), Some(89.9));
+    assert_eq!(median.get_float(0, &Field::LAB_A), Some(10.0));
+
+    let trimmed = cgv.average_with(&AverageOptions::new().method(AverageMethod::from_str("trimmed:0.25")?))?.cgats;
+    assert_eq!(trimmed.get_float(1, &Field::LAB_L), Some(89.9));
+
+    let weighted = cgv.average_with(&AverageOptions::new().method(AverageMethod::InverseVariance))?.cgats;
+    let l = weighted.get_float(1, &Field::LAB_L).expect("No LAB_L!");
+    assert!(l > 88.0 && l < 90.0, "The noisy sheet should weigh less: {}", l);
+
+    assert!(AverageMethod::from_str("trimmed:0.5").is_err());
+    assert!(AverageMethod::from_str("mode").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn outlier_rejection() -> Result<()> {
+    let cgv = sheets()?;
+
+    let options = AverageOptions::new().reject_outliers(8.0, DeltaEFormula::De1976);
+    let average = cgv.average_with(&options)?;
+    println!("{}", average.cgats);
+
+    assert_eq!(average.rejections.len(), 1);
+    assert_eq!(average.rejections[0].key, 1);
+    assert_eq!(average.rejections[0].sheet, 3);
+    assert_eq!(average.rejections[0].sample_id.as_deref(), Some("2"));
+    assert!(average.rejections[0].de > 8.0);
+
+    let l = average.cgats.get_float(1, &Field::LAB_L).expect("No LAB_L!");
+    assert!((l - 90.0).abs() < 0.01);
+    assert!(average.cgats.get_float(1, &Field::STDEV_DE).expect("No STDEV_DE!") < 1.0);
+    assert_eq!(average.cgats.meta.lines[0].raw_samples.last().map(String::as_str), Some("Average of 4"));
+
+    let no_lab = CgatsVec::from_files(&vec!["test_files/cgats1.tsv", "test_files/cgats2.tsv"]);
+    assert!(no_lab.average_with(&options).is_err());
+    let plain = no_lab.average_with(&AverageOptions::new())?.cgats;
+    assert_eq!(plain.data_map, no_lab.average()?.data_map);
+
+    Ok(())
+}
+
+#[test]
+fn colorimetric_average() -> Result<()> {
+    let sheet = |d_vis: Float, lab: [Float; 3]| Cgats::from_reader(format!(
+        "CGATS.17\nBEGIN_DATA_FORMAT\nSAMPLE_ID\tCMYK_C\tD_VIS\tLAB_L\tLAB_A\tLAB_B\tLAB_C\nEND_DATA_FORMAT\n\
+        BEGIN_DATA\n1\t{}\t{}\t{}\t{}\t{}\t{}\nEND_DATA\n",
+        d_vis * 10.0, d_vis, lab[0], lab[1], lab[2], lab[1].hypot(lab[2])
+    ).as_bytes());
+
+    let cgv = CgatsVec::from(vec![sheet(1.0, [50.0, 60.0, 0.0])?, sheet(2.0, [50.0, -60.0, 0.0])?]);
+    let options = AverageOptions::new().stdev(false).colorimetric(true);
+    let average = cgv.average_with(&options)?.cgats;
+    println!("{}", average);
+
+    // Device values are still the arithmetic mean
+    assert_eq!(average.get_float(0, &Field::CMYK_C), Some(15.0));
+
+    // Density of the mean reflectance
+    let density = average.get_float(0, &Field::D_VIS).expect("No D_VIS!");
+    assert!((density - color::density((0.1 + 0.01) / 2.0)).abs() < 0.0001);
+
+    // LAB of the mean XYZ
+    let xyz0 = color::lab_to_xyz(&LabValue { l: 50.0, a: 60.0, b: 0.0 });
+    let xyz1 = color::lab_to_xyz(&LabValue { l: 50.0, a: -60.0, b: 0.0 });
+    let expected = color::xyz_to_lab([(xyz0[0] + xyz1[0]) / 2.0, (xyz0[1] + xyz1[1]) / 2.0, (xyz0[2] + xyz1[2]) / 2.0]);
+    let lab = average.sample_lab(0).expect("No LAB!");
+    assert!((lab.l - 50.0).abs() < 0.001);
+    assert!((lab.a - expected.a).abs() < 0.001 && lab.a.abs() > 1.0);
+    assert!((lab.b - expected.b).abs() < 0.001);
+    assert_eq!(average.get_float(0, &Field::LAB_C), Some(lab.a.hypot(lab.b)));
+
+    let plain = cgv.average()?;
+    assert_eq!(plain.get_float(0, &Field::LAB_A), Some(0.0));
+    assert_eq!(plain.get_float(0, &Field::D_VIS), Some(1.5));
+
+    Ok(())
+}
+
+#[test]
+fn spectral_average() -> Result<()> {
+    // The LAB values of the sheets don't match their spectra, so they must not be averaged
+    let sheet = |reflectance: Float, scale: Float| Cgats::from_reader(format!(
+        "CGATS.17\nBEGIN_DATA_FORMAT\nSAMPLE_ID\tD_VIS\tLAB_L\tLAB_A\tLAB_B\t{}\nEND_DATA_FORMAT\n\
+        BEGIN_DATA\n1\t0\t0\t50\t-50\t{}\nEND_DATA\n",
+        (38..=73).map(|step| format!("SPECTRAL_{}", step * 10)).collect::<Vec<_>>().join("\t"),
+        (38..=73).map(|_| (reflectance * scale).to_string()).collect::<Vec<_>>().join("\t"),
+    ).as_bytes());
+
+    let options = AverageOptions::new().stdev(false).colorimetric(true);
+    let expected = color::xyz_to_lab(color::spectral_to_xyz(&[(380, 0.5), (730, 0.5)]).expect("No XYZ!"));
+
+    for scale in [1.0, 100.0] {
+        let cgv = CgatsVec::from(vec![sheet(0.8, scale)?, sheet(0.2, scale)?]);
+        let average = cgv.average_with(&options)?.cgats;
+
+        assert_eq!(average.get_float(0, &Field::SPECTRAL_550), Some(0.5 * scale));
+
+        let lab = average.sample_lab(0).expect("No LAB!");
+        assert!((lab.l - expected.l).abs() < 0.001 && (lab.l - 76.07).abs() < 0.01);
+        assert!(lab.a.abs() < 0.1 && lab.b.abs() < 0.1);
+
+        let density = average.get_float(0, &Field::D_VIS).expect("No D_VIS!");
+        assert!((density - color::density(0.5)).abs() < 0.001);
+    }
+
+    Ok(())
+}
@@ -2,15 +2,20 @@ use super::*;
 
 use std::path::Path;
 use std::fs::File;
-use std::io::{Write, BufWriter};
+use std::io::{Write, BufRead, BufWriter};
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Clone, PartialEq)]
+use deltae::color::LabValue;
+
+#[derive(Clone)]
 pub struct Cgats {
     pub vendor: Vendor,
     pub meta: DataVec,
     pub fields: DataFormat,
     pub data_map: DataMap,
+    // The dialect detected when reading, so it is not looked up again
+    pub(crate) dialect: Option<Arc<dyn Dialect>>,
 }
 
 impl Cgats {
@@ -27,6 +32,7 @@ impl Cgats {
     pub fn new_with_vendor(vendor: Vendor) -> Cgats {
     //! Create a new empty CGATS object with a Vendor
         Cgats {
+            dialect: DialectRegistry::global().find(&vendor),
             vendor,
             ..Cgats::default()
         }
@@ -34,19 +40,55 @@ impl Cgats {
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Cgats> {
     //! Create a CGATS object from an existing CGATS file
-        let raw = DataVec::from_file(path)?;
+        Cgats::from_data_vec(DataVec::from_file(path)?)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Cgats> {
+    //! Create a CGATS object from anything that reads CGATS text
+        Cgats::from_data_vec(DataVec::from_reader(reader)?)
+    }
+
+    pub fn from_reader_with_registry<R: BufRead>(reader: R, registry: &DialectRegistry) -> Result<Cgats> {
+    //! Create a CGATS object from CGATS text, recognizing its dialect with
+    //! `registry` instead of the global registry
+        Cgats::from_data_vec_with(DataVec::from_reader(reader)?, registry)
+    }
+
+    pub(crate) fn from_data_vec(raw: DataVec) -> Result<Cgats> {
+        Cgats::from_data_vec_with(raw, &DialectRegistry::global())
+    }
 
-        let vendor = raw.get_vendor()?;
+    fn from_data_vec_with(raw: DataVec, registry: &DialectRegistry) -> Result<Cgats> {
+        let dialect = registry.detect(&raw);
+
+        // Only the first table of a file with auxiliary tables
+        let mut tables = raw.split_tables();
+        if tables.len() > 1 && !dialect.as_ref().is_some_and(|dialect| dialect.auxiliary_tables()) {
+            return Err(Error::Other(format!(
+                "Found {} tables! Read files with several measurement conditions as a ConditionSet",
+                tables.len()
+            )));
+        }
+        let raw = tables.swap_remove(0);
+
+        let vendor = raw.vendor_with(dialect.as_deref(), registry)?;
         let meta = raw.extract_meta_data();
-        let fields = raw.extract_data_format()?;
+        let fields = raw.data_format_with(dialect.as_deref())?;
         let data_map = raw.to_data_map()?;
 
-        Ok(Cgats {
+        let mut cgats = Cgats {
             vendor,
             meta,
             fields,
             data_map,
-        })
+            dialect,
+        };
+
+        if let Some(dialect) = cgats.dialect.clone() {
+            dialect.read_fixup(&mut cgats)?;
+        }
+
+        Ok(cgats)
     }
 
     pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<()> {
@@ -65,6 +107,68 @@ impl Cgats {
         )
     }
 
+    pub fn get_float(&self, key: usize, field: &Field) -> Option<Float> {
+    //! Returns the float value of a `Field` in the sample at `key`
+        let index = self.field_index(field)?;
+        self.data_map.get(&key)?.values.get(index)?.float
+    }
+
+    pub fn lab(&self) -> Option<Vec<LabValue>> {
+    //! Returns the LAB values of all samples, or None if a sample has no LAB (or XYZ)
+        self.data_map.keys().map(|key| self.sample_lab(*key)).collect()
+    }
+
+    pub fn sample_lab(&self, key: usize) -> Option<LabValue> {
+    //! Returns the LAB value of the sample at `key`, derived from XYZ if there is no LAB
+        let sample = self.data_map.get(&key)?;
+
+        match Field::lab_indexes(&self.fields) {
+            Some(indexes) => sample.to_lab(&indexes),
+            None => Some(color::xyz_to_lab(sample.to_xyz(&Field::xyz_indexes(&self.fields)?)?)),
+        }
+    }
+
+    pub fn sample_xyz(&self, key: usize) -> Option<[Float; 3]> {
+    //! Returns the D50 XYZ value (Y = 0.0...1.0) of the sample at `key`,
+    //! derived from LAB if there is no XYZ
+        let sample = self.data_map.get(&key)?;
+
+        match Field::xyz_indexes(&self.fields) {
+            Some(indexes) => sample.to_xyz(&indexes),
+            None => Some(color::lab_to_xyz(&sample.to_lab(&Field::lab_indexes(&self.fields)?)?)),
+        }
+    }
+
+    pub fn keyword(&self, name: &str) -> Option<String> {
+    //! The unquoted value of a header keyword
+        self.meta.lines.iter()
+            .find(|line| line.keyword() == name)
+            .map(|line| line.value().trim_matches('"').to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn set_keyword(&mut self, name: &str, value: &str) {
+    //! Set a header keyword to a quoted value, replacing any previous value
+        let line = DataLine::from(vec![
+            name.to_string(),
+            format!("\"{}\"", value.trim().trim_matches('"')),
+        ]);
+
+        match self.meta.lines.iter_mut().find(|line| line.keyword() == name) {
+            Some(existing) => *existing = line,
+            None => self.meta.lines.push(line),
+        }
+    }
+
+    pub fn dialect(&self) -> Option<Arc<dyn Dialect>> {
+    //! The dialect of the vendor: the one the data was read with,
+    //! or else the one registered for the vendor
+        match &self.dialect {
+            Some(dialect) if dialect.vendor() == self.vendor => Some(dialect.clone()),
+            _ => DialectRegistry::global().find(&self.vendor),
+        }
+    }
+
     pub fn is_colorburst(&self) -> bool {
         self.vendor == Vendor::ColorBurst
     }
@@ -78,8 +182,8 @@ impl Cgats {
     //! Format the DATA_FORMAT section to a string
         let mut s = String::new();
 
-        // ColorBurst does not include DATA_FORMAT information in LineFiles
-        if self.is_colorburst() {
+        // Some dialects do not include DATA_FORMAT information, e.g. ColorBurst LinFiles
+        if self.dialect().is_some_and(|dialect| !dialect.write_data_format()) {
             return s;
         }
 
@@ -101,8 +205,8 @@ impl Cgats {
         format!("{}{}{}",
             "BEGIN_DATA\n",
 
-            &self.data_map.iter()
-                .map(|(_index, sample)| format!("{}\n", sample))
+            &self.data_map.values()
+                .map(|sample| format!("{}\n", sample))
                 .collect::<String>(),
 
             "END_DATA\n"
@@ -118,10 +222,21 @@ impl Default for Cgats {
             fields: DataFormat::new(),
             data_map: DataMap::new(),
             meta: DataVec::new(),
+            dialect: None,
         }
     }
 }
 
+impl PartialEq for Cgats {
+    fn eq(&self, other: &Cgats) -> bool {
+        // The dialect follows from the vendor
+        self.vendor == other.vendor &&
+            self.meta == other.meta &&
+            self.fields == other.fields &&
+            self.data_map == other.data_map
+    }
+}
+
 impl fmt::Display for Cgats {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.format())
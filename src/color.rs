@@ -0,0 +1,327 @@
+use super::*;
+
+use deltae::color::LabValue;
+
+// CIE D50 reference white for the 2° observer, scaled to Y = 1.0.
+// Graphic arts data (ISO 13655) is relative to D50, unlike `deltae`'s D65.
+pub const D50: [Float; 3] = [0.9642, 1.0, 0.8249];
+
+const EPSILON: Float = 216.0 / 24389.0;
+const KAPPA: Float = 24389.0 / 27.0;
+
+pub fn lab_f(t: Float) -> Float {
+//! The CIE L*a*b* companding function
+    if t > EPSILON {
+        t.cbrt()
+    } else {
+        (KAPPA * t + 16.0) / 116.0
+    }
+}
+
+fn lab_f_inv(f: Float) -> Float {
+    if f.powi(3) > EPSILON {
+        f.powi(3)
+    } else {
+        (116.0 * f - 16.0) / KAPPA
+    }
+}
+
+pub fn xyz_to_lab(xyz: [Float; 3]) -> LabValue {
+//! Convert D50 XYZ (Y = 0.0...1.0) to L*a*b*
+    let fx = lab_f(xyz[0] / D50[0]);
+    let fy = lab_f(xyz[1] / D50[1]);
+    let fz = lab_f(xyz[2] / D50[2]);
+
+    LabValue {
+        l: 116.0 * fy - 16.0,
+        a: 500.0 * (fx - fy),
+        b: 200.0 * (fy - fz),
+    }
+}
+
+pub fn lab_to_xyz(lab: &LabValue) -> [Float; 3] {
+//! Convert L*a*b* to D50 XYZ (Y = 0.0...1.0)
+    let fy = (lab.l + 16.0) / 116.0;
+    let fx = fy + lab.a / 500.0;
+    let fz = fy - lab.b / 200.0;
+
+    [
+        lab_f_inv(fx) * D50[0],
+        lab_f_inv(fy) * D50[1],
+        lab_f_inv(fz) * D50[2],
+    ]
+}
+
+// The CIE D50 illuminant and the CIE 1931 2° color matching functions (x̄, ȳ, z̄),
+// in 10 nm steps from 380 to 780 nm
+const D50_2DEG: [(u32, [f64; 4]); 41] = [
+    (380, [24.49, 0.001368, 0.000039, 0.006450]),
+    (390, [29.87, 0.004243, 0.000120, 0.020050]),
+    (400, [49.31, 0.014310, 0.000396, 0.067850]),
+    (410, [56.51, 0.043510, 0.001210, 0.207400]),
+    (420, [60.03, 0.134380, 0.004000, 0.645600]),
+    (430, [57.82, 0.283900, 0.011600, 1.385600]),
+    (440, [74.82, 0.348280, 0.023000, 1.747060]),
+    (450, [87.25, 0.336200, 0.038000, 1.772110]),
+    (460, [90.61, 0.290800, 0.060000, 1.669200]),
+    (470, [91.37, 0.195360, 0.090980, 1.287640]),
+    (480, [95.11, 0.095640, 0.139020, 0.812950]),
+    (490, [91.96, 0.032010, 0.208020, 0.465180]),
+    (500, [95.72, 0.004900, 0.323000, 0.272000]),
+    (510, [96.61, 0.009300, 0.503000, 0.158200]),
+    (520, [97.13, 0.063270, 0.710000, 0.078250]),
+    (530, [102.10, 0.165500, 0.862000, 0.042160]),
+    (540, [100.75, 0.290400, 0.954000, 0.020300]),
+    (550, [102.32, 0.433450, 0.994950, 0.008750]),
+    (560, [100.00, 0.594500, 0.995000, 0.003900]),
+    (570, [97.74, 0.762100, 0.952000, 0.002100]),
+    (580, [98.92, 0.916300, 0.870000, 0.001650]),
+    (590, [93.50, 1.026300, 0.757000, 0.001100]),
+    (600, [97.69, 1.062200, 0.631000, 0.000800]),
+    (610, [99.27, 1.002600, 0.503000, 0.000340]),
+    (620, [99.04, 0.854450, 0.381000, 0.000190]),
+    (630, [95.72, 0.642400, 0.265000, 0.000050]),
+    (640, [98.86, 0.447900, 0.175000, 0.000020]),
+    (650, [95.67, 0.283500, 0.107000, 0.000000]),
+    (660, [98.19, 0.164900, 0.061000, 0.000000]),
+    (670, [103.00, 0.087400, 0.032000, 0.000000]),
+    (680, [99.13, 0.046770, 0.017000, 0.000000]),
+    (690, [87.38, 0.022700, 0.008210, 0.000000]),
+    (700, [91.60, 0.011359, 0.004102, 0.000000]),
+    (710, [92.89, 0.005790, 0.002091, 0.000000]),
+    (720, [76.85, 0.002899, 0.001047, 0.000000]),
+    (730, [86.51, 0.001440, 0.000520, 0.000000]),
+    (740, [92.58, 0.000690, 0.000249, 0.000000]),
+    (750, [78.23, 0.000332, 0.000120, 0.000000]),
+    (760, [57.69, 0.000166, 0.000060, 0.000000]),
+    (770, [82.92, 0.000083, 0.000030, 0.000000]),
+    (780, [78.27, 0.000042, 0.000015, 0.000000]),
+];
+
+// Bradford chromatic adaptation from D50 to D65
+const BRADFORD_D50_D65: [[f64; 3]; 3] = [
+    [ 0.955_576_6, -0.023_039_3, 0.063_163_6],
+    [-0.028_289_5,  1.009_941_6, 0.021_007_7],
+    [ 0.012_298_2, -0.020_483_0, 1.329_909_8],
+];
+
+// D65 XYZ to linear ITU-R BT.2020 RGB
+const XYZ_BT2020: [[f64; 3]; 3] = [
+    [ 1.716_651_2, -0.355_670_8, -0.253_366_3],
+    [-0.666_684_4,  1.616_481_2,  0.015_768_5],
+    [ 0.017_639_9, -0.042_770_6,  0.942_103_1],
+];
+
+// D65 XYZ to linear sRGB (IEC 61966-2-1)
+const XYZ_SRGB: [[f64; 3]; 3] = [
+    [ 3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266_0,  1.876_010_8,  0.041_556_0],
+    [ 0.055_643_4, -0.204_025_9,  1.057_225_2],
+];
+
+// Luminance of the reference white in cd/m² used for ΔE ITP
+pub const ITP_WHITE_LUMINANCE: f64 = 100.0;
+
+// CAM16 viewing conditions: ISO 3664 P2 (500 lx), 20% background, average surround
+const CAM16_ADAPTING_LUMINANCE: f64 = 500.0 / std::f64::consts::PI * 0.2;
+const CAM16_BACKGROUND: f64 = 20.0;
+
+const M16: [[f64; 3]; 3] = [
+    [ 0.401_288, 0.650_173, -0.051_461],
+    [-0.250_268, 1.204_414,  0.045_854],
+    [-0.002_079, 0.048_952,  0.953_127],
+];
+
+fn multiply(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        matrix[0][0] * v[0] + matrix[0][1] * v[1] + matrix[0][2] * v[2],
+        matrix[1][0] * v[0] + matrix[1][1] * v[1] + matrix[1][2] * v[2],
+        matrix[2][0] * v[0] + matrix[2][1] * v[1] + matrix[2][2] * v[2],
+    ]
+}
+
+fn pq(luminance: f64) -> f64 {
+//! The SMPTE ST 2084 (PQ) encoding of a luminance normalized to 10,000 cd/m²
+    let (m1, m2) = (2610.0 / 16384.0, 2523.0 / 4096.0 * 128.0);
+    let (c1, c2, c3) = (3424.0 / 4096.0, 2413.0 / 4096.0 * 32.0, 2392.0 / 4096.0 * 32.0);
+    let y = luminance.max(0.0).powf(m1);
+    ((c1 + c2 * y) / (1.0 + c3 * y)).powf(m2)
+}
+
+pub fn lab_to_itp(lab: &LabValue) -> [Float; 3] {
+//! Convert D50 L*a*b* to the ICtCp based ITP coordinates of ITU-R BT.2124,
+//! with the white point at ITP_WHITE_LUMINANCE
+    let xyz = lab_to_xyz(lab);
+    let xyz = multiply(&BRADFORD_D50_D65, [xyz[0] as f64, xyz[1] as f64, xyz[2] as f64]);
+    let rgb = multiply(&XYZ_BT2020, xyz);
+
+    let scale = ITP_WHITE_LUMINANCE / 10000.0;
+    let lms = [
+        (1688.0 * rgb[0] + 2146.0 * rgb[1] + 262.0 * rgb[2]) / 4096.0,
+        (683.0 * rgb[0] + 2951.0 * rgb[1] + 462.0 * rgb[2]) / 4096.0,
+        (99.0 * rgb[0] + 309.0 * rgb[1] + 3688.0 * rgb[2]) / 4096.0,
+    ];
+    let [l, m, s] = [pq(lms[0] * scale), pq(lms[1] * scale), pq(lms[2] * scale)];
+
+    let i = 0.5 * l + 0.5 * m;
+    let ct = (6610.0 * l - 13613.0 * m + 7003.0 * s) / 4096.0;
+    let cp = (17933.0 * l - 17390.0 * m - 543.0 * s) / 4096.0;
+
+    [i as Float, (0.5 * ct) as Float, cp as Float]
+}
+
+pub fn lab_to_cam16_ucs(lab: &LabValue) -> [Float; 3] {
+//! Convert D50 L*a*b* to CAM16-UCS J'a'b' under ISO 3664 P2 viewing conditions
+    let (f, c, nc) = (1.0, 0.69, 1.0);
+    let la = CAM16_ADAPTING_LUMINANCE;
+
+    let white = [D50[0] as f64 * 100.0, D50[1] as f64 * 100.0, D50[2] as f64 * 100.0];
+    let xyz = lab_to_xyz(lab);
+    let xyz = [xyz[0] as f64 * 100.0, xyz[1] as f64 * 100.0, xyz[2] as f64 * 100.0];
+
+    let k = 1.0 / (5.0 * la + 1.0);
+    let fl = 0.2 * k.powi(4) * 5.0 * la + 0.1 * (1.0 - k.powi(4)).powi(2) * (5.0 * la).cbrt();
+    let n = CAM16_BACKGROUND / white[1];
+    let z = 1.48 + n.sqrt();
+    let nbb = 0.725 * n.powf(-0.2);
+    let d = (f * (1.0 - (1.0 / 3.6) * ((-la - 42.0) / 92.0).exp())).clamp(0.0, 1.0);
+
+    let rgb_white = multiply(&M16, white);
+    let adapt = |rgb: [f64; 3]| -> [f64; 3] {
+        let mut adapted = [0.0; 3];
+        for i in 0..3 {
+            let x = (d * white[1] / rgb_white[i] + 1.0 - d) * rgb[i];
+            let p = (fl * x.abs() / 100.0).powf(0.42);
+            adapted[i] = 400.0 * x.signum() * p / (p + 27.13) + 0.1;
+        }
+        adapted
+    };
+
+    let achromatic = |rgb: [f64; 3]| (2.0 * rgb[0] + rgb[1] + 0.05 * rgb[2] - 0.305) * nbb;
+    let aw = achromatic(adapt(rgb_white));
+
+    let rgb = adapt(multiply(&M16, xyz));
+    let a = rgb[0] - 12.0 * rgb[1] / 11.0 + rgb[2] / 11.0;
+    let b = (rgb[0] + rgb[1] - 2.0 * rgb[2]) / 9.0;
+    let h = b.atan2(a);
+
+    let j = 100.0 * (achromatic(rgb) / aw).max(0.0).powf(c * z);
+    let et = 0.25 * ((h + 2.0).cos() + 3.8);
+    let t = (50000.0 / 13.0 * nc * nbb * et * a.hypot(b)) / (rgb[0] + rgb[1] + 21.0 / 20.0 * rgb[2]);
+    let chroma = t.max(0.0).powf(0.9) * (j / 100.0).sqrt() * (1.64 - 0.29f64.powf(n)).powf(0.73);
+    let colorfulness = chroma * fl.powf(0.25);
+
+    let j_ucs = 1.7 * j / (1.0 + 0.007 * j);
+    let m_ucs = (1.0 + 0.0228 * colorfulness).ln() / 0.0228;
+
+    [j_ucs as Float, (m_ucs * h.cos()) as Float, (m_ucs * h.sin()) as Float]
+}
+
+pub fn lab_to_srgb(lab: &LabValue) -> [u8; 3] {
+//! Convert D50 L*a*b* to 8-bit sRGB for display, clipping out of gamut colors
+    let xyz = lab_to_xyz(lab);
+    let xyz = multiply(&BRADFORD_D50_D65, [xyz[0] as f64, xyz[1] as f64, xyz[2] as f64]);
+    let rgb = multiply(&XYZ_SRGB, xyz);
+
+    let encode = |linear: f64| {
+        let linear = linear.clamp(0.0, 1.0);
+        let encoded = if linear <= 0.003_130_8 {
+            12.92 * linear
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (encoded * 255.0).round() as u8
+    };
+
+    [encode(rgb[0]), encode(rgb[1]), encode(rgb[2])]
+}
+
+pub fn density(reflectance: Float) -> Float {
+//! Optical density of a reflectance factor (0.0...1.0)
+    -reflectance.max(1e-6).log10()
+}
+
+pub fn spectral_to_xyz(spectrum: &[(u32, Float)]) -> Option<[Float; 3]> {
+//! Convert a reflectance spectrum of (nm, 0.0...1.0) pairs to D50 XYZ (Y = 0.0...1.0)
+//! for the 2° observer. The spectrum is interpolated linearly to 10 nm steps,
+//! and its first and last values are held outside of the measured range.
+//! Returns None for an empty spectrum.
+    let mut spectrum = spectrum.to_vec();
+    spectrum.sort_by_key(|(nm, _)| *nm);
+    let (first, last) = (spectrum.first()?, spectrum.last()?);
+
+    let reflectance = |nm: u32| -> f64 {
+        if nm <= first.0 {
+            return first.1 as f64;
+        }
+        if nm >= last.0 {
+            return last.1 as f64;
+        }
+        let upper = spectrum.iter().position(|(n, _)| *n >= nm).unwrap_or(spectrum.len() - 1);
+        let ((n0, r0), (n1, r1)) = (spectrum[upper - 1], spectrum[upper]);
+        let t = (nm - n0) as f64 / (n1 - n0) as f64;
+        r0 as f64 + (r1 - r0) as f64 * t
+    };
+
+    let mut xyz = [0.0_f64; 3];
+    let mut white = 0.0;
+    for (nm, [s, x, y, z]) in D50_2DEG.iter() {
+        let r = reflectance(*nm);
+        xyz[0] += s * x * r;
+        xyz[1] += s * y * r;
+        xyz[2] += s * z * r;
+        white += s * y;
+    }
+
+    Some([(xyz[0] / white) as Float, (xyz[1] / white) as Float, (xyz[2] / white) as Float])
+}
+
+#[test]
+fn lab_xyz_roundtrip() {
+    let lab = LabValue { l: 57.644, a: 43.118, b: -0.587 };
+    let xyz = lab_to_xyz(&lab);
+    let back = xyz_to_lab(xyz);
+
+    assert!((xyz[1] - 0.25588).abs() < 0.001);
+    assert!((back.l - lab.l).abs() < 0.001);
+    assert!((back.a - lab.a).abs() < 0.001);
+    assert!((back.b - lab.b).abs() < 0.001);
+    assert_eq!(xyz_to_lab(D50).l.round(), 100.0);
+}
+
+#[test]
+fn spectral_xyz() {
+    let flat = (38..=73).map(|step| (step * 10, 0.5)).collect::<Vec<_>>();
+    let xyz = spectral_to_xyz(&flat).expect("No XYZ!");
+    for (value, white) in xyz.iter().zip(&D50) {
+        assert!((value / 0.5 - white).abs() < 0.001);
+    }
+    assert!(spectral_to_xyz(&[]).is_none());
+}
+
+#[test]
+fn itp_and_cam16() {
+    let white = LabValue { l: 100.0, a: 0.0, b: 0.0 };
+    let itp = lab_to_itp(&white);
+    assert!(itp[1].abs() < 0.001 && itp[2].abs() < 0.001);
+    assert!((itp[0] - 0.508).abs() < 0.01);
+
+    let ucs = lab_to_cam16_ucs(&white);
+    assert!((ucs[0] - 1.7 * 100.0 / 1.7).abs() < 0.5);
+    assert!(ucs[1].hypot(ucs[2]) < 2.0);
+
+    let black = lab_to_cam16_ucs(&LabValue { l: 0.0, a: 0.0, b: 0.0 });
+    assert!(black[0].abs() < 0.001);
+}
+
+#[test]
+fn srgb() {
+    assert_eq!(lab_to_srgb(&LabValue { l: 100.0, a: 0.0, b: 0.0 }), [255, 255, 255]);
+    assert_eq!(lab_to_srgb(&LabValue { l: 0.0, a: 0.0, b: 0.0 }), [0, 0, 0]);
+
+    let gray = lab_to_srgb(&LabValue { l: 50.0, a: 0.0, b: 0.0 });
+    assert!(gray.iter().all(|c| (*c as i32 - 119).abs() <= 1));
+
+    let red = lab_to_srgb(&LabValue { l: 54.0, a: 81.0, b: 70.0 });
+    assert!(red[0] > 240 && red[1] < 30 && red[2] < 30);
+}
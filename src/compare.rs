@@ -1,28 +1,38 @@
 use super::*;
 
-use deltae::{DeltaE, DEMethod};
+use deltae::DEMethod;
 
 pub use std::str::FromStr;
 use std::path::Path;
 use std::convert::TryFrom;
 
+// Keyword for the CIEDE2000 parametric factors (kL:kC:kH) of a DE_2000 column
+const DE2000_FACTORS: &str = "DE2000_FACTORS";
+
 impl Cgats {
     pub fn average(collection: Vec<Cgats>) -> Result<Cgats> {
     //! Average all the values in a collection of CGATS.
     //! Returns an Error if the DATA_FORMATS or NUMBER_OF_SAMPLES don't match.
-        CgatsVec { collection }.average()
+        CgatsVec::from(collection).average()
     }
 
     pub fn concatenate(collection: Vec<Cgats>) -> Result<Cgats> {
     //! Concatente multiple CGATS file from a collection.
     //! Returns an Error if the DATA_FORMATS don't match.
-        CgatsVec { collection }.concatenate()
+        CgatsVec::from(collection).concatenate()
     }
 
     pub fn deltae(self, other: Cgats, method: DEMethod) -> Result<Cgats> {
     //! Calculate DELTA E of all samples between exactly 2 CGATS objects.
     //! Returns an Error if both CGATS do not contain LAB, or if the NUMBER_OF_SAMPLES differ.
-        CgatsVec { collection: vec![self, other] }.deltae(method)
+        CgatsVec::from(vec![self, other]).deltae(method)
+    }
+
+    pub fn delta(self, other: Cgats, fields: &[Field]) -> Result<Cgats> {
+    //! Calculate the given color differences of all samples between exactly 2 CGATS objects.
+    //! Returns an Error if both CGATS do not contain LAB, if the NUMBER_OF_SAMPLES differ,
+    //! or if a field is not a color difference.
+        CgatsVec::from(vec![self, other]).delta(fields)
     }
 
     // TODO: Make this return an Option
@@ -38,6 +48,27 @@ impl Cgats {
         Err(Error::IncompleteData)
     }
 
+    pub fn de_formula(&self) -> Result<(usize, DeltaEFormula)> {
+    //! Returns the index and the DeltaEFormula of the first DE_* field in the DATA_FORMAT,
+    //! with CIEDE2000 parametric factors read from the DE2000_FACTORS keyword.
+    //! Returns an error if no DE is found
+        let (index, formula) = self.fields.iter()
+            .enumerate()
+            .find_map(|(index, field)| field.to_de_formula().map(|formula| (index, formula)))
+            .ok_or(Error::IncompleteData)?;
+
+        let factors = self.meta.lines.iter()
+            .find(|line| line.raw_samples.first().map(String::as_str) == Some(DE2000_FACTORS))
+            .and_then(|line| line.raw_samples.get(1))
+            .map(|factors| DeltaEFormula::from_str(&format!("2000:{}", factors.trim_matches('"'))))
+            .transpose()?;
+
+        match (formula, factors) {
+            (DeltaEFormula::De2000 { .. }, Some(factors)) => Ok((index, factors)),
+            _ => Ok((index, formula)),
+        }
+    }
+
     pub fn field_index(&self, field: &Field) -> Option<usize> {
     //! Returns the index of a given `Field`, returns `None` if not present.
         self.fields.iter().position(|f| f == field)
@@ -48,17 +79,18 @@ impl Cgats {
             vendor: Vendor::Cgats,
             meta: DataVec::new(),
             fields,
-            data_map: DataMap::new(),
+            ..Cgats::default()
         }
     }
 
-    fn derive(&self) -> Cgats {
+    pub(crate) fn derive(&self) -> Cgats {
     //! Returns a new, empty CGATS object based on an existing CGATS object
         Cgats {
             vendor: self.vendor.clone(),
             meta: self.meta.clone(),
             fields: self.fields.clone(),
             data_map: DataMap::new(),
+            dialect: self.dialect.clone(),
         }
     }
 
@@ -120,25 +152,42 @@ fn reindex() -> Result<()> {
 #[derive(Debug, Clone, PartialEq)]
 pub struct CgatsVec {
     pub collection: Vec<Cgats>,
+    allow_mismatch: bool,
 }
 
 impl From<Vec<Cgats>> for CgatsVec {
     fn from(collection: Vec<Cgats>) -> CgatsVec {
-        CgatsVec { collection }
+        CgatsVec { collection, allow_mismatch: false }
     }
 }
 
 impl CgatsVec {
+    #[allow(clippy::ptr_arg)]
     pub fn from_files<P: AsRef<Path>>(files: &Vec<P>) -> CgatsVec {
     //! Convert a collection of files into a CgatsVec
-        CgatsVec {
-            collection: files.iter()
+        CgatsVec::from(
+            files.iter()
                 .filter_map(|f| Cgats::from_file(f).ok())
-                .collect()
+                .collect::<Vec<Cgats>>()
+        )
+    }
+
+    pub fn allow_mismatch(mut self, allow: bool) -> CgatsVec {
+    //! Average and compare data sets measured under different conditions,
+    //! illuminants or observers instead of returning `Error::ConditionMismatch`
+        self.allow_mismatch = allow;
+        self
+    }
+
+    fn check_mismatch(&self) -> Result<()> {
+        if self.allow_mismatch {
+            Ok(())
+        } else {
+            self.check_conditions()
         }
     }
 
-    fn can_compare(&self) -> Result<()> {
+    pub(crate) fn can_compare(&self) -> Result<()> {
     //!  Test that all samples have the same number of fields
         if self.collection.is_empty() {
             return Err(Error::NoData);
@@ -155,7 +204,7 @@ impl CgatsVec {
             return Err(Error::CannotCompare);
         }
 
-        Ok(())
+        self.check_mismatch()
     }
 
     fn can_delta(&self) -> bool {
@@ -227,19 +276,53 @@ impl CgatsVec {
     pub fn deltae(&self, method: DEMethod) -> Result<Cgats> {
     //! Calculate DELTA E of all samples between exactly 2 CGATS objects.
     //! Returns an Error if both CGATS do not contain LAB, or if the NUMBER_OF_SAMPLES differ.
+        self.delta(&[Field::from_de_method(method)])
+    }
+
+    pub fn deltae_formula(&self, formula: DeltaEFormula) -> Result<Cgats> {
+    //! Calculate DELTA E with any DeltaEFormula of all samples between exactly 2 CGATS objects.
+    //! Returns an Error if both CGATS do not contain LAB, or if the NUMBER_OF_SAMPLES differ.
+        self.delta_with(&[formula.field()], formula)
+    }
+
+    pub fn delta(&self, fields: &[Field]) -> Result<Cgats> {
+    //! Calculate color differences (DE_*, DELTA_* and DE2000_* fields) of all samples
+    //! between exactly 2 CGATS objects. Signed differences are the second minus the first.
+    //! Returns an Error if both CGATS do not contain LAB, if the NUMBER_OF_SAMPLES differ,
+    //! or if a field is not a color difference.
+        self.delta_with(fields, DeltaEFormula::default())
+    }
+
+    pub fn delta_with(&self, fields: &[Field], formula: DeltaEFormula) -> Result<Cgats> {
+    //! Calculate color differences like `delta`, with the CIEDE2000 parametric factors
+    //! of a formula applied to the DE_2000 and DE2000_* fields.
+    //! Parametric factors are recorded in the DE2000_FACTORS keyword.
         if !self.can_delta() {
             return Err(Error::CannotCompare);
         }
+        self.check_mismatch()?;
 
-        let mut cgats = Cgats::new_with_fields(vec![
-            Field::SAMPLE_ID, Field::from_de_method(method)
-        ]);
+        if let Some(field) = fields.iter().find(|field| !field.is_difference()) {
+            return Err(Error::Other(format!("{} is not a color difference!", field)));
+        }
+
+        let mut cgats = Cgats::new_with_fields(
+            std::iter::once(Field::SAMPLE_ID).chain(fields.iter().copied()).collect()
+        );
 
         cgats.vendor = Vendor::Cgats;
         cgats.meta = DataVec::from(vec![
             DataLine::from(vec!["CGATS.17".to_string()]),
         ]);
 
+        let factors = formula.factors();
+        if formula.is_parametric() {
+            cgats.meta.lines.push(DataLine::from(vec![
+                DE2000_FACTORS.to_string(),
+                format!("\"{}:{}:{}\"", factors[0], factors[1], factors[2]),
+            ]));
+        }
+
         let (sample0, sample1) = (&self.collection[0], &self.collection[1]);
 
         // We can unwrap these because `self.can_delta()` already vetted that both samples contain LAB
@@ -252,14 +335,15 @@ impl CgatsVec {
             let lab1 = sample1.data_map
                 .get(index).expect("Key doesn't exist in map!")
                 .to_lab(&lab1_indexes).expect("Cannot find LAB in fields!");
-            let de = DeltaE::new(&lab0, &lab1, method);
-            cgats.data_map.insert(*index,
-                Sample {
-                    values: vec![
-                        CgatsValue::from_str(&index.to_string())?,
-                        CgatsValue::from_float(de.value as Float),
-                    ]
-                });
+
+            let mut values = vec![CgatsValue::from_str(&index.to_string())?];
+            for field in fields {
+                let value = difference::lab_difference(*field, &lab0, &lab1, factors)
+                    .expect("Field is a color difference!");
+                values.push(CgatsValue::from_float(value));
+            }
+
+            cgats.data_map.insert(*index, Sample { values });
         }
 
         cgats.meta.lines.push(
@@ -344,3 +428,50 @@ fn deltae() -> Result<()> {
     std::fs::remove_file(temp)?;
     Ok(())
 }
+
+#[test]
+fn delta_components() -> Result<()> {
+    let cgv = CgatsVec::from_files(&vec![
+        "test_files/colorburst2.lin", "test_files/colorburst3.lin"
+    ]);
+    let fields = [Field::DE_2000, Field::DELTA_L, Field::DELTA_C, Field::DELTA_H, Field::DE2000_L];
+    let delta = cgv.delta(&fields)?;
+    let de = cgv.deltae(deltae::DEMethod::DE2000)?;
+
+    assert_eq!(delta.fields[0], Field::SAMPLE_ID);
+    assert_eq!(&delta.fields[1..], &fields);
+
+    for (key, sample) in delta.data_map.iter() {
+        assert_eq!(sample.values[1], de.data_map[key].values[1]);
+    }
+
+    let report = DeReport::new(&delta)?;
+    println!("{}", report);
+
+    assert!(cgv.delta(&[Field::LAB_L]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn delta_formula() -> Result<()> {
+    let cgv = CgatsVec::from_files(&vec![
+        "test_files/colorburst2.lin", "test_files/colorburst3.lin"
+    ]);
+
+    let textiles = DeltaEFormula::De2000 { kl: 2.0, kc: 1.0, kh: 1.0 };
+    let de = cgv.deltae_formula(textiles)?;
+    assert_eq!(de.fields, vec![Field::SAMPLE_ID, Field::DE_2000]);
+
+    let temp = test::mktemp()?;
+    de.write_to_file(&temp)?;
+    let reconstructed = Cgats::from_file(&temp)?;
+    assert_eq!(reconstructed.de_formula()?, (1, textiles));
+    std::fs::remove_file(temp)?;
+
+    let itp = cgv.deltae_formula(DeltaEFormula::Itp)?;
+    assert_eq!(itp.de_formula()?, (1, DeltaEFormula::Itp));
+    assert_eq!(DeReport::new(&itp)?.to_string().lines().nth(1), Some("DE Formula: DEITP"));
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use super::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// ISO 13655 measurement condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeasurementCondition {
+    /// Illuminant A, undefined UV content
+    M0,
+    /// D50 including UV, for papers with optical brighteners
+    M1,
+    /// UV excluded
+    M2,
+    /// Polarized
+    M3,
+}
+
+impl MeasurementCondition {
+    pub fn from_filter(filter: &str) -> Option<MeasurementCondition> {
+    //! The condition implied by an instrument filter name, e.g. "UVcut" is M2
+        match filter.trim().trim_matches('"').to_lowercase().as_str() {
+            "m0" | "incandescent" => Some(MeasurementCondition::M0),
+            "m1" | "d50" => Some(MeasurementCondition::M1),
+            "m2" | "uvcut" | "uv-cut" | "uvexcluded" => Some(MeasurementCondition::M2),
+            "m3" | "pol" | "polarized" => Some(MeasurementCondition::M3),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for MeasurementCondition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<MeasurementCondition> {
+        // "M1", "m1" and the CxF names like "M1_Part1" or "M0_Incandescent"
+        let lower = s.trim().trim_matches('"').to_lowercase();
+        let prefix = lower.get(..2).filter(|_| !lower[2..].starts_with(|c: char| c.is_ascii_digit()));
+
+        prefix.and_then(MeasurementCondition::from_filter)
+            .or_else(|| MeasurementCondition::from_filter(&lower))
+            .ok_or_else(|| Error::Other(format!("Unknown measurement condition: '{}'", s)))
+    }
+}
+
+impl fmt::Display for MeasurementCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A difference in how two data sets were measured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionMismatch {
+    Condition(MeasurementCondition, MeasurementCondition),
+    Illuminant(String, String),
+    Observer(String, String),
+}
+
+impl fmt::Display for ConditionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConditionMismatch::Condition(a, b) => write!(f, "Measurement conditions differ: {} and {}", a, b),
+            ConditionMismatch::Illuminant(a, b) => write!(f, "Illuminants differ: {} and {}", a, b),
+            ConditionMismatch::Observer(a, b) => write!(f, "Observers differ: {}° and {}°", a, b),
+        }
+    }
+}
+
+impl Cgats {
+    pub fn measurement_condition(&self) -> Option<MeasurementCondition> {
+    //! The measurement condition from MEASUREMENT_CONDITION, the vendor keywords
+    //! (e.g. `Measurement Condition=M1` in BARBIERI_INFO_*) or the FILTER
+        let meta = self.metadata();
+        meta.measurement_condition.as_deref()
+            .and_then(|condition| condition.parse().ok())
+            .or_else(|| MeasurementCondition::from_filter(meta.filter.as_deref()?))
+    }
+
+    pub fn set_measurement_condition(&mut self, condition: MeasurementCondition) {
+    //! Write the MEASUREMENT_CONDITION keyword
+        self.set_keyword("MEASUREMENT_CONDITION", &condition.to_string());
+    }
+
+    pub fn condition_mismatch(&self, other: &Cgats) -> Option<ConditionMismatch> {
+    //! How `other` was measured differently. Conditions, illuminants and
+    //! observers that are unknown in either data set are not compared.
+        let (meta, other_meta) = (self.metadata(), other.metadata());
+
+        if let (Some(a), Some(b)) = (self.measurement_condition(), other.measurement_condition()) {
+            if a != b {
+                return Some(ConditionMismatch::Condition(a, b));
+            }
+        }
+
+        if let (Some(a), Some(b)) = (meta.illuminant, other_meta.illuminant) {
+            if a.trim().to_uppercase() != b.trim().to_uppercase() {
+                return Some(ConditionMismatch::Illuminant(a, b));
+            }
+        }
+
+        if let (Some(a), Some(b)) = (meta.observer, other_meta.observer) {
+            if a != b {
+                return Some(ConditionMismatch::Observer(a, b));
+            }
+        }
+
+        None
+    }
+}
+
+impl CgatsVec {
+    pub fn check_conditions(&self) -> Result<()> {
+    //! Test that all data sets were measured under the same condition,
+    //! illuminant and observer as the first one
+        match self.collection.split_first() {
+            Some((prime, others)) => others.iter()
+                .find_map(|other| prime.condition_mismatch(other))
+                .map_or(Ok(()), |mismatch| Err(Error::ConditionMismatch(mismatch))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn parse_condition() -> Result<()> {
+    use MeasurementCondition::*;
+
+    assert_eq!("M1".parse::<MeasurementCondition>()?, M1);
+    assert_eq!("\"m2\"".parse::<MeasurementCondition>()?, M2);
+    assert_eq!("M0_Incandescent".parse::<MeasurementCondition>()?, M0);
+    assert_eq!("M3_Polarized".parse::<MeasurementCondition>()?, M3);
+    assert_eq!("UVcut".parse::<MeasurementCondition>()?, M2);
+    assert!("M12".parse::<MeasurementCondition>().is_err());
+
+    assert_eq!(Cgats::from_file("test_files/cgats0.txt")?.measurement_condition(), Some(M1));
+    assert_eq!(Cgats::from_file("test_files/konica0.txt")?.measurement_condition(), Some(M1));
+    assert_eq!(Cgats::from_file("test_files/xrite0.txt")?.measurement_condition(), Some(M2));
+    assert_eq!(Cgats::from_file("test_files/colorburst0.txt")?.measurement_condition(), None);
+
+    let filter = "CGATS.17\nFILTER\t\"UVcut\"\nBEGIN_DATA_FORMAT\nSAMPLE_ID\tLAB_L\nEND_DATA_FORMAT\nBEGIN_DATA\n1\t50\nEND_DATA\n";
+    assert_eq!(Cgats::from_reader(filter.as_bytes())?.measurement_condition(), Some(M2));
+
+    Ok(())
+}
+
+#[test]
+fn condition_mismatch() -> Result<()> {
+    let reference = Cgats::from_file("test_files/colorburst2.lin")?;
+    let mut m0 = reference.clone();
+    let mut m1 = reference.clone();
+    m0.set_measurement_condition(MeasurementCondition::M0);
+    m1.set_measurement_condition(MeasurementCondition::M1);
+
+    // Unknown conditions are not compared
+    assert!(CgatsVec::from(vec![reference.clone(), m0.clone()]).delta(&[Field::DE_2000]).is_ok());
+
+    let mismatch = || Error::ConditionMismatch(
+        ConditionMismatch::Condition(MeasurementCondition::M0, MeasurementCondition::M1)
+    );
+    let cgv = CgatsVec::from(vec![m0.clone(), m1.clone()]);
+    assert_eq!(cgv.delta(&[Field::DE_2000]), Err(mismatch()));
+    assert_eq!(cgv.average(), Err(mismatch()));
+    assert_eq!(cgv.average_with(&AverageOptions::new()).map(|_| ()), Err(mismatch()));
+
+    let cgv = cgv.allow_mismatch(true);
+    assert_eq!(cgv.check_conditions(), Err(mismatch()));
+    assert!(cgv.delta(&[Field::DE_2000]).is_ok());
+    assert!(cgv.average().is_ok());
+
+    let mut d65 = m1.clone();
+    d65.set_keyword("ILLUMINATION_NAME", "D65");
+    m1.set_keyword("ILLUMINATION_NAME", "d50");
+    assert_eq!(
+        CgatsVec::from(vec![m1, d65]).check_conditions(),
+        Err(Error::ConditionMismatch(ConditionMismatch::Illuminant("d50".into(), "D65".into())))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,337 @@
+use super::*;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The same patch set measured under several measurement conditions, one
+/// table per condition. Files may hold the conditions as separate tables or
+/// as one table with suffixed columns, e.g. `LAB_L_M0` and `LAB_L_M1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionSet {
+    tables: Vec<(MeasurementCondition, Cgats)>,
+}
+
+impl ConditionSet {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ConditionSet> {
+    //! Read a file with one or more measurement conditions
+        ConditionSet::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<ConditionSet> {
+    //! Read CGATS text with one or more measurement conditions
+        let raw = DataVec::from_reader(reader)?;
+        let raw_tables = raw.split_tables();
+
+        let mut tables = Vec::new();
+        for table in raw_tables.iter().map(|table| with_file_header(table, &raw_tables[0])) {
+            match split_columns(&table) {
+                Some(columns) => for (condition, raw) in columns {
+                    let mut cgats = Cgats::from_data_vec(raw)?;
+                    cgats.set_measurement_condition(condition);
+                    tables.push(cgats);
+                },
+                None => tables.push(Cgats::from_data_vec(table)?),
+            }
+        }
+
+        ConditionSet::merge(tables)
+    }
+
+    pub fn merge(tables: Vec<Cgats>) -> Result<ConditionSet> {
+    //! Combine single-condition tables of the same patch set.
+    //! Returns an Error if a table has no measurement condition, if two tables
+    //! have the same condition, or if the NUMBER_OF_SETS differ.
+        let mut set = ConditionSet { tables: Vec::with_capacity(tables.len()) };
+
+        for table in tables {
+            let condition = table.measurement_condition()
+                .ok_or_else(|| Error::Other("Table has no measurement condition!".to_string()))?;
+            set.insert(condition, table)?;
+        }
+
+        if set.tables.is_empty() {
+            return Err(Error::NoData);
+        }
+
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, condition: MeasurementCondition, mut cgats: Cgats) -> Result<()> {
+    //! Add the table of a condition
+        if self.condition(condition).is_some() {
+            return Err(Error::Other(format!("Measurement condition {} appears twice!", condition)));
+        }
+
+        if self.tables.first().is_some_and(|(_, first)| first.sample_count() != cgats.sample_count()) {
+            return Err(Error::CannotCompare);
+        }
+
+        cgats.set_measurement_condition(condition);
+        self.tables.push((condition, cgats));
+        Ok(())
+    }
+
+    pub fn conditions(&self) -> Vec<MeasurementCondition> {
+        self.tables.iter().map(|(condition, _)| *condition).collect()
+    }
+
+    pub fn condition(&self, condition: MeasurementCondition) -> Option<&Cgats> {
+    //! The table measured under a condition
+        self.tables.iter()
+            .find(|(c, _)| *c == condition)
+            .map(|(_, cgats)| cgats)
+    }
+
+    pub fn condition_mut(&mut self, condition: MeasurementCondition) -> Option<&mut Cgats> {
+        self.tables.iter_mut()
+            .find(|(c, _)| *c == condition)
+            .map(|(_, cgats)| cgats)
+    }
+
+    pub fn split(self) -> Vec<Cgats> {
+    //! Single-condition tables, each tagged with its MEASUREMENT_CONDITION
+        self.tables.into_iter().map(|(_, cgats)| cgats).collect()
+    }
+
+    pub fn format(&self) -> String {
+    //! Format the conditions as consecutive tables
+        self.tables.iter()
+            .map(|(_, cgats)| cgats.format())
+            .collect()
+    }
+
+    pub fn format_columns(&self) -> String {
+    //! Format the conditions as one table. Fields that are the same under every
+    //! condition (e.g. SAMPLE_ID and device values) are written once and
+    //! the measurements are suffixed with the condition, e.g. `LAB_L_M1`.
+        let prime = &self.tables[0].1;
+
+        let shared: Vec<Field> = prime.fields.iter()
+            .copied()
+            .filter(|field| self.tables.iter().all(|(_, table)| {
+                prime.field_index(field)
+                    .zip(table.field_index(field))
+                    .is_some_and(|(a, b)| prime.data_map.iter().all(|(key, sample)|
+                        table.data_map.get(key).map(|other| &other.values[b]) == Some(&sample.values[a])
+                    ))
+            }))
+            .collect();
+
+        // (table, field index, column name)
+        let mut columns: Vec<(usize, usize, String)> = shared.iter()
+            .map(|field| (0, prime.field_index(field).expect("Shared field"), field.to_string()))
+            .collect();
+        for (table, (condition, cgats)) in self.tables.iter().enumerate() {
+            for (index, field) in cgats.fields.iter().enumerate() {
+                if !shared.contains(field) {
+                    columns.push((table, index, format!("{}_{}", field, condition)));
+                }
+            }
+        }
+
+        let mut meta = prime.meta.clone();
+        meta.lines.retain(|line| line.keyword() != "MEASUREMENT_CONDITION");
+        meta.meta_renumber_fields(columns.len());
+
+        let mut s = format!("{}BEGIN_DATA_FORMAT\n", meta);
+        s.push_str(&columns.iter().map(|(_, _, name)| name.as_str()).collect::<Vec<&str>>().join("\t"));
+        s.push_str("\nEND_DATA_FORMAT\nBEGIN_DATA\n");
+
+        for key in prime.data_map.keys() {
+            let row = columns.iter()
+                .map(|(table, index, _)| self.tables[*table].1.data_map.get(key)
+                    .and_then(|sample| sample.values.get(*index))
+                    .map(|value| value.to_string())
+                    .unwrap_or_default())
+                .collect::<Vec<String>>()
+                .join("\t");
+            s.push_str(&row);
+            s.push('\n');
+        }
+
+        s.push_str("END_DATA\n");
+        s
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, file: P) -> Result<()> {
+    //! Write the conditions as consecutive tables
+        let mut buf = BufWriter::new(File::create(file)?);
+        write!(buf, "{}", self.format())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConditionSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format())
+    }
+}
+
+fn condition_tag(s: &str) -> Option<MeasurementCondition> {
+    // Only the condition names themselves: a filter name like D50 could be part of a field
+    s.parse::<MeasurementCondition>().ok()
+        .filter(|condition| condition.to_string().eq_ignore_ascii_case(s))
+}
+
+fn with_file_header(table: &DataVec, first: &DataVec) -> DataVec {
+// Later tables (e.g. the M1 and M2 blocks of an i1Profiler export) may only
+// repeat the keywords that differ, so they get those of the first table
+// that they leave out
+    let header: Vec<&DataLine> = first.lines.iter()
+        .take_while(|line| !matches!(line.keyword(), "BEGIN_DATA_FORMAT" | "BEGIN_DATA"))
+        .filter(|line| !matches!(line.keyword(), "NUMBER_OF_FIELDS" | "NUMBER_OF_SETS"))
+        .collect();
+    let identifier = match header.first() {
+        Some(identifier) => identifier,
+        None => return table.clone(),
+    };
+
+    let mut lines = table.lines.clone();
+    if lines.first().map(DataLine::keyword) != Some(identifier.keyword()) {
+        lines.insert(0, (*identifier).clone());
+    }
+
+    let missing = header.iter()
+        .skip(1)
+        .filter(|line| !table.lines.iter().any(|own| own.keyword() == line.keyword()))
+        .map(|line| (*line).clone());
+    lines.splice(1..1, missing);
+
+    DataVec { lines }
+}
+
+fn split_column(column: &str) -> (Option<MeasurementCondition>, String) {
+    // LAB_L_M1 or M1_LAB_L
+    if let Some((field, tag)) = column.rsplit_once('_') {
+        if let Some(condition) = condition_tag(tag) {
+            return (Some(condition), field.to_string());
+        }
+    }
+    if let Some((tag, field)) = column.split_once('_') {
+        if let Some(condition) = condition_tag(tag) {
+            return (Some(condition), field.to_string());
+        }
+    }
+    (None, column.to_string())
+}
+
+fn split_columns(raw: &DataVec) -> Option<Vec<(MeasurementCondition, DataVec)>> {
+    // One table per condition of a table with suffixed columns
+    let format_index = raw.lines.iter().position(|line| line.keyword() == "BEGIN_DATA_FORMAT")? + 1;
+    let columns: Vec<(Option<MeasurementCondition>, String)> = raw.lines.get(format_index)?
+        .raw_samples.iter()
+        .map(|column| split_column(column))
+        .collect();
+
+    let mut conditions: Vec<MeasurementCondition> = Vec::new();
+    for condition in columns.iter().filter_map(|(condition, _)| *condition) {
+        if !conditions.contains(&condition) {
+            conditions.push(condition);
+        }
+    }
+
+    if conditions.is_empty() {
+        return None;
+    }
+
+    let tables = conditions.into_iter().map(|condition| {
+        let keep: Vec<usize> = columns.iter()
+            .enumerate()
+            .filter(|(_, (c, _))| c.is_none_or(|c| c == condition))
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut table = DataVec::new();
+        let mut in_data = false;
+        for (index, line) in raw.lines.iter().enumerate() {
+            let line = match line.keyword() {
+                _ if index == format_index => DataLine::from(keep.iter().map(|k| columns[*k].1.clone()).collect()),
+                "BEGIN_DATA" | "END_DATA" => {
+                    in_data = line.keyword() == "BEGIN_DATA";
+                    line.clone()
+                },
+                _ if in_data => DataLine::from(
+                    keep.iter().map(|k| line.raw_samples.get(*k).cloned().unwrap_or_default()).collect()
+                ),
+                _ => line.clone(),
+            };
+            table.lines.push(line);
+        }
+        table.meta_renumber_fields(keep.len());
+
+        (condition, table)
+    }).collect();
+
+    Some(tables)
+}
+
+#[test]
+fn separate_tables() -> Result<()> {
+    use MeasurementCondition::*;
+
+    let set = ConditionSet::from_file("test_files/conditions0.txt")?;
+    assert_eq!(set.conditions(), vec![M0, M1, M2]);
+
+    let m1 = set.condition(M1).expect("M1 table");
+    assert_eq!(m1.lab().map(|lab| lab.len()), Some(4));
+    assert_eq!(m1.get_float(0, &Field::LAB_B), Some(-7.2));
+    assert_eq!(set.condition(M2).and_then(|m2| m2.get_float(0, &Field::LAB_B)), Some(-1.4));
+    assert!(set.condition(M3).is_none());
+
+    let reread = ConditionSet::from_reader(set.format().as_bytes())?;
+    assert_eq!(reread.conditions(), set.conditions());
+    assert_eq!(reread.condition(M0).map(|t| &t.data_map), set.condition(M0).map(|t| &t.data_map));
+
+    Ok(())
+}
+
+#[test]
+fn suffixed_columns() -> Result<()> {
+    use MeasurementCondition::*;
+
+    let set = ConditionSet::from_file("test_files/conditions1.txt")?;
+    assert_eq!(set.conditions(), vec![M0, M1]);
+
+    let m0 = set.condition(M0).expect("M0 table");
+    assert_eq!(m0.fields, vec![
+        Field::SAMPLE_ID, Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K,
+        Field::LAB_L, Field::LAB_A, Field::LAB_B,
+    ]);
+    assert_eq!(m0.keyword("NUMBER_OF_FIELDS").as_deref(), Some("8"));
+    assert_eq!(set.condition(M1).and_then(|m1| m1.get_float(0, &Field::LAB_B)), Some(-7.2));
+
+    // Splitting and merging again gives back the same file
+    let merged = ConditionSet::merge(set.clone().split())?;
+    assert_eq!(merged, set);
+    let text = std::fs::read_to_string("test_files/conditions1.txt")?;
+    assert_eq!(merged.format_columns(), text);
+
+    assert!(ConditionSet::merge(vec![m0.clone(), m0.clone()]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn xrite_blocks() -> Result<()> {
+    use MeasurementCondition::*;
+
+    // Reading one table would drop the M1 and M2 blocks
+    assert!(Cgats::from_file("test_files/xrite1.txt").is_err());
+
+    let set = ConditionSet::from_file("test_files/xrite1.txt")?;
+    assert_eq!(set.conditions(), vec![M0, M1, M2]);
+
+    for table in set.clone().split() {
+        assert_eq!(table.vendor, Vendor::XRite);
+        assert_eq!(table.metadata().serial.as_deref(), Some("1012345"));
+        assert_eq!(table.keyword("LGOROWLENGTH").as_deref(), Some("2"));
+    }
+
+    assert_eq!(set.condition(M0).and_then(|m0| m0.get_float(0, &Field::LAB_B)), Some(-2.1));
+    assert_eq!(set.condition(M1).and_then(|m1| m1.get_float(0, &Field::LAB_B)), Some(-7.2));
+    assert_eq!(set.condition(M2).and_then(|m2| m2.get_float(0, &Field::LAB_B)), Some(-3.2));
+
+    Ok(())
+}
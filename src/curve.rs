@@ -0,0 +1,361 @@
+use super::*;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A one-dimensional transfer function sampled at increasing input values.
+/// Values between the samples are found with monotone cubic (Fritsch-Carlson)
+/// interpolation, so a monotone set of points never overshoots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    points: Vec<(Float, Float)>,
+    tangents: Vec<Float>,
+}
+
+impl Curve {
+    pub fn new(mut points: Vec<(Float, Float)>) -> Result<Curve> {
+    //! Create a new Curve from (input, output) pairs in any order.
+    //! Points sharing the same input are averaged.
+    //! Returns an Error if fewer than 2 distinct inputs are given.
+        if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+            return Err(Error::IncompleteData);
+        }
+
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Curve input is NaN!"));
+
+        let mut merged: Vec<(Float, Float, usize)> = Vec::with_capacity(points.len());
+        for (x, y) in points {
+            match merged.last_mut() {
+                Some(last) if (last.0 - x).abs() < Float::EPSILON => {
+                    last.1 += y;
+                    last.2 += 1;
+                },
+                _ => merged.push((x, y, 1)),
+            }
+        }
+
+        let points: Vec<(Float, Float)> = merged.into_iter()
+            .map(|(x, y, n)| (x, y / n as Float))
+            .collect();
+
+        if points.len() < 2 {
+            return Err(Error::IncompleteData);
+        }
+
+        let tangents = monotone_tangents(&points);
+
+        Ok(Curve { points, tangents })
+    }
+
+    pub fn identity() -> Curve {
+    //! A straight line from 0 to 100
+        Curve::new(vec![(0.0, 0.0), (100.0, 100.0)])
+            .expect("Identity curve has 2 points!")
+    }
+
+    pub fn points(&self) -> &[(Float, Float)] {
+    //! The (input, output) pairs the Curve was built from
+        &self.points
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = Float> + '_ {
+    //! The input values of the sampled points
+        self.points.iter().map(|(x, _)| *x)
+    }
+
+    pub fn min_input(&self) -> Float {
+        self.points[0].0
+    }
+
+    pub fn max_input(&self) -> Float {
+        self.points[self.points.len() - 1].0
+    }
+
+    pub fn interpolate(&self, x: Float) -> Float {
+    //! Find the output for any input. Inputs outside of the sampled
+    //! range are extrapolated linearly from the end segments.
+        let last = self.points.len() - 1;
+
+        if x <= self.points[0].0 {
+            let (x0, y0) = self.points[0];
+            return y0 + (x - x0) * segment_slope(&self.points, 0);
+        }
+
+        if x >= self.points[last].0 {
+            let (x1, y1) = self.points[last];
+            return y1 + (x - x1) * segment_slope(&self.points, last - 1);
+        }
+
+        let index = self.points.iter()
+            .position(|(px, _)| *px > x)
+            .expect("Input is inside the curve range!") - 1;
+
+        let (x0, y0) = self.points[index];
+        let (x1, y1) = self.points[index + 1];
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+
+        let h00 = (1.0 + 2.0 * t) * (1.0 - t).powi(2);
+        let h10 = t * (1.0 - t).powi(2);
+        let h01 = t.powi(2) * (3.0 - 2.0 * t);
+        let h11 = t.powi(2) * (t - 1.0);
+
+        h00 * y0 + h10 * h * self.tangents[index] + h01 * y1 + h11 * h * self.tangents[index + 1]
+    }
+
+    pub fn is_increasing(&self) -> bool {
+    //! Test that each output is greater than the one before it
+        self.points.windows(2).all(|w| w[1].1 > w[0].1)
+    }
+
+    pub fn is_decreasing(&self) -> bool {
+    //! Test that each output is less than the one before it
+        self.points.windows(2).all(|w| w[1].1 < w[0].1)
+    }
+
+    pub fn inverse(&self) -> Result<Curve> {
+    //! Swap the inputs and outputs of a strictly monotone Curve.
+    //! Returns an Error if the outputs are not strictly monotone.
+        if !self.is_increasing() && !self.is_decreasing() {
+            return Err(Error::Other("Curve is not monotone and cannot be inverted!".to_string()));
+        }
+
+        Curve::new(self.points.iter().map(|(x, y)| (*y, *x)).collect())
+    }
+
+    pub fn resample<I: IntoIterator<Item = Float>>(&self, inputs: I) -> Result<Curve> {
+    //! Evaluate the Curve at new input values
+        Curve::new(inputs.into_iter().map(|x| (x, self.interpolate(x))).collect())
+    }
+
+    pub fn map<F: FnMut(Float, Float) -> Float>(&self, mut f: F) -> Result<Curve> {
+    //! Create a new Curve with the same inputs and transformed outputs
+        Curve::new(self.points.iter().map(|(x, y)| (*x, f(*x, *y))).collect())
+    }
+
+    pub fn compose(&self, inner: &Curve) -> Result<Curve> {
+    //! Returns `self(inner(x))` sampled at the inputs of `inner`
+        Curve::new(inner.points.iter().map(|(x, y)| (*x, self.interpolate(*y))).collect())
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (x, y) in &self.points {
+            writeln!(f, "{:0.4}\t{:0.4}", x, y)?;
+        }
+        Ok(())
+    }
+}
+
+// Input steps written to curve files: 0, 5, 10 ... 100
+const CURVE_FILE_STEPS: usize = 21;
+
+/// Transfer Curves for each Channel of a CMYK device.
+/// Channels without a Curve pass their values through unchanged.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CurveSet {
+    curves: BTreeMap<Channel, Curve>,
+}
+
+impl CurveSet {
+    pub fn new() -> CurveSet {
+        CurveSet::default()
+    }
+
+    pub fn insert(&mut self, channel: Channel, curve: Curve) {
+        self.curves.insert(channel, curve);
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&Curve> {
+        self.curves.get(&channel)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.curves.is_empty()
+    }
+
+    pub fn apply(&self, channel: Channel, value: Float) -> Float {
+    //! Transform a device value through the Curve of a Channel
+        match self.curves.get(&channel) {
+            Some(curve) => curve.interpolate(value).clamp(0.0, 100.0),
+            None => value,
+        }
+    }
+
+    pub fn from_cgats(cgats: &Cgats) -> Result<CurveSet> {
+    //! Read a curve file written by `to_cgats`, `to_curve3` or Curve3 itself.
+    //! Returns an Error if there is no SAMPLE_NAME input or no CMYK output.
+        let name_index = cgats.field_index(&Field::SAMPLE_NAME).ok_or(Error::IncompleteData)?;
+        let mut curves = CurveSet::new();
+
+        for channel in Channel::CMYK.iter() {
+            let points = cgats.data_map.iter()
+                .map(|(key, sample)| {
+                    let input = sample.values.get(name_index)?
+                        .string.trim_matches('"')
+                        .parse::<Float>().ok()?;
+                    Some((input, cgats.get_float(*key, &channel.field())?))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::IncompleteData)?;
+
+            curves.insert(*channel, Curve::new(points)?);
+        }
+
+        Ok(curves)
+    }
+
+    pub fn to_cgats(&self) -> Cgats {
+    //! Format the CurveSet as a CGATS.17 curve file:
+    //! SAMPLE_NAME is the input %, the CMYK fields are the output %
+        let mut cgats = self.to_curve3();
+        cgats.vendor = Vendor::Cgats;
+
+        cgats.meta = DataVec::from(
+            [
+                vec!["CGATS.17".to_string()],
+                vec!["DESCRIPTOR".to_string(), "\"Calibration curves\"".to_string()],
+                vec!["NUMBER_OF_FIELDS".to_string(), "6".to_string()],
+                vec!["NUMBER_OF_SETS".to_string(), CURVE_FILE_STEPS.to_string()],
+            ].iter().cloned().map(DataLine::from).collect()
+        );
+
+        for (key, sample) in cgats.data_map.iter_mut() {
+            let input = round_to(curve_file_inputs().nth(*key).expect("Key is a curve step!"), 2);
+            sample.values[0] = CgatsValue::from_float(*key as Float);
+            sample.values[1] = CgatsValue::from_float(input);
+        }
+
+        cgats
+    }
+
+    pub fn to_curve3(&self) -> Cgats {
+    //! Format the CurveSet in the CGATS layout Curve3 writes:
+    //! SAMPLE_NAME is the input %, the CMYK fields are the output %
+        let mut cgats = Cgats::new_with_vendor(Vendor::Curve);
+
+        cgats.meta = DataVec::from(
+            [
+                vec!["\"Calibration curve file\"".to_string()],
+                vec!["#".to_string()],
+                vec!["# Values in this file are 'Wanted'".to_string()],
+                vec!["#".to_string()],
+                vec!["NUMBER_OF_FIELDS".to_string(), "6".to_string()],
+                vec!["NUMBER_OF_SETS".to_string(), CURVE_FILE_STEPS.to_string()],
+            ].iter().cloned().map(DataLine::from).collect()
+        );
+
+        cgats.fields = vec![
+            Field::SAMPLE_ID, Field::SAMPLE_NAME,
+            Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K,
+        ];
+
+        for (index, input) in curve_file_inputs().enumerate() {
+            let mut values = vec![
+                CgatsValue { string: format!("A{}", index), float: None },
+                CgatsValue { string: format!("\"{:0.2}\"", input), float: None },
+            ];
+
+            for channel in Channel::CMYK.iter() {
+                values.push(CgatsValue::from_float(round_to(self.apply(*channel, input), 2)));
+            }
+
+            cgats.data_map.insert(index, Sample { values });
+        }
+
+        cgats
+    }
+}
+
+pub(crate) fn curve_file_inputs() -> impl Iterator<Item = Float> {
+//! The input values of each step in a curve file
+    (0..CURVE_FILE_STEPS).map(|i| i as Float * 100.0 / (CURVE_FILE_STEPS - 1) as Float)
+}
+
+fn segment_slope(points: &[(Float, Float)], index: usize) -> Float {
+    let (x0, y0) = points[index];
+    let (x1, y1) = points[index + 1];
+    (y1 - y0) / (x1 - x0)
+}
+
+// Fritsch-Carlson tangents for monotone cubic Hermite interpolation
+fn monotone_tangents(points: &[(Float, Float)]) -> Vec<Float> {
+    let n = points.len();
+    let slopes: Vec<Float> = (0..n - 1).map(|i| segment_slope(points, i)).collect();
+
+    let mut tangents = Vec::with_capacity(n);
+    tangents.push(slopes[0]);
+    for i in 1..n - 1 {
+        if slopes[i - 1] * slopes[i] <= 0.0 {
+            tangents.push(0.0);
+        } else {
+            tangents.push((slopes[i - 1] + slopes[i]) / 2.0);
+        }
+    }
+    tangents.push(slopes[n - 2]);
+
+    for i in 0..n - 1 {
+        if slopes[i] == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+
+        let alpha = tangents[i] / slopes[i];
+        let beta = tangents[i + 1] / slopes[i];
+        let sum = alpha.powi(2) + beta.powi(2);
+
+        if sum > 9.0 {
+            let tau = 3.0 / sum.sqrt();
+            tangents[i] = tau * alpha * slopes[i];
+            tangents[i + 1] = tau * beta * slopes[i];
+        }
+    }
+
+    tangents
+}
+
+#[test]
+fn interpolate() -> Result<()> {
+    let curve = Curve::new(vec![(100.0, 100.0), (0.0, 0.0), (50.0, 70.0)])?;
+
+    assert_eq!(curve.interpolate(0.0), 0.0);
+    assert_eq!(curve.interpolate(50.0), 70.0);
+    assert_eq!(curve.interpolate(100.0), 100.0);
+    assert!(curve.interpolate(25.0) > 35.0 && curve.interpolate(25.0) < 70.0);
+    assert!(curve.interpolate(75.0) > 70.0 && curve.interpolate(75.0) < 100.0);
+
+    let dense = curve.resample((0..=20).map(|i| i as Float * 5.0))?;
+    let inverse = dense.inverse()?;
+    assert!((inverse.interpolate(dense.interpolate(33.0)) - 33.0).abs() < 0.1);
+
+    assert!(Curve::new(vec![(1.0, 1.0)]).is_err());
+    assert!(Curve::new(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)])?.inverse().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn curve3_layout() -> Result<()> {
+    let mut curves = CurveSet::new();
+    curves.insert(Channel::Cyan, Curve::new(vec![(0.0, 0.0), (50.0, 40.0), (100.0, 100.0)])?);
+
+    let temp = test::mktemp()?;
+    curves.to_curve3().write_to_file(&temp)?;
+    let curve3 = Cgats::from_file(&temp)?;
+    let expected = Cgats::from_file("test_files/curve0.txt")?;
+
+    assert_eq!(curve3.vendor, Vendor::Curve);
+    assert_eq!(curve3.fields, expected.fields);
+    assert_eq!(curve3.sample_count(), expected.sample_count());
+    assert_eq!(curve3.get_float(10, &Field::CMYK_C), Some(40.0));
+    assert_eq!(curve3.get_float(10, &Field::CMYK_M), Some(50.0));
+
+    assert_eq!(CurveSet::from_cgats(&curve3)?.apply(Channel::Cyan, 50.0), 40.0);
+    assert_eq!(CurveSet::from_cgats(&curves.to_cgats())?.apply(Channel::Cyan, 50.0), 40.0);
+    assert_eq!(CurveSet::from_cgats(&expected)?.apply(Channel::Black, 50.0), 47.71);
+
+    std::fs::remove_file(temp)?;
+    Ok(())
+}
@@ -0,0 +1,733 @@
+use super::*;
+
+use roxmltree::{Document, Node};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const CXF_NAMESPACE: &str = "http://colorexchangeformat.com/CxF3-core";
+const SPOT_NAMESPACE: &str = "http://colorexchangeformat.com/CxF3-SpotInkCharacterisation";
+
+// The ID of the single ColorSpecification written by `Cgats::to_cxf`
+const SPEC_ID: &str = "CS1";
+
+// MEASUREMENT_CONDITION keywords and their CxF3 DeviceIllumination
+const CONDITIONS: &[(&str, &str)] = &[
+    ("M0", "M0_Incandescent"),
+    ("M1", "M1_Part1"),
+    ("M2", "M2_UVExcluded"),
+    ("M3", "M3_Polarized"),
+];
+
+// CxF3 color elements and the child elements that hold each field
+const LAB_ELEMENT: (&str, [(&str, Field); 3]) =
+    ("ColorCIELab", [("L", Field::LAB_L), ("A", Field::LAB_A), ("B", Field::LAB_B)]);
+const XYZ_ELEMENT: (&str, [(&str, Field); 3]) =
+    ("ColorCIEXYZ", [("X", Field::XYZ_X), ("Y", Field::XYZ_Y), ("Z", Field::XYZ_Z)]);
+const RGB_ELEMENT: (&str, [(&str, Field); 3]) =
+    ("ColorRGB", [("R", Field::RGB_R), ("G", Field::RGB_G), ("B", Field::RGB_B)]);
+const CMYK_ELEMENT: (&str, [(&str, Field); 4]) = ("ColorCMYK", [
+    ("Cyan", Field::CMYK_C), ("Magenta", Field::CMYK_M),
+    ("Yellow", Field::CMYK_Y), ("Black", Field::CMYK_K),
+]);
+
+// Measurement conditions of a CxF3 ColorSpecification
+#[derive(Debug, Default, Clone)]
+struct ColorSpec {
+    illuminant: Option<String>,
+    observer:   Option<String>,
+    geometry:   Option<String>,
+    condition:  Option<String>,
+    filter:     Option<String>,
+    instrument: Option<String>,
+    start_wl:   Option<u32>,
+    increment:  Option<u32>,
+}
+
+impl ColorSpec {
+    fn from_node(node: Node) -> ColorSpec {
+        let measurement = child(node, "MeasurementSpec");
+        let device = measurement.and_then(|m| child(m, "Device"));
+        let range = measurement.and_then(|m| child(m, "WavelengthRange"));
+        let angle = measurement
+            .and_then(|m| child(m, "GeometryChoice"))
+            .and_then(|g| child(g, "SingleAngle"));
+
+        ColorSpec {
+            illuminant: child(node, "TristimulusSpec").and_then(|t| text(t, "Illuminant")),
+            observer: child(node, "TristimulusSpec").and_then(|t| text(t, "Observer"))
+                .map(|o| o.trim_end_matches("_Degree").to_string()),
+            geometry: angle.and_then(|a| {
+                Some(format!("{}/{}", text(a, "IlluminationAngle")?, text(a, "MeasurementAngle")?))
+            }),
+            condition: device.and_then(|d| text(d, "DeviceIllumination")).map(|illumination| {
+                CONDITIONS.iter()
+                    .find(|(_, cxf)| *cxf == illumination)
+                    .map_or(illumination.clone(), |(condition, _)| condition.to_string())
+            }),
+            filter: device.and_then(|d| text(d, "DeviceFilter"))
+                .map(|f| f.trim_start_matches("Filter_").to_string()),
+            instrument: device.and_then(|d| text(d, "Model")),
+            start_wl: range.and_then(|r| r.attribute("StartWL")?.parse().ok()),
+            increment: range.and_then(|r| r.attribute("Increment")?.parse().ok()),
+        }
+    }
+
+    fn from_cgats(cgats: &Cgats, grid: &[u32]) -> ColorSpec {
+        ColorSpec {
+            illuminant: cgats.keyword("ILLUMINATION_NAME"),
+            observer:   cgats.keyword("OBSERVER_ANGLE"),
+            geometry:   cgats.keyword("MEASUREMENT_GEOMETRY"),
+            condition:  cgats.keyword("MEASUREMENT_CONDITION"),
+            filter:     cgats.keyword("FILTER"),
+            instrument: cgats.keyword("INSTRUMENTATION"),
+            start_wl:   grid.first().copied(),
+            increment:  grid.get(1).zip(grid.first()).map(|(b, a)| b - a),
+        }
+    }
+
+    fn keywords(&self) -> Vec<(&'static str, &String)> {
+        [
+            ("INSTRUMENTATION", &self.instrument),
+            ("MEASUREMENT_CONDITION", &self.condition),
+            ("MEASUREMENT_GEOMETRY", &self.geometry),
+            ("FILTER", &self.filter),
+            ("ILLUMINATION_NAME", &self.illuminant),
+            ("OBSERVER_ANGLE", &self.observer),
+        ].iter()
+            .filter_map(|(keyword, value)| Some((*keyword, value.as_ref()?)))
+            .collect()
+    }
+
+    fn to_xml(&self) -> String {
+        let mut s = format!("      <cc:ColorSpecification Id=\"{}\">\n", SPEC_ID);
+
+        s.push_str("        <cc:TristimulusSpec>\n");
+        s.push_str(&element(10, "Illuminant", self.illuminant.as_deref().unwrap_or("D50")));
+        s.push_str(&element(10, "Observer",
+            &format!("{}_Degree", self.observer.as_deref().unwrap_or("2"))));
+        s.push_str(&element(10, "Method", "E308"));
+        s.push_str("        </cc:TristimulusSpec>\n");
+
+        s.push_str("        <cc:MeasurementSpec>\n");
+        s.push_str(&element(10, "MeasurementType", "Spectrum_Reflectance"));
+
+        let angles = self.geometry.as_ref().and_then(|g| {
+            let mut angles = g.split('/').map(str::trim);
+            let illumination = angles.next()?.parse::<u32>().ok()?;
+            let measurement = angles.next()?.parse::<u32>().ok()?;
+            Some((illumination, measurement))
+        });
+        if let Some((illumination, measurement)) = angles {
+            s.push_str("          <cc:GeometryChoice>\n            <cc:SingleAngle>\n");
+            s.push_str(&element(14, "IlluminationAngle", &illumination.to_string()));
+            s.push_str(&element(14, "MeasurementAngle", &measurement.to_string()));
+            s.push_str("            </cc:SingleAngle>\n          </cc:GeometryChoice>\n");
+        }
+
+        if let Some(start) = self.start_wl {
+            s.push_str(&format!("          <cc:WavelengthRange StartWL=\"{}\" Increment=\"{}\"/>\n",
+                start, self.increment.unwrap_or(10)));
+        }
+
+        if self.instrument.is_some() || self.condition.is_some() || self.filter.is_some() {
+            s.push_str("          <cc:Device>\n");
+            if let Some(instrument) = &self.instrument {
+                s.push_str(&element(12, "Model", instrument));
+            }
+            if let Some(condition) = &self.condition {
+                let illumination = CONDITIONS.iter()
+                    .find(|(keyword, _)| keyword == condition)
+                    .map_or(condition.as_str(), |(_, cxf)| cxf);
+                s.push_str(&element(12, "DeviceIllumination", illumination));
+            }
+            if let Some(filter) = &self.filter {
+                s.push_str(&element(12, "DeviceFilter", &format!("Filter_{}", filter)));
+            }
+            s.push_str("          </cc:Device>\n");
+        }
+
+        s.push_str("        </cc:MeasurementSpec>\n");
+        s.push_str("      </cc:ColorSpecification>\n");
+        s
+    }
+}
+
+// One row of the CGATS table: a CxF Object, or one tint of a CxF/X-4 tint ladder
+#[derive(Debug, Default)]
+struct Record {
+    name:       String,
+    tint:       Option<Float>,
+    background: Option<String>,
+    values:     BTreeMap<Field, Float>,
+}
+
+// The values of a Record by the measurement condition of their ColorSpecification.
+// Values without a condition, e.g. device values, belong to every condition.
+type ConditionValues = BTreeMap<Option<String>, BTreeMap<Field, Float>>;
+
+impl Cgats {
+    pub fn from_cxf_file<P: AsRef<Path>>(path: P) -> Result<Cgats> {
+    //! Read a CxF3 (ISO 17972) XML file
+        Cgats::from_cxf(&read_to_string(path)?)
+    }
+
+    pub fn from_cxf(xml: &str) -> Result<Cgats> {
+    //! Read CxF3 XML: one sample per CxF Object, or one sample per tint of a
+    //! CxF/X-4 spot color tint ladder with TINT and BACKGROUND fields.
+    //! The measurement conditions of the first ColorSpecification in use become
+    //! header keywords.
+    //! Returns an Error if the data was measured under more than one condition:
+    //! use `ConditionSet::from_cxf` to read those.
+        let mut tables = cxf_tables(xml)?;
+
+        if tables.len() > 1 {
+            return Err(Error::Other(format!(
+                "CxF3 data has {} measurement conditions! Read it with ConditionSet::from_cxf", tables.len()
+            )));
+        }
+
+        Ok(tables.remove(0))
+    }
+
+    pub fn write_cxf_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    //! Write the CGATS data to a CxF3 XML file
+        let cxf = self.to_cxf()?;
+        let mut buf = BufWriter::new(File::create(path)?);
+        write!(buf, "{}", cxf)?;
+        Ok(())
+    }
+
+    pub fn to_cxf(&self) -> Result<String> {
+    //! Format the CGATS data as CxF3 XML. Consecutive samples with the same
+    //! SAMPLE_NAME and a TINT become a CxF/X-4 tint ladder on one Object.
+    //! Returns an Error if the SPECTRAL fields are not evenly spaced, or if a
+    //! sample has a spectrum with missing values.
+        let grid = wavelength_grid(&self.fields)?;
+        let spec = ColorSpec::from_cgats(self, &grid);
+        let records = self.cxf_records();
+
+        let mut s = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        s.push_str(&format!("<cc:CxF xmlns:cc=\"{}\" xmlns:sc=\"{}\">\n", CXF_NAMESPACE, SPOT_NAMESPACE));
+
+        s.push_str("  <cc:FileInformation>\n");
+        s.push_str(&element(4, "Creator", &self.keyword("ORIGINATOR").unwrap_or_else(|| "cgats".into())));
+        if let Some(created) = self.keyword("CREATED") {
+            s.push_str(&element(4, "CreationDate", &created));
+        }
+        if let Some(description) = self.keyword("DESCRIPTOR") {
+            s.push_str(&element(4, "Description", &description));
+        }
+        s.push_str("  </cc:FileInformation>\n");
+
+        s.push_str("  <cc:Resources>\n    <cc:ObjectCollection>\n");
+
+        let mut index = 0;
+        while index < records.len() {
+            let record = &records[index];
+            s.push_str(&format!("      <cc:Object ObjectType=\"Standard\" Name=\"{}\" Id=\"{}\">\n",
+                escape(&record.name), index + 1));
+
+            if record.tint.is_some() {
+                // Gather the whole ladder of this object
+                let ladder = records[index..].iter()
+                    .take_while(|r| r.tint.is_some() && r.name == record.name)
+                    .collect::<Vec<&Record>>();
+                index += ladder.len();
+                s.push_str(&ladder_xml(&ladder, &grid)?);
+            } else {
+                index += 1;
+                s.push_str(&colors_xml(&record.values, &grid, 8)?);
+            }
+
+            s.push_str("      </cc:Object>\n");
+        }
+
+        s.push_str("    </cc:ObjectCollection>\n    <cc:ColorSpecificationCollection>\n");
+        s.push_str(&spec.to_xml());
+        s.push_str("    </cc:ColorSpecificationCollection>\n  </cc:Resources>\n</cc:CxF>\n");
+
+        Ok(s)
+    }
+
+    fn cxf_records(&self) -> Vec<Record> {
+        let index = |field| self.fields.iter().position(|f| *f == field);
+        let (name, tint, background) = (index(Field::SAMPLE_NAME), index(Field::TINT), index(Field::BACKGROUND));
+
+        self.data_map.iter()
+            .map(|(key, sample)| {
+                let value = |i: Option<usize>| sample.values.get(i?);
+                Record {
+                    name: value(name).map_or_else(|| format!("Sample {}", key + 1), |v| v.string.clone()),
+                    tint: value(tint).and_then(|v| v.float),
+                    background: value(background).map(|v| v.string.clone()).filter(|b| !b.is_empty()),
+                    values: self.fields.iter()
+                        .zip(&sample.values)
+                        .filter(|(field, _)| field.is_float() && **field != Field::TINT)
+                        .filter_map(|(field, value)| Some((*field, value.float?)))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ConditionSet {
+    pub fn from_cxf_file<P: AsRef<Path>>(path: P) -> Result<ConditionSet> {
+    //! Read a CxF3 (ISO 17972) XML file with one or more measurement conditions
+        ConditionSet::from_cxf(&read_to_string(path)?)
+    }
+
+    pub fn from_cxf(xml: &str) -> Result<ConditionSet> {
+    //! Read CxF3 XML with one table per measurement condition of its ColorSpecifications,
+    //! e.g. an Object with M0 and M1 ColorValues.
+    //! Returns an Error if a ColorSpecification has no M0...M3 condition.
+        ConditionSet::merge(cxf_tables(xml)?)
+    }
+}
+
+fn cxf_tables(xml: &str) -> Result<Vec<Cgats>> {
+// One table per measurement condition, each with every Object
+    let doc = Document::parse(xml)
+        .map_err(|e| Error::Other(format!("Invalid CxF3 XML: {}", e)))?;
+    let root = doc.root_element();
+
+    let specs = root.descendants()
+        .filter(|node| node.tag_name().name() == "ColorSpecification")
+        .filter_map(|node| Some((node.attribute("Id")?.to_string(), ColorSpec::from_node(node))))
+        .collect::<BTreeMap<String, ColorSpec>>();
+
+    let mut records: Vec<(Record, ConditionValues)> = Vec::new();
+    let mut spec_ids = Vec::new();
+
+    for object in root.descendants().filter(|node| node.tag_name().name() == "Object") {
+        let name = object.attribute("Name").unwrap_or_default().to_string();
+
+        let ladder = object.descendants()
+            .filter(|node| node.tag_name().name() == "MeasurementSet")
+            .flat_map(|set| {
+                let background = set.attribute("Background").unwrap_or("Substrate");
+                set.children()
+                    .filter(|node| node.tag_name().name() == "Measurement")
+                    .map(move |measurement| (background, measurement))
+            })
+            .map(|(background, measurement)| {
+                let mut values = ConditionValues::new();
+                read_colors(measurement, &specs, &mut spec_ids, &mut values)?;
+                Ok((Record {
+                    name: name.clone(),
+                    tint: measurement.attribute("Tint").and_then(|t| t.parse().ok()),
+                    background: Some(background.to_string()),
+                    values: BTreeMap::new(),
+                }, values))
+            })
+            .collect::<Result<Vec<(Record, ConditionValues)>>>()?;
+
+        if ladder.is_empty() {
+            let mut values = ConditionValues::new();
+            for colors in object.children().filter(|node|
+                matches!(node.tag_name().name(), "ColorValues" | "DeviceColorValues")
+            ) {
+                read_colors(colors, &specs, &mut spec_ids, &mut values)?;
+            }
+            records.push((Record { name, ..Record::default() }, values));
+        } else {
+            records.extend(ladder);
+        }
+    }
+
+    if records.is_empty() {
+        return Err(Error::NoData);
+    }
+
+    let mut conditions = records.iter()
+        .flat_map(|(_, values)| values.keys().filter(|condition| condition.is_some()).cloned())
+        .collect::<BTreeSet<Option<String>>>();
+    if conditions.is_empty() {
+        conditions.insert(None);
+    }
+
+    let info = root.children().find(|node| node.tag_name().name() == "FileInformation");
+
+    let mut tables = Vec::with_capacity(conditions.len());
+    for condition in conditions {
+        let mut cgats = Cgats::new();
+        cgats.meta.insert(0, "CGATS.17");
+
+        for (keyword, tag) in &[("ORIGINATOR", "Creator"), ("DESCRIPTOR", "Description"), ("CREATED", "CreationDate")] {
+            if let Some(value) = info.and_then(|info| text(info, tag)) {
+                cgats.set_keyword(keyword, &value);
+            }
+        }
+
+        let in_use = spec_ids.iter().filter_map(|id| specs.get(id)).collect::<Vec<&ColorSpec>>();
+        let spec = in_use.iter().find(|spec| spec.condition == condition)
+            .or_else(|| in_use.first())
+            .copied()
+            .or_else(|| specs.values().next());
+        if let Some(spec) = spec {
+            for (keyword, value) in spec.keywords() {
+                cgats.set_keyword(keyword, value);
+            }
+        }
+
+        // The values of this condition and those that belong to every condition
+        let mut table_records = Vec::with_capacity(records.len());
+        for (record, values) in &records {
+            let mut merged = values.get(&None).cloned().unwrap_or_default();
+            if condition.is_some() {
+                for (field, value) in values.get(&condition).into_iter().flatten() {
+                    insert_value(&mut merged, *field, *value)?;
+                }
+            }
+            table_records.push(Record {
+                name: record.name.clone(),
+                tint: record.tint,
+                background: record.background.clone(),
+                values: merged,
+            });
+        }
+
+        let mut fields = table_records.iter()
+            .flat_map(|record| record.values.keys().copied())
+            .collect::<BTreeSet<Field>>();
+        fields.extend(&[Field::SAMPLE_ID, Field::SAMPLE_NAME]);
+        if table_records.iter().any(|record| record.tint.is_some()) {
+            fields.extend(&[Field::TINT, Field::BACKGROUND]);
+        }
+        cgats.fields = fields.into_iter().collect();
+
+        for (key, record) in table_records.into_iter().enumerate() {
+            let values = cgats.fields.iter()
+                .map(|field| match field {
+                    Field::SAMPLE_ID => CgatsValue::from_float((key + 1) as Float),
+                    Field::SAMPLE_NAME => string_value(&record.name),
+                    Field::BACKGROUND => string_value(record.background.as_deref().unwrap_or_default()),
+                    Field::TINT => record.tint.map(CgatsValue::from_float).unwrap_or_default(),
+                    _ => record.values.get(field).copied().map(CgatsValue::from_float).unwrap_or_default(),
+                })
+                .collect();
+            cgats.data_map.insert(key, Sample { values });
+        }
+
+        tables.push(cgats);
+    }
+
+    Ok(tables)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+fn text(node: Node, name: &str) -> Option<String> {
+    Some(child(node, name)?.text()?.trim().to_string())
+}
+
+fn parse_float(node: Node, name: &str) -> Result<Option<Float>> {
+    match text(node, name) {
+        Some(value) => value.parse::<Float>()
+            .map(Some)
+            .map_err(|_| Error::Other(format!("Invalid CxF3 value in <{}>: '{}'", name, value))),
+        None => Ok(None),
+    }
+}
+
+fn string_value(s: &str) -> CgatsValue {
+    CgatsValue { string: s.to_string(), float: None }
+}
+
+fn read_colors(
+    node: Node,
+    specs: &BTreeMap<String, ColorSpec>,
+    spec_ids: &mut Vec<String>,
+    values: &mut ConditionValues,
+) -> Result<()> {
+    for color in node.children().filter(Node::is_element) {
+        let spec = color.attribute("ColorSpecification");
+        if let Some(id) = spec {
+            if !spec_ids.iter().any(|s| s == id) {
+                spec_ids.push(id.to_string());
+            }
+        }
+
+        let condition = spec.and_then(|id| specs.get(id)?.condition.clone());
+        let values = values.entry(condition).or_default();
+
+        let tag = color.tag_name().name();
+        let channels: &[(&str, Field)] = match tag {
+            t if t == LAB_ELEMENT.0 => &LAB_ELEMENT.1,
+            t if t == XYZ_ELEMENT.0 => &XYZ_ELEMENT.1,
+            t if t == RGB_ELEMENT.0 => &RGB_ELEMENT.1,
+            t if t == CMYK_ELEMENT.0 => &CMYK_ELEMENT.1,
+            "ReflectanceSpectrum" => {
+                let spec = spec.and_then(|id| specs.get(id));
+                let start = color.attribute("StartWL").and_then(|s| s.parse().ok())
+                    .or_else(|| spec?.start_wl)
+                    .unwrap_or(380);
+                let increment = color.attribute("Increment").and_then(|s| s.parse().ok())
+                    .or_else(|| spec?.increment)
+                    .unwrap_or(10);
+
+                for (i, value) in color.text().unwrap_or_default().split_whitespace().enumerate() {
+                    let value = value.parse::<Float>()
+                        .map_err(|_| Error::Other(format!("Invalid CxF3 reflectance: '{}'", value)))?;
+                    // Wavelengths off the 10nm SPECTRAL_* grid have no field
+                    if let Some(field) = Field::from_spectral_nm(start + i as u32 * increment) {
+                        insert_value(values, field, value)?;
+                    }
+                }
+                continue;
+            },
+            _ => continue,
+        };
+
+        for (name, field) in channels {
+            if let Some(value) = parse_float(color, name)? {
+                insert_value(values, *field, value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_value(values: &mut BTreeMap<Field, Float>, field: Field, value: Float) -> Result<()> {
+// Two ColorSpecifications of the same condition must not disagree about a field
+    match values.insert(field, value) {
+        Some(previous) if previous != value => Err(Error::Other(format!(
+            "Conflicting CxF3 values for {}: {} and {}", field, previous, value
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn element(indent: usize, name: &str, value: &str) -> String {
+    format!("{:indent$}<cc:{name}>{}</cc:{name}>\n", "", escape(value), indent = indent, name = name)
+}
+
+fn color_xml(values: &BTreeMap<Field, Float>, tag: &str, channels: &[(&str, Field)], indent: usize) -> String {
+    let found = channels.iter()
+        .map(|(name, field)| Some((*name, *values.get(field)?)))
+        .collect::<Option<Vec<(&str, Float)>>>();
+
+    match found {
+        Some(found) => {
+            let mut s = format!("{:indent$}<cc:{} ColorSpecification=\"{}\">\n", "", tag, SPEC_ID, indent = indent);
+            for (name, value) in found {
+                s.push_str(&element(indent + 2, name, &value.to_string()));
+            }
+            s.push_str(&format!("{:indent$}</cc:{}>\n", "", tag, indent = indent));
+            s
+        },
+        None => String::new(),
+    }
+}
+
+fn wavelength_grid(fields: &DataFormat) -> Result<Vec<u32>> {
+// The sorted wavelengths of the SPECTRAL fields. A CxF3 spectrum has one increment.
+    let mut grid = fields.iter()
+        .filter_map(|field| field.spectral_nm())
+        .collect::<Vec<u32>>();
+    grid.sort_unstable();
+    grid.dedup();
+
+    match grid.windows(2).find(|pair| pair[1] - pair[0] != grid[1] - grid[0]) {
+        Some(pair) => Err(Error::Other(format!(
+            "CxF3 spectra must be evenly spaced: {}nm to {}nm is not a {}nm step", pair[0], pair[1], grid[1] - grid[0]
+        ))),
+        None => Ok(grid),
+    }
+}
+
+fn spectrum_xml(values: &BTreeMap<Field, Float>, grid: &[u32], indent: usize) -> Result<String> {
+// The values on every wavelength of the grid, or nothing when the record has no spectrum
+    let spectrum = grid.iter()
+        .map(|nm| (*nm, Field::from_spectral_nm(*nm).and_then(|field| values.get(&field))))
+        .collect::<Vec<(u32, Option<&Float>)>>();
+
+    if spectrum.iter().all(|(_, value)| value.is_none()) {
+        return Ok(String::new());
+    }
+
+    let values = spectrum.iter()
+        .map(|(nm, value)| value.map(|v| v.to_string())
+            .ok_or_else(|| Error::Other(format!("CxF3 spectrum has no value at {}nm!", nm))))
+        .collect::<Result<Vec<String>>>()?;
+
+    Ok(format!(
+        "{:indent$}<cc:ReflectanceSpectrum ColorSpecification=\"{}\" StartWL=\"{}\" Increment=\"{}\">{}</cc:ReflectanceSpectrum>\n",
+        "", SPEC_ID, grid[0], grid.get(1).map_or(10, |nm| nm - grid[0]), values.join(" "),
+        indent = indent,
+    ))
+}
+
+fn colors_xml(values: &BTreeMap<Field, Float>, grid: &[u32], indent: usize) -> Result<String> {
+    let pad = " ".repeat(indent);
+    let mut s = String::new();
+
+    let colorimetric = format!("{}{}{}",
+        color_xml(values, LAB_ELEMENT.0, &LAB_ELEMENT.1, indent + 2),
+        color_xml(values, XYZ_ELEMENT.0, &XYZ_ELEMENT.1, indent + 2),
+        spectrum_xml(values, grid, indent + 2)?,
+    );
+    if !colorimetric.is_empty() {
+        s.push_str(&format!("{}<cc:ColorValues>\n{}{}</cc:ColorValues>\n", pad, colorimetric, pad));
+    }
+
+    let device = format!("{}{}",
+        color_xml(values, CMYK_ELEMENT.0, &CMYK_ELEMENT.1, indent + 2),
+        color_xml(values, RGB_ELEMENT.0, &RGB_ELEMENT.1, indent + 2),
+    );
+    if !device.is_empty() {
+        s.push_str(&format!("{}<cc:DeviceColorValues>\n{}{}</cc:DeviceColorValues>\n", pad, device, pad));
+    }
+
+    Ok(s)
+}
+
+fn ladder_xml(ladder: &[&Record], grid: &[u32]) -> Result<String> {
+    let mut backgrounds: Vec<&str> = Vec::new();
+    for record in ladder {
+        let background = record.background.as_deref().unwrap_or("Substrate");
+        if !backgrounds.contains(&background) {
+            backgrounds.push(background);
+        }
+    }
+
+    let mut s = String::from("        <cc:CustomResources>\n          <sc:SpotInkCharacterisation>\n");
+    for background in backgrounds {
+        s.push_str(&format!("            <sc:MeasurementSet Background=\"{}\">\n", escape(background)));
+        for record in ladder.iter()
+            .filter(|r| r.background.as_deref().unwrap_or("Substrate") == background)
+        {
+            s.push_str(&format!("              <sc:Measurement Tint=\"{}\">\n", record.tint.unwrap_or_default()));
+            s.push_str(&color_xml(&record.values, LAB_ELEMENT.0, &LAB_ELEMENT.1, 16));
+            s.push_str(&color_xml(&record.values, XYZ_ELEMENT.0, &XYZ_ELEMENT.1, 16));
+            s.push_str(&spectrum_xml(&record.values, grid, 16)?);
+            s.push_str("              </sc:Measurement>\n");
+        }
+        s.push_str("            </sc:MeasurementSet>\n");
+    }
+    s.push_str("          </sc:SpotInkCharacterisation>\n        </cc:CustomResources>\n");
+    Ok(s)
+}
+
+#[test]
+fn read_cxf() -> Result<()> {
+    let cgats = Cgats::from_cxf_file("test_files/cxf0.cxf")?;
+
+    // Three objects plus a spot color with 3 tints on substrate and 2 on black
+    assert_eq!(cgats.sample_count(), 8);
+    assert_eq!(cgats.keyword("ORIGINATOR"), Some("Spot Library Export".to_string()));
+    assert_eq!(cgats.keyword("DESCRIPTOR"), Some("Process and spot colors & tints".to_string()));
+    assert_eq!(cgats.keyword("MEASUREMENT_CONDITION"), Some("M1".to_string()));
+    assert_eq!(cgats.keyword("MEASUREMENT_GEOMETRY"), Some("45/0".to_string()));
+    assert_eq!(cgats.keyword("OBSERVER_ANGLE"), Some("2".to_string()));
+    assert_eq!(cgats.keyword("FILTER"), Some("None".to_string()));
+    assert_eq!(cgats.keyword("INSTRUMENTATION"), Some("i1Pro 3".to_string()));
+
+    assert!(cgats.fields.contains(&Field::TINT));
+    assert!(cgats.fields.contains(&Field::SPECTRAL_400));
+    assert!(cgats.fields.contains(&Field::SPECTRAL_700));
+    assert!(!cgats.fields.contains(&Field::SPECTRAL_710));
+
+    assert_eq!(cgats.get_float(0, &Field::CMYK_C), Some(100.0));
+    assert_eq!(cgats.get_float(1, &Field::LAB_A), Some(74.0));
+    assert_eq!(cgats.get_float(0, &Field::TINT), None);
+    assert_eq!(cgats.get_float(4, &Field::TINT), Some(50.0));
+    assert_eq!(cgats.data_map[&6].values[cgats.field_index(&Field::BACKGROUND).unwrap()].string, "Black");
+
+    Ok(())
+}
+
+#[test]
+fn cxf_roundtrip() -> Result<()> {
+    let cgats = Cgats::from_cxf_file("test_files/cxf0.cxf")?;
+    let xml = cgats.to_cxf()?;
+
+    assert!(xml.contains("<sc:MeasurementSet Background=\"Black\">"));
+    assert!(xml.contains("<cc:DeviceIllumination>M1_Part1</cc:DeviceIllumination>"));
+    assert!(xml.contains("<cc:WavelengthRange StartWL=\"400\" Increment=\"10\"/>"));
+
+    let reread = Cgats::from_cxf(&xml)?;
+    assert_eq!(reread.fields, cgats.fields);
+    assert_eq!(reread.data_map, cgats.data_map);
+    assert_eq!(reread.meta, cgats.meta);
+
+    // Plain CGATS data without names or spectra
+    let cmyk = Cgats::from_file("test_files/colorburst0.txt")?;
+    let reread = Cgats::from_cxf(&cmyk.to_cxf()?)?;
+    assert_eq!(reread.sample_count(), cmyk.sample_count());
+    assert_eq!(reread.get_float(3, &Field::LAB_L), cmyk.get_float(3, &Field::LAB_L));
+
+    Ok(())
+}
+
+#[test]
+fn cxf_spectra() -> Result<()> {
+    let cgats = |fields: &str, values: &str| Cgats::from_reader(format!(
+        "CGATS.17\nBEGIN_DATA_FORMAT\nSAMPLE_ID\t{}\nEND_DATA_FORMAT\nBEGIN_DATA\n{}END_DATA\n", fields, values
+    ).as_bytes());
+
+    // Fields in descending order are written on the ascending grid
+    let descending = cgats("SPECTRAL_420\tSPECTRAL_410\tSPECTRAL_400", "1\t0.3\t0.2\t0.1\n2\t0.6\t0.5\t0.4\n")?;
+    let xml = descending.to_cxf()?;
+    assert!(xml.contains("StartWL=\"400\" Increment=\"10\">0.1 0.2 0.3</cc:ReflectanceSpectrum>"));
+    assert!(xml.contains("<cc:WavelengthRange StartWL=\"400\" Increment=\"10\"/>"));
+
+    let reread = Cgats::from_cxf(&xml)?;
+    for field in [Field::SPECTRAL_400, Field::SPECTRAL_410, Field::SPECTRAL_420] {
+        assert_eq!(reread.get_float(1, &field), descending.get_float(1, &field));
+    }
+
+    // A 20nm grid keeps its increment
+    let wide = cgats("SPECTRAL_400\tSPECTRAL_420\tSPECTRAL_440", "1\t0.1\t0.2\t0.3\n")?;
+    let reread = Cgats::from_cxf(&wide.to_cxf()?)?;
+    assert_eq!(reread.get_float(0, &Field::SPECTRAL_440), Some(0.3));
+    assert!(!reread.fields.contains(&Field::SPECTRAL_410));
+
+    // A gap in one sample or in the grid cannot be written
+    assert!(cgats("SPECTRAL_400\tSPECTRAL_410\tSPECTRAL_420", "1\t0.1\tn/a\t0.3\n")?.to_cxf().is_err());
+    assert!(cgats("SPECTRAL_400\tSPECTRAL_410\tSPECTRAL_430", "1\t0.1\t0.2\t0.3\n")?.to_cxf().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn cxf_conditions() -> Result<()> {
+    // Each Object has M0 and M1 ColorValues
+    assert!(Cgats::from_cxf_file("test_files/cxf1.cxf").is_err());
+
+    let set = ConditionSet::from_cxf_file("test_files/cxf1.cxf")?;
+    assert_eq!(set.conditions(), vec![MeasurementCondition::M0, MeasurementCondition::M1]);
+
+    let m0 = set.condition(MeasurementCondition::M0).expect("No M0 table!");
+    let m1 = set.condition(MeasurementCondition::M1).expect("No M1 table!");
+    assert_eq!(m0.get_float(0, &Field::LAB_L), Some(50.0));
+    assert_eq!(m1.get_float(0, &Field::LAB_L), Some(60.0));
+    assert_eq!(m0.keyword("MEASUREMENT_CONDITION"), Some("M0".to_string()));
+    assert_eq!(m1.keyword("MEASUREMENT_CONDITION"), Some("M1".to_string()));
+
+    // Device values belong to every condition
+    assert_eq!(m0.get_float(1, &Field::CMYK_C), Some(100.0));
+    assert_eq!(m1.get_float(1, &Field::CMYK_C), Some(100.0));
+
+    // Without a condition the two specifications disagree about the same fields
+    let xml = std::fs::read_to_string("test_files/cxf1.cxf")?
+        .lines()
+        .filter(|line| !line.contains("DeviceIllumination"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    assert!(Cgats::from_cxf(&xml).is_err());
+
+    Ok(())
+}
@@ -11,7 +11,7 @@ pub type DataMap = BTreeMap<usize, Sample>;
 // type MapKey = (usize, Field);
 pub type Float = f32;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct CgatsValue {
     pub string: String,
     pub float: Option<Float>,
@@ -27,19 +27,17 @@ impl CgatsValue {
 
     fn add_mut(&mut self, other: &CgatsValue) {
         if let Some(f) = self.float {
-                let float = Some(f + other.float.unwrap_or(0.0));
-                let string = float.unwrap().to_string();
-                self.float = float;
-                self.string = string;
+                let float = f + other.float.unwrap_or(0.0);
+                self.string = float.to_string();
+                self.float = Some(float);
         }
     }
 
     fn divide_mut(&mut self, divisor: usize) {
         if let Some(f) = self.float {
-                let float = Some(f / divisor as Float);
-                let string = float.unwrap().to_string();
-                self.float = float;
-                self.string = string;
+                let float = f / divisor as Float;
+                self.string = float.to_string();
+                self.float = Some(float);
         }
     }
 
@@ -59,15 +57,6 @@ impl FromStr for CgatsValue {
     }
 }
 
-impl Default for CgatsValue {
-    fn default() -> CgatsValue {
-        CgatsValue {
-            string: String::new(),
-            float: None,
-        }
-    }
-}
-
 impl fmt::Display for CgatsValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.float {
@@ -77,7 +66,7 @@ impl fmt::Display for CgatsValue {
     }
 }
 
-fn round_to(float: Float, places: i32) -> Float {
+pub fn round_to(float: Float, places: i32) -> Float {
     let mult = (10 as Float).powi(places);
     (float * mult).round() / mult
 }
@@ -126,6 +115,15 @@ impl Sample {
             b: self.values.get(indexes[2])?.float?,
         })
     }
+
+    pub fn to_xyz(&self, indexes: &[usize; 3]) -> Option<[Float; 3]> {
+    //! XYZ is stored as 0...100 in CGATS, but returned as 0.0...1.0
+        Some([
+            self.values.get(indexes[0])?.float? / 100.0,
+            self.values.get(indexes[1])?.float? / 100.0,
+            self.values.get(indexes[2])?.float? / 100.0,
+        ])
+    }
 }
 
 impl fmt::Display for Sample {
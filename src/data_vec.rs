@@ -4,10 +4,11 @@ use std::io::{BufReader, BufRead};
 use std::fs::File;
 use std::fmt;
 use std::path::Path;
+use std::sync::Arc;
 use std::str::FromStr;
 
 /// Intermediate data format
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct DataLine {
     pub raw_samples: Vec<String>
 }
@@ -30,6 +31,25 @@ impl DataLine {
     pub fn insert(&mut self, index: usize, s: &str) {
         self.raw_samples.insert(index, String::from(s))
     }
+
+    pub fn keyword(&self) -> &str {
+    //! The keyword of a header line, whether it is followed by a tab or by spaces
+        self.raw_samples.first()
+            .and_then(|first| first.split_whitespace().next())
+            .unwrap_or_default()
+    }
+
+    pub fn value(&self) -> String {
+    //! Everything after the keyword of a header line
+        let first = self.raw_samples.first().map_or("", |first| first.trim_start());
+        let rest = first[self.keyword().len()..].trim();
+
+        std::iter::once(rest)
+            .chain(self.raw_samples.iter().skip(1).map(|s| s.trim()))
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
 }
 
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
@@ -53,10 +73,17 @@ impl DataVec {
     }
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DataVec> {
+        DataVec::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<DataVec> {
         let mut data_vec = DataVec::new();
 
+        // Argyll files (CTI1/CTI2/CTI3) are whitespace delimited instead of tab delimited
+        let mut argyll = None;
+
         // Loop through lines and trim trailing whitespace
-        for line in BufReader::new(File::open(path)?).lines() {
+        for line in reader.lines() {
             let text = match line {
                 Ok(txt) => txt.trim().to_string(),
                 Err(_)  => "".to_string()
@@ -69,11 +96,15 @@ impl DataVec {
 
             // Push each item in a line into a Vector
             for split_line in v_cr {
-                let split = split_line.split('\t');
+                let argyll = *argyll.get_or_insert_with(|| is_argyll(split_line));
                 let mut v = DataLine::new();
 
-                for item in split {
-                    v.push(item.trim().to_string());
+                if argyll {
+                    v.raw_samples = split_argyll(split_line);
+                } else {
+                    for item in split_line.split('\t') {
+                        v.push(item.trim().to_string());
+                    }
                 }
 
                 // Push the Vectors into the RawVec
@@ -97,7 +128,7 @@ impl DataVec {
         for (index, line) in data.lines.iter().enumerate() {
             let values = line.raw_samples.iter()
                 .map(|val|
-                    CgatsValue::from_str(&val).unwrap_or_default()
+                    CgatsValue::from_str(val).unwrap_or_default()
                 ).collect();
             let sample = Sample  { values };
             map.insert(index, sample);
@@ -108,16 +139,20 @@ impl DataVec {
 
     // Extract the DATA_FORMAT into a Vector of DataFormatTypes (DataFormat)
     pub fn extract_data_format(&self) -> Result<DataFormat> {
+        self.data_format_with(self.dialect().as_deref())
+    }
+
+    pub(crate) fn data_format_with(&self, dialect: Option<&dyn Dialect>) -> Result<DataFormat> {
+    // The DATA_FORMAT of a file whose dialect has already been detected
         // We need at least 2 lines to extract DATA_FORMAT
         // OK, really 3 lines, but we only need to see 2
         if self.lines.len() < 2 {
             return Err(Error::NoDataFormat);
         }
 
-        // Use implicit format type for ColorBurst LinFiles
-        let vendor = &self.get_vendor()?;
-        if let Vendor::ColorBurst = vendor {
-            return Ok(field::ColorBurstFormat());
+        // Use the implicit format of dialects without DATA_FORMAT, e.g. ColorBurst LinFiles
+        if let Some(fields) = dialect.and_then(|dialect| dialect.implicit_fields()) {
+            return Ok(fields);
         }
 
         let mut data_format = DataFormat::new();
@@ -128,7 +163,7 @@ impl DataVec {
             match item.raw_samples[0].as_str() {
                 "BEGIN_DATA_FORMAT" => {
                     for format_type in self.lines[index + 1].raw_samples.iter() {
-                        let format = Field::from_str(&format_type)?;
+                        let format = Field::from_str(format_type)?;
                         data_format.push(format);
                     }
                     break;
@@ -202,7 +237,7 @@ impl DataVec {
         while index < self.lines.len() {
             let item = &self.lines[index];
             if bookends.contains(&item.raw_samples[0].as_str()) {
-                if tag_switch { tag_switch = false } else { tag_switch = true };
+                tag_switch = !tag_switch;
                 index += 1;
                 continue;
             }
@@ -217,17 +252,58 @@ impl DataVec {
 
     // Get the Vendor from the first line in the DataVec (first line in file)
     pub fn get_vendor(&self) -> Result<Vendor> {
+        let registry = DialectRegistry::global();
+        self.vendor_with(registry.detect(self).as_deref(), &registry)
+    }
+
+    pub(crate) fn vendor_with(&self, dialect: Option<&dyn Dialect>, registry: &DialectRegistry) -> Result<Vendor> {
+    // The Vendor of a file whose dialect has already been detected
         let first_line = match self.lines.first() {
             Some(line) => line,
             None => return Err(Error::UnknownVendor),
         };
-        
+
+        // Registered dialects, by the first line and then by the header keywords
+        if let Some(dialect) = dialect {
+            return Ok(dialect.vendor());
+        }
+
         let s: String = first_line.raw_samples.iter()
             .map(|s| s.to_lowercase())
             .collect::<String>();
 
-        // Search the string for a Vendor
-        Vendor::from_str(&s)
+        // A plain CGATS.17 identifier or an unknown first line
+        Vendor::from_name(&s, registry)
+    }
+
+    pub fn dialect(&self) -> Option<Arc<dyn Dialect>> {
+    //! The registered dialect that recognizes this file
+        DialectRegistry::global().detect(self)
+    }
+
+    pub fn split_tables(&self) -> Vec<DataVec> {
+    //! Split a file with several tables (e.g. the Argyll .ti3 calibration table)
+    //! into one DataVec per table. Each table ends with END_DATA.
+        let mut tables = vec![DataVec::new()];
+
+        for line in &self.lines {
+            if let Some(table) = tables.last_mut() {
+                table.lines.push(line.clone());
+            }
+            if line.raw_samples[0] == "END_DATA" {
+                tables.push(DataVec::new());
+            }
+        }
+
+        // Anything after the last END_DATA belongs to the last table
+        if let Some(rest) = tables.pop() {
+            match tables.last_mut() {
+                Some(last) => last.lines.extend(rest.lines),
+                None => tables.push(rest),
+            }
+        }
+
+        tables
     }
 
     pub fn meta_renumber_sets(&mut self, num: usize) {
@@ -237,6 +313,52 @@ impl DataVec {
             }
         }
     }
+
+    pub fn meta_renumber_fields(&mut self, num: usize) {
+        for line in self.lines.iter_mut() {
+            if line.raw_samples[0].contains("NUMBER_OF_FIELDS") {
+                *line = DataLine::from(vec!["NUMBER_OF_FIELDS".to_string(), num.to_string()]);
+            }
+        }
+    }
+}
+
+fn is_argyll(line: &str) -> bool {
+    let first = line.split_whitespace().next().unwrap_or_default();
+    ["CTI1", "CTI2", "CTI3"].contains(&first)
+}
+
+fn split_argyll(line: &str) -> Vec<String> {
+    // Comments are kept whole
+    if line.starts_with('#') {
+        return vec![line.to_string()];
+    }
+
+    // Split on whitespace, but keep quoted strings (and their quotes) together
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                item.push(c);
+            },
+            c if c.is_whitespace() && !quoted => {
+                if !item.is_empty() {
+                    items.push(std::mem::take(&mut item));
+                }
+            },
+            _ => item.push(c),
+        }
+    }
+
+    if !item.is_empty() {
+        items.push(item);
+    }
+
+    items
 }
 
 impl fmt::Display for DataLine {
@@ -251,14 +373,6 @@ impl fmt::Display for DataLine {
     }
 }
 
-impl Default for DataLine {
-    fn default() -> Self {
-        DataLine {
-            raw_samples: Vec::new(),
-        }
-    }
-}
-
 impl fmt::Display for DataVec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}",
@@ -275,3 +389,12 @@ fn from_file() -> Result<()> {
     println!("{:?}", raw);
     Ok(())
 }
+
+#[test]
+fn argyll_tokens() -> Result<()> {
+    let raw = DataVec::from_reader("CTI3\n\nDESCRIPTOR \"Argyll Calibration Target\"\nBEGIN_DATA_FORMAT\nSAMPLE_ID  RGB_R RGB_G\nEND_DATA_FORMAT\nBEGIN_DATA\n1 0.0 100.0\nEND_DATA\n".as_bytes())?;
+    assert_eq!(raw.lines[1].raw_samples, vec!["DESCRIPTOR", "\"Argyll Calibration Target\""]);
+    assert_eq!(raw.lines[3].raw_samples, vec!["SAMPLE_ID", "RGB_R", "RGB_G"]);
+    assert_eq!(raw.split_tables().len(), 1);
+    Ok(())
+}
@@ -1,66 +1,458 @@
 use super::*;
 use std::convert::TryFrom;
-use deltae::DEMethod;
 use statistical::{mean, standard_deviation};
+use deltae::color::LabValue;
 use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub struct DeReport {
-    de_cgats:  Cgats,
-    de_method: DEMethod,
-    overall:   DeSummary,
-    best_90:   DeSummary,
-    worst_10:  DeSummary,
+    de_cgats:    Cgats,
+    formula:     DeltaEFormula,
+    sorted:      Vec<Float>,
+    overall:     DeSummary,
+    splits:      Vec<DeSplit>,
+    percentiles: Vec<(Float, Float)>,
+    histogram:   Vec<HistogramBin>,
+    worst:       Vec<WorstSample>,
+}
+
+/// What a DeReport includes beyond the overall summary
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeReportOptions {
+    percentiles: Vec<Float>,
+    splits:      Vec<Float>,
+    histogram:   Vec<Float>,
+    worst:       usize,
+}
+
+impl Default for DeReportOptions {
+    fn default() -> DeReportOptions {
+        DeReportOptions {
+            percentiles: Vec::new(),
+            splits:      vec![90.0],
+            histogram:   Vec::new(),
+            worst:       0,
+        }
+    }
+}
+
+impl DeReportOptions {
+    pub fn new() -> DeReportOptions {
+    //! The default report: a split into the best 90% and worst 10%
+        DeReportOptions::default()
+    }
+
+    pub fn percentiles(mut self, percentiles: &[Float]) -> DeReportOptions {
+    //! Report these percentiles (0...100) of the Delta E values, e.g. 50 for the median
+        self.percentiles = percentiles.to_vec();
+        self
+    }
+
+    pub fn splits(mut self, splits: &[Float]) -> DeReportOptions {
+    //! Summarize the best and worst samples on either side of each split point (0...100)
+        self.splits = splits.to_vec();
+        self
+    }
+
+    pub fn histogram(mut self, edges: &[Float]) -> DeReportOptions {
+    //! Count the samples between bin edges, with a last bin above the last edge.
+    //! The edges are sorted and duplicates are removed.
+        self.histogram = edges.to_vec();
+        self.histogram.sort_by(|a, b| a.total_cmp(b));
+        self.histogram.dedup();
+        self
+    }
+
+    pub fn worst(mut self, count: usize) -> DeReportOptions {
+    //! List the `count` samples with the largest Delta E
+        self.worst = count;
+        self
+    }
+}
+
+/// Summaries of the samples below and above a split point
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeSplit {
+    pub percent: Float,
+    pub best:    DeSummary,
+    pub worst:   DeSummary,
+}
+
+/// The number of samples with a Delta E from `min` up to (but not including) `max`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramBin {
+    pub min:   Float,
+    pub max:   Option<Float>,
+    pub count: usize,
+}
+
+/// One of the samples with the largest Delta E
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorstSample {
+    pub key:         usize,
+    pub sample_id:   Option<String>,
+    pub sample_name: Option<String>,
+    pub de:          Float,
+    pub reference:   Option<LabValue>,
+    pub sample:      Option<LabValue>,
 }
 
 impl DeReport {
     pub fn new(de_cgats: &Cgats) -> Result<DeReport> {
+        DeReport::with_options(de_cgats, &DeReportOptions::default())
+    }
+
+    pub fn with_options(de_cgats: &Cgats, options: &DeReportOptions) -> Result<DeReport> {
+    //! Build a report of a Delta E CGATS object.
+    //! Returns an Error if there is no DE field, no data, a percentile or
+    //! split point outside of 0...100, or a negative or non-finite histogram edge.
         let mut de_list =  DeList::try_from(de_cgats)?;
+        if de_list.list.is_empty() {
+            return Err(Error::NoData);
+        }
+
+        if options.percentiles.iter().any(|p| !(0.0..=100.0).contains(p)) {
+            return Err(Error::Other("Percentiles must be between 0 and 100!".to_string()));
+        }
+
+        if options.splits.iter().any(|p| *p <= 0.0 || *p >= 100.0) {
+            return Err(Error::Other("Split points must be between 0 and 100!".to_string()));
+        }
+
+        if options.histogram.iter().any(|edge| !edge.is_finite() || *edge < 0.0) {
+            return Err(Error::Other("Histogram edges must be finite and not negative!".to_string()));
+        }
+
         let overall =  DeSummary::from(&de_list);
 
-        let [best_90_list, worst_10_list] = DeList::split_pct(&mut de_list, 0.9);
-        let best_90 = DeSummary::from(&best_90_list);
-        let worst_10 = DeSummary::from(&worst_10_list);
+        // Splits that would leave either side without samples are skipped
+        let splits = options.splits.iter().filter_map(|percent| {
+            let [best_list, worst_list] = DeList::split_pct(&mut de_list, percent / 100.0)?;
+            Some(DeSplit {
+                percent: *percent,
+                best:    DeSummary::from(&best_list),
+                worst:   DeSummary::from(&worst_list),
+            })
+        }).collect();
 
-        let de_method = de_list.de_method;
+        de_list.list.sort_by(|a, b| a.total_cmp(b));
 
-        Ok(
-            DeReport {
-                de_cgats: de_cgats.clone(),
-                de_method,
-                overall,
-                best_90,
-                worst_10,
-            }
+        let percentiles = options.percentiles.iter()
+            .map(|p| (*p, percentile(&de_list.list, *p)))
+            .collect();
+
+        let histogram = histogram(&de_list.list, &options.histogram);
+
+        let mut report = DeReport {
+            de_cgats: de_cgats.clone(),
+            formula: de_list.formula,
+            sorted: de_list.list,
+            overall,
+            splits,
+            percentiles,
+            histogram,
+            worst: Vec::new(),
+        };
+
+        report.worst = report.worst_samples(options.worst)?;
+
+        Ok(report)
+    }
+
+    pub fn with_samples(mut self, reference: &Cgats, sample: &Cgats) -> DeReport {
+    //! Fill in the identifiers and LAB of the worst samples from the compared data sets
+        for worst in self.worst.iter_mut() {
+            worst.sample_id = text_value(reference, worst.key, &Field::SAMPLE_ID)
+                .or_else(|| worst.sample_id.take());
+            worst.sample_name = text_value(reference, worst.key, &Field::SAMPLE_NAME)
+                .or_else(|| worst.sample_name.take());
+            worst.reference = reference.sample_lab(worst.key);
+            worst.sample = sample.sample_lab(worst.key);
+        }
+        self
+    }
+
+    pub fn formula(&self) -> DeltaEFormula {
+        self.formula
+    }
+
+    pub fn overall(&self) -> &DeSummary {
+        &self.overall
+    }
+
+    pub fn splits(&self) -> &[DeSplit] {
+        &self.splits
+    }
+
+    pub fn percentile(&self, percent: Float) -> Float {
+    //! Any percentile (0...100) of the Delta E values
+        percentile(&self.sorted, percent)
+    }
+
+    pub fn histogram(&self) -> &[HistogramBin] {
+        &self.histogram
+    }
+
+    pub fn worst(&self) -> &[WorstSample] {
+        &self.worst
+    }
+
+    fn worst_samples(&self, count: usize) -> Result<Vec<WorstSample>> {
+        let (index, _) = self.de_cgats.de_formula()?;
+
+        let mut samples: Vec<(usize, Float)> = self.de_cgats.data_map.iter()
+            .filter_map(|(key, sample)| sample.values.get(index)?.float.map(|de| (*key, de)))
+            .collect();
+        samples.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(samples.into_iter().take(count).map(|(key, de)| WorstSample {
+            key,
+            sample_id: text_value(&self.de_cgats, key, &Field::SAMPLE_ID),
+            sample_name: text_value(&self.de_cgats, key, &Field::SAMPLE_NAME),
+            de,
+            reference: None,
+            sample: self.de_cgats.sample_lab(key),
+        }).collect())
+    }
+}
+
+/// Output formats of a DeReport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Cgats,
+}
+
+impl FromStr for ReportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ReportFormat> {
+        match s.trim().to_lowercase().as_str() {
+            "text" | "txt" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "cgats" => Ok(ReportFormat::Cgats),
+            _ => Err(Error::Other(format!("Unknown report format: '{}'", s))),
+        }
+    }
+}
+
+impl DeReport {
+    pub fn format(&self, format: ReportFormat) -> String {
+    //! Format the report as text, JSON, CSV or CGATS
+        match format {
+            ReportFormat::Text  => self.to_string(),
+            ReportFormat::Json  => self.to_json(),
+            ReportFormat::Csv   => self.to_csv(),
+            ReportFormat::Cgats => self.to_cgats().format(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+    //! Serialize the whole report to a JSON object
+        let splits = self.splits.iter().map(|split| format!(
+            "{{\"percent\":{},\"best\":{},\"worst\":{}}}",
+            json_float(split.percent), split.best.to_json(), split.worst.to_json()
+        )).collect::<Vec<_>>();
+
+        let percentiles = self.percentiles.iter().map(|(percent, de)| format!(
+            "{{\"percent\":{},\"de\":{}}}", json_float(*percent), json_float(*de)
+        )).collect::<Vec<_>>();
+
+        let histogram = self.histogram.iter().map(|bin| format!(
+            "{{\"min\":{},\"max\":{},\"count\":{}}}",
+            json_float(bin.min), bin.max.map(json_float).unwrap_or_else(|| "null".to_string()), bin.count
+        )).collect::<Vec<_>>();
+
+        let worst = self.worst.iter().map(WorstSample::to_json).collect::<Vec<_>>();
+
+        format!(
+            "{{\"formula\":{},\"overall\":{},\"splits\":[{}],\"percentiles\":[{}],\"histogram\":[{}],\"worst\":[{}]}}",
+            json_string(&self.formula.to_string()),
+            self.overall.to_json(),
+            splits.join(","),
+            percentiles.join(","),
+            histogram.join(","),
+            worst.join(","),
         )
     }
+
+    pub fn to_csv(&self) -> String {
+    //! Serialize the report to CSV with one statistic per row, named like the CGATS keywords
+        let mut csv = String::from("STATISTIC,VALUE\n");
+        for (keyword, value) in self.keywords() {
+            csv.push_str(&format!("{},{}\n", keyword, csv_value(&value)));
+        }
+        csv
+    }
+
+    pub fn to_cgats(&self) -> Cgats {
+    //! The Delta E data set with the report statistics as keywords in the header
+        let mut cgats = self.de_cgats.clone();
+        let index = cgats.meta.lines.len().min(1);
+
+        for (offset, (keyword, value)) in self.keywords().into_iter().enumerate() {
+            let value = if value.parse::<Float>().is_ok() { value } else { format!("\"{}\"", value) };
+            cgats.meta.lines.insert(index + offset, DataLine::from(vec![keyword, value]));
+        }
+
+        cgats
+    }
+
+    fn keywords(&self) -> Vec<(String, String)> {
+    // The statistics of the report as keyword and value pairs
+        let mut keywords = vec![("DE_FORMULA".to_string(), self.formula.to_string())];
+        keywords.extend(self.overall.keywords("DE"));
+
+        for split in &self.splits {
+            keywords.extend(split.best.keywords(&format!("BEST_{}_DE", keyword_number(split.percent))));
+            keywords.extend(split.worst.keywords(&format!("WORST_{}_DE", keyword_number(round_to(100.0 - split.percent, 4)))));
+        }
+
+        for (percent, de) in &self.percentiles {
+            let name = if *percent == 50.0 { "MEDIAN".to_string() } else { format!("P{}", keyword_number(*percent)) };
+            keywords.push((format!("DE_{}", name), de.to_string()));
+        }
+
+        for bin in &self.histogram {
+            let name = match bin.max {
+                Some(max) => format!("DE_HISTOGRAM_{}_{}", keyword_number(bin.min), keyword_number(max)),
+                None => format!("DE_HISTOGRAM_{}_UP", keyword_number(bin.min)),
+            };
+            keywords.push((name, bin.count.to_string()));
+        }
+
+        for (rank, sample) in self.worst.iter().enumerate() {
+            let id = sample.sample_id.clone().unwrap_or_else(|| sample.key.to_string());
+            keywords.push((format!("WORST_SAMPLE_{}_ID", rank + 1), id));
+            keywords.push((format!("WORST_SAMPLE_{}_DE", rank + 1), sample.de.to_string()));
+        }
+
+        keywords
+    }
 }
 
 impl fmt::Display for DeReport {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "Number of Samples: {}", self.overall.sample_count)?;
-        writeln!(f, "DE Formula: {}\n", self.de_method)?;
+        writeln!(f, "DE Formula: {}\n", self.formula)?;
 
         writeln!(f, "OVERALL - ({} colors)", self.overall.sample_count)?;
         writeln!(f, "{}", self.overall)?;
 
-        writeln!(f, "BEST 90% - ({} colors)", self.best_90.sample_count)?;
-        writeln!(f, "{}", self.best_90)?;
+        for split in &self.splits {
+            writeln!(f, "BEST {}% - ({} colors)", split.percent, split.best.sample_count)?;
+            writeln!(f, "{}", split.best)?;
 
-        writeln!(f, "WORST 10% - ({} colors)", self.worst_10.sample_count)?;
-        writeln!(f, "{}", self.worst_10)?;
+            writeln!(f, "WORST {}% - ({} colors)", round_to(100.0 - split.percent, 4), split.worst.sample_count)?;
+            writeln!(f, "{}", split.worst)?;
+        }
+
+        if !self.percentiles.is_empty() {
+            writeln!(f, "PERCENTILES")?;
+            for (percent, value) in &self.percentiles {
+                writeln!(f, "\t{:>10}: {:0.4}", percentile_name(*percent), value)?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.histogram.is_empty() {
+            writeln!(f, "HISTOGRAM")?;
+            for bin in &self.histogram {
+                let range = match bin.max {
+                    Some(max) => format!("{:0.2} - {:0.2}", bin.min, max),
+                    None => format!(">= {:0.2}", bin.min),
+                };
+                writeln!(f, "\t{:>13}: {} ({:0.1}%)", range, bin.count,
+                    100.0 * bin.count as Float / self.overall.sample_count as Float)?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.worst.is_empty() {
+            writeln!(f, "WORST {} SAMPLES", self.worst.len())?;
+            for sample in &self.worst {
+                writeln!(f, "\t{}", sample)?;
+            }
+            writeln!(f)?;
+        }
 
         Ok(())
     }
 }
 
-#[derive(Debug)]
-struct DeSummary {
-    sample_count: usize,
-    mean:  Float,
-    min:   Float,
-    max:   Float,
-    stdev: Float,
+impl WorstSample {
+    pub fn to_json(&self) -> String {
+        let text = |value: &Option<String>| value.as_deref().map(json_string).unwrap_or_else(|| "null".to_string());
+        let lab = |lab: &Option<LabValue>| lab.as_ref()
+            .map(|lab| format!("[{},{},{}]", json_float(lab.l), json_float(lab.a), json_float(lab.b)))
+            .unwrap_or_else(|| "null".to_string());
+
+        format!(
+            "{{\"key\":{},\"sample_id\":{},\"sample_name\":{},\"de\":{},\"reference\":{},\"sample\":{}}}",
+            self.key, text(&self.sample_id), text(&self.sample_name), json_float(self.de),
+            lab(&self.reference), lab(&self.sample),
+        )
+    }
+}
+
+impl fmt::Display for WorstSample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = self.sample_id.clone().unwrap_or_else(|| self.key.to_string());
+        write!(f, "{:>8}", id)?;
+
+        if let Some(name) = &self.sample_name {
+            write!(f, "\t{}", name)?;
+        }
+
+        write!(f, "\tDE: {:0.4}", self.de)?;
+
+        let lab = |lab: &LabValue| format!("{:0.2} {:0.2} {:0.2}", lab.l, lab.a, lab.b);
+        match (&self.reference, &self.sample) {
+            (Some(reference), Some(sample)) => write!(f, "\tLAB: {} -> {}", lab(reference), lab(sample)),
+            (None, Some(lab_value)) | (Some(lab_value), None) => write!(f, "\tLAB: {}", lab(lab_value)),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeSummary {
+    pub sample_count: usize,
+    pub mean:  Float,
+    pub min:   Float,
+    pub max:   Float,
+    pub stdev: Float,
+}
+
+impl DeSummary {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"sample_count\":{},\"mean\":{},\"min\":{},\"max\":{},\"stdev\":{}}}",
+            self.sample_count, json_float(self.mean), json_float(self.min),
+            json_float(self.max), json_float(self.stdev),
+        )
+    }
+
+    pub fn to_csv(&self) -> String {
+    //! A CSV header and one row of values
+        format!("SAMPLE_COUNT,MEAN,MIN,MAX,STDEV\n{},{},{},{},{}\n",
+            self.sample_count, self.mean, self.min, self.max, self.stdev)
+    }
+
+    fn keywords(&self, prefix: &str) -> Vec<(String, String)> {
+        vec![
+            (format!("{}_SAMPLE_COUNT", prefix), self.sample_count.to_string()),
+            (format!("{}_MEAN", prefix), self.mean.to_string()),
+            (format!("{}_MIN", prefix), self.min.to_string()),
+            (format!("{}_MAX", prefix), self.max.to_string()),
+            (format!("{}_STDEV", prefix), self.stdev.to_string()),
+        ]
+    }
 }
 
 impl fmt::Display for DeSummary {
@@ -87,7 +479,7 @@ impl From<&DeList> for DeSummary {
 
 #[derive(Debug)]
 pub struct DeList {
-    de_method: DEMethod,
+    formula: DeltaEFormula,
     list: Vec<Float>,
 }
 
@@ -97,31 +489,32 @@ impl DeList {
     }
 
     fn min(&self) -> Float {
-        *self.list.iter().min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap()
+        *self.list.iter().min_by(|a, b| a.total_cmp(b)).unwrap()
     }
 
     fn max(&self) -> Float {
-        *self.list.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap()
+        *self.list.iter().max_by(|a, b| a.total_cmp(b)).unwrap()
     }
 
     fn stdev(&self) -> Float {
         if self.list.len() <= 1 {
             0.0
         } else {
-            standard_deviation(&self.list.as_slice(), None)
+            standard_deviation(self.list.as_slice(), None)
         }
     }
 
-    fn split_pct(&mut self, pct: Float) -> [DeList; 2] {
-        if pct <= 0.0 || pct >= 1.0 {
-            panic!("Split must be between 0.0 and 1.0");
+    fn split_pct(&mut self, pct: Float) -> Option<[DeList; 2]> {
+    // Returns None if the split is not between 0.0 and 1.0 or there are fewer than 2 samples
+        if pct <= 0.0 || pct >= 1.0 || self.list.len() < 2 {
+            return None;
         }
 
-        let de_method = self.de_method;
+        let formula = self.formula;
+
+        self.list.sort_by(|a, b| a.total_cmp(b));
 
-        self.list.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        
-        let mut split_index = ((self.list.len() as Float) * pct) as usize; 
+        let mut split_index = ((self.list.len() as Float) * pct) as usize;
         if split_index == 0 {
             split_index = 1;
         } else if split_index == self.list.len() {
@@ -130,16 +523,16 @@ impl DeList {
 
         let (best_90, worst_10) = self.list.split_at(split_index);
 
-        [
+        Some([
             DeList {
-                de_method,
+                formula,
                 list: best_90.to_vec(),
             },
             DeList {
-                de_method,
+                formula,
                 list: worst_10.to_vec(),
             }
-        ]
+        ])
     }
 }
 
@@ -147,30 +540,237 @@ impl std::convert::TryFrom<&Cgats> for DeList {
     type Error = Error;
     fn try_from(cgats: &Cgats) -> std::result::Result<DeList, Self::Error> {
 
-        let (method_index, de_method) = cgats.de_method()?;
+        let (method_index, formula) = cgats.de_formula()?;
         let list = cgats.data_map.values()
             .filter_map(|sample| sample.values.get(method_index))
             .filter_map(|cgv| cgv.float)
             .collect();
-        
+
         Ok(
             DeList {
-                de_method,
+                formula,
                 list,
             }
         )
     }
 }
 
+fn percentile(sorted: &[Float], percent: Float) -> Float {
+//! Linear interpolation between the closest ranks of a sorted list
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (percent / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as Float;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as Float)
+}
+
+fn percentile_name(percent: Float) -> String {
+    if percent == 50.0 {
+        "Median".to_string()
+    } else {
+        format!("P{}", percent)
+    }
+}
+
+fn keyword_number(value: Float) -> String {
+//! A number as part of a keyword name, which may not contain a dot: 95.5 is "95P5"
+    value.to_string().replace('.', "P")
+}
+
+fn histogram(sorted: &[Float], edges: &[Float]) -> Vec<HistogramBin> {
+//! Count the samples in each bin of sorted edges. The first bin starts at 0, the last is open-ended.
+    if edges.is_empty() {
+        return Vec::new();
+    }
+
+    let count = |min: Float, max: Option<Float>| sorted.iter()
+        .filter(|de| **de >= min && max.is_none_or(|max| **de < max))
+        .count();
+
+    let mut bins = Vec::with_capacity(edges.len() + 1);
+    let mut min = 0.0;
+    for edge in edges.iter().copied() {
+        bins.push(HistogramBin { min, max: Some(edge), count: count(min, Some(edge)) });
+        min = edge;
+    }
+    bins.push(HistogramBin { min, max: None, count: count(min, None) });
+
+    bins
+}
+
+fn json_float(value: Float) -> String {
+    if value.is_finite() { value.to_string() } else { "null".to_string() }
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"'  => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn csv_value(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn text_value(cgats: &Cgats, key: usize, field: &Field) -> Option<String> {
+    let index = cgats.field_index(field)?;
+    let value = cgats.data_map.get(&key)?.values.get(index)?;
+    Some(value.string.clone())
+}
+
 #[test]
 fn de_report() -> Result<()> {
     let cg0 = Cgats::from_file("test_files/colorburst2.lin")?;
     let cg1 = Cgats::from_file("test_files/colorburst3.lin")?;
-    let cgd = cg0.deltae(cg1, DEMethod::DE2000)?;
+    let cgd = cg0.deltae(cg1, deltae::DEMethod::DE2000)?;
 
     let report = DeReport::new(&cgd)?;
 
     println!("{}", report);
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn de_report_options() -> Result<()> {
+    let cg0 = Cgats::from_file("test_files/colorburst2.lin")?;
+    let cg1 = Cgats::from_file("test_files/colorburst3.lin")?;
+    let cgd = cg0.clone().deltae(cg1.clone(), deltae::DEMethod::DE2000)?;
+
+    let options = DeReportOptions::new()
+        .percentiles(&[50.0, 95.0, 99.0])
+        .splits(&[50.0, 95.0])
+        .histogram(&[1.0, 2.0, 4.0, 6.0])
+        .worst(5);
+    let report = DeReport::with_options(&cgd, &options)?.with_samples(&cg0, &cg1);
+    println!("{}", report);
+
+    assert_eq!(report.splits().len(), 2);
+    assert_eq!(report.splits()[1].worst.sample_count, 7);
+
+    let median = report.percentile(50.0);
+    assert!(median >= report.overall().min && median <= report.overall().max);
+    assert!(report.percentile(95.0) <= report.percentile(99.0));
+    assert_eq!(report.percentile(100.0), report.overall().max);
+
+    assert_eq!(report.histogram().len(), 5);
+    assert_eq!(report.histogram().iter().map(|bin| bin.count).sum::<usize>(), 126);
+
+    let worst = report.worst();
+    assert_eq!(worst.len(), 5);
+    assert_eq!(worst[0].de, report.overall().max);
+    assert!(worst.windows(2).all(|pair| pair[0].de >= pair[1].de));
+    assert_eq!(worst[0].reference, cg0.sample_lab(worst[0].key));
+    assert_eq!(worst[0].sample, cg1.sample_lab(worst[0].key));
+
+    assert!(DeReport::with_options(&cgd, &DeReportOptions::new().splits(&[100.0])).is_err());
+    assert!(DeReport::with_options(&cgd, &DeReportOptions::new().percentiles(&[101.0])).is_err());
+    assert!(DeReport::with_options(&cgd, &DeReportOptions::new().histogram(&[1.0, Float::NAN])).is_err());
+    assert!(DeReport::with_options(&cgd, &DeReportOptions::new().histogram(&[-1.0, 2.0])).is_err());
+    assert!(DeReport::with_options(&cgd, &DeReportOptions::new().histogram(&[Float::INFINITY])).is_err());
+
+    // Unsorted and repeated edges give the same bins as sorted ones
+    let unsorted = DeReport::with_options(&cgd, &DeReportOptions::new().histogram(&[4.0, 1.0, 2.0, 1.0, 6.0]))?;
+    assert_eq!(unsorted.histogram(), report.histogram());
+
+    Ok(())
+}
+
+#[test]
+fn de_report_single_sample() -> Result<()> {
+    let cg0 = Cgats::from_file("test_files/colorburst2.lin")?;
+    let cg1 = Cgats::from_file("test_files/colorburst3.lin")?;
+    let mut cgd = cg0.deltae(cg1, deltae::DEMethod::DE2000)?;
+    cgd.data_map.retain(|key, _| *key == 0);
+
+    let report = DeReport::with_options(&cgd, &DeReportOptions::new().splits(&[10.0, 90.0]))?;
+    println!("{}", report);
+
+    assert_eq!(report.overall().sample_count, 1);
+    assert_eq!(report.overall().min, report.overall().max);
+    assert!(report.splits().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn de_report_formats() -> Result<()> {
+    let cg0 = Cgats::from_file("test_files/colorburst2.lin")?;
+    let cg1 = Cgats::from_file("test_files/colorburst3.lin")?;
+    let cgd = cg0.clone().deltae(cg1.clone(), deltae::DEMethod::DE2000)?;
+
+    let options = DeReportOptions::new().percentiles(&[50.0, 95.0]).histogram(&[1.0, 2.0]).worst(2);
+    let report = DeReport::with_options(&cgd, &options)?.with_samples(&cg0, &cg1);
+
+    assert_eq!(report.format(ReportFormat::Text), report.to_string());
+    assert_eq!(ReportFormat::from_str("JSON")?, ReportFormat::Json);
+    assert!(ReportFormat::from_str("xml").is_err());
+
+    let json = report.to_json();
+    println!("{}", json);
+    assert!(json.starts_with("{\"formula\":\"DE2000\",\"overall\":{\"sample_count\":126,"));
+    assert!(json.contains("\"histogram\":[{\"min\":0,\"max\":1,\"count\":8},"));
+    assert!(json.contains("{\"min\":2,\"max\":null,"));
+    assert_eq!(json.matches("\"key\":").count(), 2);
+    assert_eq!(json_string("a \"b\"\\"), "\"a \\\"b\\\"\\\\\"");
+
+    let csv = report.to_csv();
+    println!("{}", csv);
+    assert!(csv.starts_with("STATISTIC,VALUE\nDE_FORMULA,DE2000\nDE_SAMPLE_COUNT,126\n"));
+    assert!(csv.contains(&format!("DE_P95,{}\n", report.percentile(95.0))));
+    assert!(csv.contains("DE_HISTOGRAM_2_UP,"));
+    assert!(csv.contains("WORST_SAMPLE_2_DE,"));
+
+    let cgats = report.to_cgats();
+    let temp = test::mktemp()?;
+    cgats.write_to_file(&temp)?;
+    let reconstructed = Cgats::from_file(&temp)?;
+    assert_eq!(reconstructed.sample_count(), cgd.sample_count());
+    assert_eq!(reconstructed.de_formula()?.1, DeltaEFormula::default());
+    assert!((DeReport::new(&reconstructed)?.overall().mean - report.overall().mean).abs() < 0.001);
+    assert!(reconstructed.format().contains("DE_FORMULA\t\"DE2000\"\nDE_SAMPLE_COUNT\t126\n"));
+
+    std::fs::remove_file(temp)?;
+    Ok(())
+}
+
+#[test]
+fn de_report_keywords() -> Result<()> {
+    let cg0 = Cgats::from_file("test_files/colorburst2.lin")?;
+    let cg1 = Cgats::from_file("test_files/colorburst3.lin")?;
+    let cgd = cg0.deltae(cg1, deltae::DEMethod::DE2000)?;
+
+    let options = DeReportOptions::new().splits(&[95.5]).percentiles(&[50.0, 99.5]).histogram(&[1.5, 2.0]);
+    let report = DeReport::with_options(&cgd, &options)?;
+    let keywords = report.keywords();
+
+    let names: Vec<&str> = keywords.iter().map(|(name, _)| name.as_str()).collect();
+    assert!(names.iter().all(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')));
+    for name in &["BEST_95P5_DE_MEAN", "WORST_4P5_DE_MAX", "DE_MEDIAN", "DE_P99P5", "DE_HISTOGRAM_0_1P5", "DE_HISTOGRAM_1P5_2", "DE_HISTOGRAM_2_UP"] {
+        assert!(names.contains(name), "{} is missing", name);
+    }
+
+    // The CGATS output reads back with the same keywords and values
+    let reconstructed = Cgats::from_reader(report.to_cgats().format().as_bytes())?;
+    for (name, value) in &keywords {
+        assert_eq!(reconstructed.keyword(name).as_ref(), Some(value), "{}", name);
+    }
+
+    Ok(())
+}
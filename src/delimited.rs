@@ -0,0 +1,258 @@
+use super::*;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+// Spreadsheet column headers that are not CGATS field names
+const HEADER_ALIASES: &[(&str, Field)] = &[
+    ("ID", Field::SAMPLE_ID),
+    ("NAME", Field::SAMPLE_NAME),
+    ("L*", Field::LAB_L),
+    ("A*", Field::LAB_A),
+    ("B*", Field::LAB_B),
+    ("C*", Field::LAB_C),
+    ("H", Field::LAB_H),
+    ("H*", Field::LAB_H),
+    ("C", Field::CMYK_C),
+    ("M", Field::CMYK_M),
+    ("Y", Field::CMYK_Y),
+    ("K", Field::CMYK_K),
+    ("CYAN", Field::CMYK_C),
+    ("MAGENTA", Field::CMYK_M),
+    ("YELLOW", Field::CMYK_Y),
+    ("BLACK", Field::CMYK_K),
+];
+
+/// Separator and decimal mark of a CSV or TSV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Delimiter {
+    separator: char,
+    decimal_comma: bool,
+}
+
+impl Delimiter {
+    pub fn new(separator: char) -> Delimiter {
+        Delimiter { separator, decimal_comma: false }
+    }
+
+    pub fn comma() -> Delimiter {
+        Delimiter::new(',')
+    }
+
+    pub fn semicolon() -> Delimiter {
+        Delimiter::new(';')
+    }
+
+    pub fn tab() -> Delimiter {
+        Delimiter::new('\t')
+    }
+
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> Delimiter {
+    //! Write numbers with a decimal comma. A comma separator becomes a
+    //! semicolon so that the numbers stay in one column.
+        self.decimal_comma = decimal_comma;
+        if decimal_comma && self.separator == ',' {
+            self.separator = ';';
+        }
+        self
+    }
+
+    pub fn separator(&self) -> char {
+        self.separator
+    }
+
+    fn detect(header: &str) -> Delimiter {
+        ['\t', ';', ',']
+            .iter()
+            .max_by_key(|sep| split_row(header, **sep).len())
+            .map_or_else(Delimiter::tab, |sep| Delimiter::new(*sep))
+    }
+}
+
+impl Default for Delimiter {
+    fn default() -> Delimiter {
+        Delimiter::comma()
+    }
+}
+
+impl Cgats {
+    pub fn from_delimited_file<P: AsRef<Path>>(path: P) -> Result<Cgats> {
+    //! Read a CSV or TSV file with a header row and no CGATS tags
+        Cgats::from_delimited_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_delimited_reader<R: BufRead>(reader: R) -> Result<Cgats> {
+    //! Read CSV or TSV text. The first row is the DATA_FORMAT, the separator
+    //! (tab, semicolon or comma) is taken from it, and decimal commas are accepted.
+        let mut lines = reader.lines()
+            .map(|line| line.map(|l| l.trim_end_matches(&['\r', '\n'][..]).to_string()))
+            .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()));
+
+        let header = lines.next().ok_or(Error::EmptyFile)??;
+        let delimiter = Delimiter::detect(&header);
+
+        let mut cgats = Cgats::new();
+        cgats.meta.insert(0, "CGATS.17");
+        cgats.fields = split_row(&header, delimiter.separator).iter()
+            .map(|column| header_field(column))
+            .collect::<Result<DataFormat>>()?;
+
+        for (key, line) in lines.enumerate() {
+            let cells = split_row(&line?, delimiter.separator);
+            if cells.len() > cgats.fields.len() {
+                return Err(Error::FormatDataMismatch);
+            }
+
+            let values = (0..cgats.fields.len())
+                .map(|i| cells.get(i)
+                    .and_then(|cell| CgatsValue::from_str(&decimal_point(cell)).ok())
+                    .unwrap_or_default())
+                .collect();
+            cgats.data_map.insert(key, Sample { values });
+        }
+
+        if cgats.data_map.is_empty() {
+            return Err(Error::NoData);
+        }
+
+        Ok(cgats)
+    }
+
+    pub fn to_delimited(&self, delimiter: Delimiter) -> String {
+    //! Format the DATA_FORMAT as a header row followed by one row per sample
+        let sep = delimiter.separator.to_string();
+
+        let mut s = self.fields.iter()
+            .map(|field| quote_cell(&field.to_string(), delimiter.separator))
+            .collect::<Vec<String>>()
+            .join(&sep);
+        s.push('\n');
+
+        for sample in self.data_map.values() {
+            let row = sample.values.iter()
+                .map(|value| {
+                    let mut cell = value.to_string();
+                    if delimiter.decimal_comma && value.float.is_some() {
+                        cell = cell.replace('.', ",");
+                    }
+                    quote_cell(&cell, delimiter.separator)
+                })
+                .collect::<Vec<String>>()
+                .join(&sep);
+            s.push_str(&row);
+            s.push('\n');
+        }
+
+        s
+    }
+
+    pub fn write_delimited_file<P: AsRef<Path>>(&self, path: P, delimiter: Delimiter) -> Result<()> {
+    //! Write the data to a CSV or TSV file
+        let mut buf = BufWriter::new(File::create(path)?);
+        write!(buf, "{}", self.to_delimited(delimiter))?;
+        Ok(())
+    }
+}
+
+fn header_field(column: &str) -> Result<Field> {
+    let upper = column.trim().to_uppercase();
+    HEADER_ALIASES.iter()
+        .find(|(alias, _)| *alias == upper)
+        .map(|(_, field)| Ok(*field))
+        .unwrap_or_else(|| Field::from_str(&upper))
+        .map_err(|_| Error::Other(format!("Unknown column header: '{}'", column.trim())))
+}
+
+fn split_row(line: &str, separator: char) -> Vec<String> {
+    // Separators inside double quotes belong to the cell; "" is an escaped quote
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+
+    cells
+}
+
+fn decimal_point(cell: &str) -> String {
+    // "12,5" is a decimal comma when the rest of the cell is a number
+    let candidate = cell.replacen(',', ".", 1);
+    if cell.matches(',').count() == 1 && candidate.parse::<Float>().is_ok() {
+        candidate
+    } else {
+        cell.to_string()
+    }
+}
+
+fn quote_cell(cell: &str, separator: char) -> String {
+    if cell.contains(separator) || cell.contains('"') {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[test]
+fn tsv_header_row() -> Result<()> {
+    let tsv = Cgats::from_delimited_file("test_files/cgats_format.tsv")?;
+    let cgats = Cgats::from_file("test_files/cgats1.tsv")?;
+
+    assert_eq!(tsv.fields, cgats.fields);
+    assert_eq!(tsv.data_map, cgats.data_map);
+
+    Ok(())
+}
+
+#[test]
+fn csv_aliases_and_decimal_comma() -> Result<()> {
+    let csv = "Name;C;M;Y;K;L*;a*;b*\n\
+        Cyan;100;0;0;0;55,2;-37,5;-50\n\
+        \"Red; warm\";0;100;100;0;47,9;68,1;48,4\n";
+    let cgats = Cgats::from_delimited_reader(csv.as_bytes())?;
+
+    assert_eq!(cgats.fields, vec![
+        Field::SAMPLE_NAME, Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K,
+        Field::LAB_L, Field::LAB_A, Field::LAB_B,
+    ]);
+    assert_eq!(cgats.get_float(0, &Field::LAB_L), Some(55.2));
+    assert_eq!(cgats.get_float(1, &Field::LAB_A), Some(68.1));
+    assert_eq!(cgats.data_map[&1].values[0].string, "Red; warm");
+
+    let comma = Cgats::from_delimited_reader("SAMPLE_ID,LAB_L\n1,\"50,5\"\n".as_bytes())?;
+    assert_eq!(comma.get_float(0, &Field::LAB_L), Some(50.5));
+
+    assert!(Cgats::from_delimited_reader("Name,Foo\nA,1\n".as_bytes()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn delimited_roundtrip() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/colorburst0.txt")?;
+
+    for delimiter in &[Delimiter::comma(), Delimiter::tab(), Delimiter::comma().decimal_comma(true)] {
+        let text = cgats.to_delimited(*delimiter);
+        let reread = Cgats::from_delimited_reader(text.as_bytes())?;
+        assert_eq!(reread.fields, cgats.fields);
+        assert_eq!(reread.sample_count(), cgats.sample_count());
+        assert_eq!(reread.get_float(5, &Field::LAB_A), cgats.get_float(5, &Field::LAB_A));
+    }
+
+    assert!(cgats.to_delimited(Delimiter::comma().decimal_comma(true)).starts_with("D_RED;D_GREEN;"));
+
+    Ok(())
+}
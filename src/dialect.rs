@@ -0,0 +1,338 @@
+use super::*;
+
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// The rules of a vendor's CGATS flavour: how to recognize its files, which
+/// DATA_FORMAT is implied when a file has none, how to fix up data after
+/// reading it, and how to write it back.
+///
+/// Register your own dialects with `DialectRegistry::register_global`, or read
+/// with a registry of your own with `Cgats::from_reader_with_registry`.
+pub trait Dialect: Send + Sync {
+    /// The vendor that files of this dialect are tagged with
+    fn vendor(&self) -> Vendor;
+
+    /// Lowercase words that name the vendor in the first line or the ORIGINATOR
+    fn keywords(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Recognize a file by its first line
+    fn detect(&self, raw: &DataVec) -> bool {
+        let first = raw.lines.first()
+            .map(|line| line.raw_samples.join(" ").to_lowercase())
+            .unwrap_or_default();
+        self.keywords().iter().any(|keyword| first.contains(keyword))
+    }
+
+    /// Recognize a file by its header keywords. This is only tried when no
+    /// dialect claims the first line, e.g. for a plain CGATS.17 identifier.
+    fn detect_header(&self, raw: &DataVec) -> bool {
+        raw.lines.iter()
+            .take_while(|line| line.keyword() != "BEGIN_DATA_FORMAT")
+            .filter(|line| line.keyword() == "ORIGINATOR")
+            .any(|line| {
+                let originator = line.value().to_lowercase();
+                self.keywords().iter().any(|keyword| originator.contains(keyword))
+            })
+    }
+
+    /// The DATA_FORMAT of files that do not include one
+    fn implicit_fields(&self) -> Option<DataFormat> {
+        None
+    }
+
+    /// Adjust a CGATS object after it has been read
+    fn read_fixup(&self, _cgats: &mut Cgats) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the DATA_FORMAT section is written
+    fn write_data_format(&self) -> bool {
+        true
+    }
+
+    /// Whether tables after the first hold auxiliary data that may be skipped
+    /// when reading the file as one table, e.g. the Argyll calibration table
+    fn auxiliary_tables(&self) -> bool {
+        false
+    }
+}
+
+/// ColorBurst linearization files: no DATA_FORMAT, density and LAB data
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ColorBurstDialect;
+
+impl Dialect for ColorBurstDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::ColorBurst
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["colorburst"]
+    }
+
+    fn implicit_fields(&self) -> Option<DataFormat> {
+        Some(field::ColorBurstFormat())
+    }
+
+    fn write_data_format(&self) -> bool {
+        false
+    }
+}
+
+/// Curve3/Curve4 calibration files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurveDialect;
+
+impl Dialect for CurveDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::Curve
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["curve"]
+    }
+}
+
+/// Argyll CMS .ti1/.ti2/.ti3 files (see `ArgyllTarget` for all their tables)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArgyllDialect;
+
+impl Dialect for ArgyllDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::Argyll
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["argyll", "cti1", "cti2", "cti3"]
+    }
+
+    fn read_fixup(&self, cgats: &mut Cgats) -> Result<()> {
+        // Declarations only matter to Argyll's own parser
+        cgats.meta.lines.retain(|line| line.keyword() != "KEYWORD");
+        Ok(())
+    }
+
+    fn auxiliary_tables(&self) -> bool {
+        true
+    }
+}
+
+/// X-Rite i1Profiler and ColorPort exports
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XRiteDialect;
+
+impl Dialect for XRiteDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::XRite
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["i1profiler", "colorport", "x-rite", "xrite"]
+    }
+}
+
+/// Barbieri Gateway exports with BARBIERI_INFO_* keywords
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BarbieriDialect;
+
+impl Dialect for BarbieriDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::Barbieri
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["barbieri"]
+    }
+
+    fn detect_header(&self, raw: &DataVec) -> bool {
+        raw.lines.iter()
+            .take_while(|line| line.keyword() != "BEGIN_DATA_FORMAT")
+            .any(|line| line.keyword().starts_with("BARBIERI_") ||
+                (line.keyword() == "ORIGINATOR" && line.value().to_lowercase().contains("barbieri")))
+    }
+}
+
+/// Konica Minolta exports
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KonicaMinoltaDialect;
+
+impl Dialect for KonicaMinoltaDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::KonicaMinolta
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["konica", "minolta"]
+    }
+}
+
+/// Techkon exports with TECHKON_* keywords
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TechkonDialect;
+
+impl Dialect for TechkonDialect {
+    fn vendor(&self) -> Vendor {
+        Vendor::Techkon
+    }
+
+    fn keywords(&self) -> &[&str] {
+        &["techkon"]
+    }
+}
+
+/// An ordered list of dialects. User dialects are tried before the built-in ones.
+#[derive(Clone)]
+pub struct DialectRegistry {
+    dialects: Vec<Arc<dyn Dialect>>,
+}
+
+static GLOBAL: OnceLock<RwLock<Arc<DialectRegistry>>> = OnceLock::new();
+
+impl DialectRegistry {
+    pub fn empty() -> DialectRegistry {
+        DialectRegistry { dialects: Vec::new() }
+    }
+
+    pub fn builtin() -> DialectRegistry {
+    //! The dialects that ship with this crate
+        DialectRegistry {
+            dialects: vec![
+                Arc::new(ArgyllDialect),
+                Arc::new(ColorBurstDialect),
+                Arc::new(CurveDialect),
+                Arc::new(XRiteDialect),
+                Arc::new(BarbieriDialect),
+                Arc::new(KonicaMinoltaDialect),
+                Arc::new(TechkonDialect),
+            ],
+        }
+    }
+
+    pub fn register<D: Dialect + 'static>(&mut self, dialect: D) {
+    //! Add a dialect that takes precedence over those already registered
+        self.dialects.insert(0, Arc::new(dialect));
+    }
+
+    pub fn dialects(&self) -> &[Arc<dyn Dialect>] {
+        &self.dialects
+    }
+
+    pub fn detect(&self, raw: &DataVec) -> Option<Arc<dyn Dialect>> {
+    //! The dialect that claims the first line, or else the header keywords
+        self.dialects.iter()
+            .find(|dialect| dialect.detect(raw))
+            .or_else(|| self.dialects.iter().find(|dialect| dialect.detect_header(raw)))
+            .cloned()
+    }
+
+    pub fn find(&self, vendor: &Vendor) -> Option<Arc<dyn Dialect>> {
+    //! The dialect of a vendor
+        self.dialects.iter()
+            .find(|dialect| dialect.vendor() == *vendor)
+            .cloned()
+    }
+
+    pub fn find_name(&self, name: &str) -> Option<Arc<dyn Dialect>> {
+    //! The dialect whose keywords appear in a vendor name
+        let name = name.to_lowercase();
+        self.dialects.iter()
+            .find(|dialect| dialect.keywords().iter().any(|keyword| name.contains(keyword)))
+            .cloned()
+    }
+
+    pub fn global() -> Arc<DialectRegistry> {
+    //! A snapshot of the registry used when reading and writing files
+        GLOBAL.get_or_init(|| RwLock::new(Arc::new(DialectRegistry::builtin())))
+            .read()
+            .expect("Dialect registry lock is poisoned!")
+            .clone()
+    }
+
+    pub fn register_global<D: Dialect + 'static>(dialect: D) {
+    //! Add a dialect to the registry used when reading and writing files.
+    //! Snapshots taken before keep the dialects they had.
+        let mut global = GLOBAL.get_or_init(|| RwLock::new(Arc::new(DialectRegistry::builtin())))
+            .write()
+            .expect("Dialect registry lock is poisoned!");
+        Arc::make_mut(&mut global).register(dialect)
+    }
+}
+
+impl Default for DialectRegistry {
+    fn default() -> DialectRegistry {
+        DialectRegistry::builtin()
+    }
+}
+
+impl std::fmt::Debug for DialectRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.dialects.iter().map(|dialect| dialect.vendor()))
+            .finish()
+    }
+}
+
+#[test]
+fn builtin_detection() -> Result<()> {
+    let registry = DialectRegistry::builtin();
+    let vendor = |file| -> Result<Option<Vendor>> {
+        Ok(registry.detect(&DataVec::from_file(file)?).map(|dialect| dialect.vendor()))
+    };
+
+    assert_eq!(vendor("test_files/colorburst0.txt")?, Some(Vendor::ColorBurst));
+    assert_eq!(vendor("test_files/curve0.txt")?, Some(Vendor::Curve));
+    assert_eq!(vendor("test_files/argyll0.ti3")?, Some(Vendor::Argyll));
+    assert_eq!(vendor("test_files/cgats0.txt")?, Some(Vendor::Barbieri));
+    assert_eq!(vendor("test_files/cgats1.tsv")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn user_dialect() -> Result<()> {
+    struct InHouse;
+
+    impl Dialect for InHouse {
+        fn vendor(&self) -> Vendor {
+            Vendor::Other("InHouse".to_string())
+        }
+
+        fn keywords(&self) -> &[&str] {
+            &["inhouse lab"]
+        }
+
+        fn implicit_fields(&self) -> Option<DataFormat> {
+            Some(vec![Field::SAMPLE_ID, Field::LAB_L, Field::LAB_A, Field::LAB_B])
+        }
+
+        fn read_fixup(&self, cgats: &mut Cgats) -> Result<()> {
+            cgats.set_keyword("ORIGINATOR", "InHouse");
+            Ok(())
+        }
+
+        fn write_data_format(&self) -> bool {
+            false
+        }
+    }
+
+    let mut registry = DialectRegistry::builtin();
+    registry.register(InHouse);
+
+    let text = "InHouse LAB\nBEGIN_DATA\n1\t95\t0.5\t-2\n2\t50\t60\t40\nEND_DATA\n";
+    let cgats = Cgats::from_reader_with_registry(text.as_bytes(), &registry)?;
+
+    assert_eq!(cgats.vendor, Vendor::Other("InHouse".to_string()));
+    assert_eq!(cgats.fields.len(), 4);
+    assert_eq!(cgats.get_float(1, &Field::LAB_A), Some(60.0));
+    assert_eq!(cgats.keyword("ORIGINATOR"), Some("InHouse".to_string()));
+    assert!(!cgats.format().contains("BEGIN_DATA_FORMAT"));
+    assert_eq!(Vendor::from_name("inhouse lab v2", &registry)?, Vendor::Other("InHouse".to_string()));
+
+    // The global registry does not know the dialect
+    assert!(Cgats::from_reader(text.as_bytes()).is_err());
+    assert_eq!("inhouse lab v2".parse::<Vendor>()?, Vendor::Other("inhouse lab v2".to_string()));
+
+    Ok(())
+}
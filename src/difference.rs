@@ -0,0 +1,350 @@
+use super::*;
+
+use deltae::color::LabValue;
+use deltae::{DeltaE, DEMethod};
+use std::fmt;
+use std::str::FromStr;
+
+/// A color difference formula: the `deltae` methods, CIEDE2000 with
+/// parametric factors, ΔE ITP (ITU-R BT.2124) and ΔE in CAM16-UCS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeltaEFormula {
+    De1976,
+    De1994,
+    De1994T,
+    DeCmc1,
+    DeCmc2,
+    De2000 { kl: Float, kc: Float, kh: Float },
+    Itp,
+    Cam16Ucs,
+}
+
+impl Default for DeltaEFormula {
+    fn default() -> DeltaEFormula {
+        DeltaEFormula::DE2000
+    }
+}
+
+impl DeltaEFormula {
+    /// CIEDE2000 with all parametric factors at 1
+    pub const DE2000: DeltaEFormula = DeltaEFormula::De2000 { kl: 1.0, kc: 1.0, kh: 1.0 };
+
+    pub fn delta_e(&self, reference: &LabValue, sample: &LabValue) -> Float {
+    //! The color difference from a reference to a sample
+        match self {
+            DeltaEFormula::De2000 { kl, kc, kh } if self.is_parametric() =>
+                Ciede2000::weighted(reference, sample, [*kl, *kc, *kh]).value(),
+            DeltaEFormula::Itp => {
+                let (r, s) = (color::lab_to_itp(reference), color::lab_to_itp(sample));
+                720.0 * distance(r, s)
+            },
+            DeltaEFormula::Cam16Ucs => {
+                distance(color::lab_to_cam16_ucs(reference), color::lab_to_cam16_ucs(sample))
+            },
+            _ => DeltaE::new(reference, sample, self.to_de_method().expect("Formula is a DEMethod!")).value,
+        }
+    }
+
+    pub fn field(&self) -> Field {
+    //! The DATA_FORMAT field that holds this difference
+        match self {
+            DeltaEFormula::De1976   => Field::DE_1976,
+            DeltaEFormula::De1994   => Field::DE_1994,
+            DeltaEFormula::De1994T  => Field::DE_1994T,
+            DeltaEFormula::DeCmc1   => Field::DE_CMC,
+            DeltaEFormula::DeCmc2   => Field::DE_CMC2,
+            DeltaEFormula::De2000 { .. } => Field::DE_2000,
+            DeltaEFormula::Itp      => Field::DE_ITP,
+            DeltaEFormula::Cam16Ucs => Field::DE_CAM16UCS,
+        }
+    }
+
+    pub fn to_de_method(self) -> Option<DEMethod> {
+    //! The equivalent `deltae` method, if there is one
+        match self {
+            DeltaEFormula::De1976  => Some(DEMethod::DE1976),
+            DeltaEFormula::De1994  => Some(DEMethod::DE1994),
+            DeltaEFormula::De1994T => Some(DEMethod::DE1994T),
+            DeltaEFormula::DeCmc1  => Some(DEMethod::DECMC1),
+            DeltaEFormula::DeCmc2  => Some(DEMethod::DECMC2),
+            DeltaEFormula::De2000 { kl, kc, kh } if [kl, kc, kh] == [1.0; 3] => Some(DEMethod::DE2000),
+            _ => None,
+        }
+    }
+
+    pub fn factors(&self) -> [Float; 3] {
+    //! The CIEDE2000 parametric factors kL, kC and kH (all 1 for other formulas)
+        match self {
+            DeltaEFormula::De2000 { kl, kc, kh } => [*kl, *kc, *kh],
+            _ => [1.0; 3],
+        }
+    }
+
+    pub fn is_parametric(&self) -> bool {
+    //! Test if this is CIEDE2000 with factors other than 1:1:1
+        self.factors() != [1.0; 3]
+    }
+}
+
+impl From<DEMethod> for DeltaEFormula {
+    fn from(method: DEMethod) -> DeltaEFormula {
+        match method {
+            DEMethod::DE1976  => DeltaEFormula::De1976,
+            DEMethod::DE1994  => DeltaEFormula::De1994,
+            DEMethod::DE1994T => DeltaEFormula::De1994T,
+            DEMethod::DECMC1  => DeltaEFormula::DeCmc1,
+            DEMethod::DECMC2  => DeltaEFormula::DeCmc2,
+            DEMethod::DE2000  => DeltaEFormula::DE2000,
+        }
+    }
+}
+
+impl FromStr for DeltaEFormula {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DeltaEFormula> {
+    //! Parse a formula name. CIEDE2000 factors follow the name: `2000:2:1:1` is kL=2, kC=1, kH=1
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default().trim().to_lowercase().replace(['_', '-', ' '], "");
+        let factors = parts
+            .map(|factor| factor.trim().parse::<Float>()
+                .map_err(|_| Error::Other(format!("Invalid parametric factor: '{}'", factor))))
+            .collect::<Result<Vec<Float>>>()?;
+
+        let formula = match name.strip_prefix("de").unwrap_or(&name) {
+            "1976" | "76" => DeltaEFormula::De1976,
+            "1994" | "94" | "1994g" | "94g" => DeltaEFormula::De1994,
+            "1994t" | "94t" => DeltaEFormula::De1994T,
+            "cmc" | "cmc1" => DeltaEFormula::DeCmc1,
+            "cmc2" => DeltaEFormula::DeCmc2,
+            "2000" | "00" => DeltaEFormula::DE2000,
+            "itp" => DeltaEFormula::Itp,
+            "cam16" | "cam16ucs" => DeltaEFormula::Cam16Ucs,
+            _ => return Err(Error::Other(format!("Unknown Delta E formula: '{}'", s))),
+        };
+
+        match (formula, factors.as_slice()) {
+            (_, []) => Ok(formula),
+            (DeltaEFormula::De2000 { .. }, [kl, kc, kh]) if [kl, kc, kh].iter().all(|k| **k > 0.0) =>
+                Ok(DeltaEFormula::De2000 { kl: *kl, kc: *kc, kh: *kh }),
+            _ => Err(Error::Other(format!("Expected DE2000 with 3 positive factors (kL:kC:kH): '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for DeltaEFormula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeltaEFormula::De2000 { kl, kc, kh } if self.is_parametric() =>
+                write!(f, "DE2000 (kL={}, kC={}, kH={})", kl, kc, kh),
+            DeltaEFormula::Itp => write!(f, "DEITP"),
+            DeltaEFormula::Cam16Ucs => write!(f, "DECAM16UCS"),
+            _ => write!(f, "{}", self.to_de_method().expect("Formula is a DEMethod!")),
+        }
+    }
+}
+
+/// The weighted lightness, chroma and hue terms of CIEDE2000.
+/// The color difference is `sqrt(l² + c² + h² + rt)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ciede2000 {
+    /// ΔL' / (kL·SL)
+    pub l: Float,
+    /// ΔC' / (kC·SC)
+    pub c: Float,
+    /// ΔH' / (kH·SH)
+    pub h: Float,
+    /// The rotation term RT·(ΔC' / kC·SC)·(ΔH' / kH·SH)
+    pub rt: Float,
+}
+
+impl Ciede2000 {
+    pub fn new(reference: &LabValue, sample: &LabValue) -> Ciede2000 {
+    //! Decompose the CIEDE2000 difference from a reference to a sample
+        Ciede2000::weighted(reference, sample, [1.0; 3])
+    }
+
+    pub fn weighted(reference: &LabValue, sample: &LabValue, factors: [Float; 3]) -> Ciede2000 {
+    //! Decompose the CIEDE2000 difference with the parametric factors kL, kC and kH
+        let (l1, a1, b1) = (reference.l as f64, reference.a as f64, reference.b as f64);
+        let (l2, a2, b2) = (sample.l as f64, sample.a as f64, sample.b as f64);
+
+        let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+        let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt());
+
+        let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+        let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+        let (h1, h2) = (hue_angle(a1, b1), hue_angle(a2, b2));
+
+        let delta_l = l2 - l1;
+        let delta_c = c2 - c1;
+        let delta_h = 2.0 * (c1 * c2).sqrt() * (hue_difference(c1, c2, h1, h2) / 2.0).to_radians().sin();
+
+        let l_bar = (l1 + l2) / 2.0;
+        let c_bar = (c1 + c2) / 2.0;
+        let h_bar = if c1 * c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+
+        let t = 1.0
+            - 0.17 * (h_bar - 30.0).to_radians().cos()
+            + 0.24 * (2.0 * h_bar).to_radians().cos()
+            + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+            - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+
+        let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+        let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + 25f64.powi(7))).sqrt();
+        let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+        let s_c = 1.0 + 0.045 * c_bar;
+        let s_h = 1.0 + 0.015 * c_bar * t;
+        let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+        let [k_l, k_c, k_h] = [factors[0] as f64, factors[1] as f64, factors[2] as f64];
+        let (l, c, h) = (delta_l / (k_l * s_l), delta_c / (k_c * s_c), delta_h / (k_h * s_h));
+
+        Ciede2000 {
+            l: l as Float,
+            c: c as Float,
+            h: h as Float,
+            rt: (r_t * c * h) as Float,
+        }
+    }
+
+    pub fn value(&self) -> Float {
+    //! The CIEDE2000 color difference
+        (self.l.powi(2) + self.c.powi(2) + self.h.powi(2) + self.rt).max(0.0).sqrt()
+    }
+}
+
+pub(crate) fn lab_difference(field: Field, reference: &LabValue, sample: &LabValue, factors: [Float; 3]) -> Option<Float> {
+//! Compute the color difference held by a `Field` from a reference to a sample.
+//! Signed components are sample minus reference.
+//! The CIEDE2000 parametric factors apply to DE_2000 and the DE2000_* terms.
+//! Returns None if the field is not a color difference.
+    if let Some(formula) = field.to_de_formula() {
+        return Some(match formula {
+            DeltaEFormula::De2000 { .. } if factors != [1.0; 3] =>
+                Ciede2000::weighted(reference, sample, factors).value(),
+            _ => formula.delta_e(reference, sample),
+        });
+    }
+
+    let chroma = |lab: &LabValue| lab.a.hypot(lab.b);
+
+    match field {
+        Field::DELTA_L => Some(sample.l - reference.l),
+        Field::DELTA_A => Some(sample.a - reference.a),
+        Field::DELTA_B => Some(sample.b - reference.b),
+        Field::DELTA_C => Some(chroma(sample) - chroma(reference)),
+        Field::DELTA_H => {
+            let (c1, c2) = (chroma(reference) as f64, chroma(sample) as f64);
+            let h1 = hue_angle(reference.a as f64, reference.b as f64);
+            let h2 = hue_angle(sample.a as f64, sample.b as f64);
+            Some((2.0 * (c1 * c2).sqrt() * (hue_difference(c1, c2, h1, h2) / 2.0).to_radians().sin()) as Float)
+        },
+        Field::DE2000_L => Some(Ciede2000::weighted(reference, sample, factors).l),
+        Field::DE2000_C => Some(Ciede2000::weighted(reference, sample, factors).c),
+        Field::DE2000_H => Some(Ciede2000::weighted(reference, sample, factors).h),
+        Field::DE2000_RT => Some(Ciede2000::weighted(reference, sample, factors).rt),
+        _ => None,
+    }
+}
+
+fn distance(a: [Float; 3], b: [Float; 3]) -> Float {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn hue_angle(a: f64, b: f64) -> f64 {
+//! Hue angle in degrees (0...360)
+    if a == 0.0 && b == 0.0 {
+        0.0
+    } else {
+        b.atan2(a).to_degrees().rem_euclid(360.0)
+    }
+}
+
+fn hue_difference(c1: f64, c2: f64, h1: f64, h2: f64) -> f64 {
+//! The signed hue angle difference (-180...180) from h1 to h2
+    if c1 * c2 == 0.0 {
+        0.0
+    } else {
+        (h2 - h1 + 180.0).rem_euclid(360.0) - 180.0
+    }
+}
+
+#[test]
+fn ciede2000_terms() {
+    // Sharma, Wu & Dalal test data
+    let pairs = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        ([22.7233, 20.0904, -46.6940], [23.0331, 14.9730, -42.5619], 2.0373),
+    ];
+
+    for (reference, sample, expected) in pairs.iter() {
+        let reference = LabValue { l: reference[0], a: reference[1], b: reference[2] };
+        let sample = LabValue { l: sample[0], a: sample[1], b: sample[2] };
+        let terms = Ciede2000::new(&reference, &sample);
+
+        assert!((terms.value() - expected).abs() < 0.0001, "{:?}: {}", terms, expected);
+        assert!((terms.value() - DeltaE::new(&reference, &sample, DEMethod::DE2000).value).abs() < 0.001);
+    }
+}
+
+#[test]
+fn lab_components() {
+    let reference = LabValue { l: 50.0, a: 30.0, b: 0.0 };
+    let sample = LabValue { l: 52.0, a: 0.0, b: 40.0 };
+
+    let get = |field| lab_difference(field, &reference, &sample, [1.0; 3]).expect("Field is a difference!");
+    assert_eq!(get(Field::DELTA_L), 2.0);
+    assert_eq!(get(Field::DELTA_A), -30.0);
+    assert_eq!(get(Field::DELTA_B), 40.0);
+    assert_eq!(get(Field::DELTA_C), 10.0);
+
+    // ΔE76² = ΔL² + ΔC² + ΔH²
+    let de76 = get(Field::DE_1976);
+    assert!((de76.powi(2) - 4.0 - 100.0 - get(Field::DELTA_H).powi(2)).abs() < 0.01);
+    assert!(get(Field::DELTA_H) > 0.0);
+
+    assert_eq!(lab_difference(Field::LAB_L, &reference, &sample, [1.0; 3]), None);
+}
+
+#[test]
+fn delta_e_formulas() -> Result<()> {
+    let reference = LabValue { l: 50.0, a: 2.5, b: 0.0 };
+    let sample = LabValue { l: 73.0, a: 25.0, b: -18.0 };
+
+    for method in &[DEMethod::DE1976, DEMethod::DE1994, DEMethod::DE1994T, DEMethod::DECMC1, DEMethod::DECMC2, DEMethod::DE2000] {
+        let formula = DeltaEFormula::from(*method);
+        assert_eq!(formula.delta_e(&reference, &sample), DeltaE::new(&reference, &sample, *method).value);
+        assert_eq!(formula.to_string(), method.to_string());
+        assert_eq!(formula.field(), Field::from_de_method(*method));
+    }
+
+    let textiles = DeltaEFormula::from_str("2000:2:1:1")?;
+    assert_eq!(textiles, DeltaEFormula::De2000 { kl: 2.0, kc: 1.0, kh: 1.0 });
+    assert!(textiles.delta_e(&reference, &sample) < DeltaEFormula::DE2000.delta_e(&reference, &sample));
+    assert_eq!(textiles.to_string(), "DE2000 (kL=2, kC=1, kH=1)");
+    assert!(DeltaEFormula::from_str("1976:2:1:1").is_err());
+    assert!(DeltaEFormula::from_str("2000:2:1").is_err());
+
+    assert_eq!(DeltaEFormula::from_str("itp")?, DeltaEFormula::Itp);
+    assert_eq!(DeltaEFormula::from_str("CAM16-UCS")?, DeltaEFormula::Cam16Ucs);
+    assert_eq!(DeltaEFormula::from_str("DE76")?, DeltaEFormula::De1976);
+    assert!(DeltaEFormula::from_str("").is_err());
+    assert!(DeltaEFormula::from_str("de").is_err());
+    assert!(DeltaEFormula::from_str("dede1976").is_err());
+    assert_eq!(DeltaEFormula::Itp.delta_e(&reference, &reference), 0.0);
+    assert!(DeltaEFormula::Itp.delta_e(&reference, &sample) > 10.0);
+    assert!(DeltaEFormula::Cam16Ucs.delta_e(&reference, &sample) > 10.0);
+
+    Ok(())
+}
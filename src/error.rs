@@ -3,8 +3,8 @@ use std::error::Error as StdError;
 use std::convert;
 use std::io;
 use std::result;
+use super::ConditionMismatch;
 
-#[macro_use]
 #[allow(unused_macros)]
 macro_rules! err {
     ($($tt:tt)*) => { Err(CgatsError::Other(format!($($tt)*))) }
@@ -14,6 +14,7 @@ macro_rules! err {
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     CannotCompare,
+    ConditionMismatch(ConditionMismatch),
     EmptyFile,
     FileError,
     FormatDataMismatch,
@@ -32,8 +33,12 @@ pub enum Error {
 pub type Result<T> = result::Result<T, Error>;
 
 impl fmt::Display for Error {
+    #[allow(deprecated)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "CgatsError::{:?}: {}", &self, &self.description())
+        match self {
+            Error::ConditionMismatch(mismatch) => write!(f, "CgatsError::ConditionMismatch: {}", mismatch),
+            _ => write!(f, "CgatsError::{:?}: {}", &self, &self.description()),
+        }
     }
 }
 
@@ -42,19 +47,20 @@ impl std::error::Error for Error {
     fn description(&self) -> &str {
         use Error::*;
         match &self {
-            CannotCompare      => "Cannot compare data sets!",
-            EmptyFile          => "File is empty!",
-            FileError          => "Problem reading file!",
-            FormatDataMismatch => "DATA length does not match DATA_FORMAT length!",
-            IncompleteData     => "Not enough data for the calculation!",
-            InvalidCommand     => "Invalid Compare command!",
-            InvalidID          => "SAMPLE_ID is not an integer!",
-            NoData             => "Color Data not found!",
-            NoDataFormat       => "Cannot find BEGIN_DATA_FORMAT tag!",
-            UnknownVendor      => "Cannot determine Vendor!",
-            UnknownFormatType  => "Unknown Data Format Type!",
-            WriteError         => "Problem writing to file!",
-            Other(message)     => &message,
+            CannotCompare        => "Cannot compare data sets!",
+            ConditionMismatch(_) => "Data sets were measured under different conditions!",
+            EmptyFile            => "File is empty!",
+            FileError            => "Problem reading file!",
+            FormatDataMismatch   => "DATA length does not match DATA_FORMAT length!",
+            IncompleteData       => "Not enough data for the calculation!",
+            InvalidCommand       => "Invalid Compare command!",
+            InvalidID            => "SAMPLE_ID is not an integer!",
+            NoData               => "Color Data not found!",
+            NoDataFormat         => "Cannot find BEGIN_DATA_FORMAT tag!",
+            UnknownVendor        => "Cannot determine Vendor!",
+            UnknownFormatType    => "Unknown Data Format Type!",
+            WriteError           => "Problem writing to file!",
+            Other(message)       => message,
         }
     }
 }
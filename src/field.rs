@@ -10,24 +10,28 @@ use std::convert::TryFrom;
 pub type DataFormat = Vec<Field>;
 
 // Known data format types: This list is incomplete
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum Field {
     // String
-    SAMPLE_ID, SAMPLE_NAME, BLANK,
+    SAMPLE_ID, SAMPLE_NAME, SAMPLE_LOC, BACKGROUND, BLANK,
 
     // Float
-    RGB_R, RGB_G, RGB_B,
-    CMYK_C, CMYK_M, CMYK_Y, CMYK_K,
+    TINT,
+    RGB_I, RGB_R, RGB_G, RGB_B,
+    CMYK_I, CMYK_C, CMYK_M, CMYK_Y, CMYK_K,
     FIVECLR_1, FIVECLR_2, FIVECLR_3, FIVECLR_4, FIVECLR_5,
     SIXCLR_1, SIXCLR_2, SIXCLR_3, SIXCLR_4, SIXCLR_5, SIXCLR_6,
     SEVENCLR_1, SEVENCLR_2, SEVENCLR_3, SEVENCLR_4, SEVENCLR_5, SEVENCLR_6, SEVENCLR_7,
     EIGHTCLR_1, EIGHTCLR_2, EIGHTCLR_3, EIGHTCLR_4, EIGHTCLR_5, EIGHTCLR_6, EIGHTCLR_7, EIGHTCLR_8,
     D_RED, D_GREEN, D_BLUE, D_VIS,
     LAB_L, LAB_A, LAB_B, LAB_C, LAB_H,
-    DE_1976, DE_1994, DE_1994T, DE_CMC, DE_CMC2, DE_2000,
+    DE_1976, DE_1994, DE_1994T, DE_CMC, DE_CMC2, DE_2000, DE_ITP, DE_CAM16UCS,
+    DELTA_L, DELTA_A, DELTA_B, DELTA_C, DELTA_H,
+    DE2000_L, DE2000_C, DE2000_H, DE2000_RT,
     XYZ_X, XYZ_Y, XYZ_Z,
     XYY_X, XYY_Y, XYY_CAPY,
+    STDEV_X, STDEV_Y, STDEV_Z, STDEV_L, STDEV_A, STDEV_B, STDEV_DE,
     SPECTRAL_340, SPECTRAL_350, SPECTRAL_360, SPECTRAL_370, SPECTRAL_380,
     SPECTRAL_390, SPECTRAL_400, SPECTRAL_410, SPECTRAL_420, SPECTRAL_430,
     SPECTRAL_440, SPECTRAL_450, SPECTRAL_460, SPECTRAL_470, SPECTRAL_480,
@@ -57,12 +61,7 @@ pub fn ColorBurstFormat() -> DataFormat {
 impl Field {
     pub fn is_float(self) -> bool {
         use Field::*;
-        match &self {
-            SAMPLE_NAME => false,
-            SAMPLE_ID => false,
-            BLANK => false,
-            _ => true
-        }
+        !matches!(self, SAMPLE_NAME | SAMPLE_ID | SAMPLE_LOC | BACKGROUND | BLANK)
     }
 
     pub fn from_de_method(method: DEMethod) -> Field {
@@ -88,12 +87,78 @@ impl Field {
         }
     }
 
+    pub fn to_de_formula(self) -> Option<DeltaEFormula> {
+    //! The formula of a DE_* field. DE_2000 is CIEDE2000 with factors 1:1:1.
+        match self {
+            Field::DE_ITP      => Some(DeltaEFormula::Itp),
+            Field::DE_CAM16UCS => Some(DeltaEFormula::Cam16Ucs),
+            _ => self.to_de_method().map(DeltaEFormula::from),
+        }
+    }
+
+    pub fn is_difference(self) -> bool {
+    //! Test if a field holds a color difference between two LAB values
+        use Field::*;
+        self.to_de_formula().is_some() || matches!(self,
+            DELTA_L | DELTA_A | DELTA_B | DELTA_C | DELTA_H |
+            DE2000_L | DE2000_C | DE2000_H | DE2000_RT
+        )
+    }
+
+    pub fn stdev_field(self) -> Option<Field> {
+    //! The STDEV_* field holding the standard deviation of an averaged field
+        use Field::*;
+        match self {
+            XYZ_X => Some(STDEV_X),
+            XYZ_Y => Some(STDEV_Y),
+            XYZ_Z => Some(STDEV_Z),
+            LAB_L => Some(STDEV_L),
+            LAB_A => Some(STDEV_A),
+            LAB_B => Some(STDEV_B),
+            _ => None,
+        }
+    }
+
+    pub fn device_fields(fields: &DataFormat) -> Option<DataFormat> {
+    //! Returns the device value fields (CMYK, RGB or N-color) of a DATA_FORMAT
+        use Field::*;
+        let sets: &[&[Field]] = &[
+            &[CMYK_C, CMYK_M, CMYK_Y, CMYK_K],
+            &[RGB_R, RGB_G, RGB_B],
+            &[EIGHTCLR_1, EIGHTCLR_2, EIGHTCLR_3, EIGHTCLR_4, EIGHTCLR_5, EIGHTCLR_6, EIGHTCLR_7, EIGHTCLR_8],
+            &[SEVENCLR_1, SEVENCLR_2, SEVENCLR_3, SEVENCLR_4, SEVENCLR_5, SEVENCLR_6, SEVENCLR_7],
+            &[SIXCLR_1, SIXCLR_2, SIXCLR_3, SIXCLR_4, SIXCLR_5, SIXCLR_6],
+            &[FIVECLR_1, FIVECLR_2, FIVECLR_3, FIVECLR_4, FIVECLR_5],
+        ];
+
+        sets.iter()
+            .find(|set| set.iter().all(|f| fields.contains(f)))
+            .map(|set| set.to_vec())
+    }
+
+    pub fn spectral_nm(self) -> Option<u32> {
+    //! The wavelength in nanometers of a SPECTRAL_* field
+        self.to_string().strip_prefix("SPECTRAL_")?.parse().ok()
+    }
+
+    pub fn from_spectral_nm(nm: u32) -> Option<Field> {
+    //! The SPECTRAL_* field for a wavelength in nanometers
+        Field::from_str(&format!("SPECTRAL_{}", nm)).ok()
+    }
+
     pub fn lab_indexes(fields: &DataFormat) -> Option<[usize; 3]> {
         let l = fields.iter().position(|f| *f == Field::LAB_L)?;
         let a = fields.iter().position(|f| *f == Field::LAB_A)?;
         let b = fields.iter().position(|f| *f == Field::LAB_B)?;
         Some([ l, a, b ])
     }
+
+    pub fn xyz_indexes(fields: &DataFormat) -> Option<[usize; 3]> {
+        let x = fields.iter().position(|f| *f == Field::XYZ_X)?;
+        let y = fields.iter().position(|f| *f == Field::XYZ_Y)?;
+        let z = fields.iter().position(|f| *f == Field::XYZ_Z)?;
+        Some([ x, y, z ])
+    }
 }
 
 impl From<&DEMethod> for Field {
@@ -142,15 +207,27 @@ impl FromStr for Field {
 
     fn from_str(s: &str) -> Result<Self> {
         use Field::*;
-        match s.to_uppercase().as_ref() {
+        let upper = s.to_uppercase();
+
+        // Argyll abbreviates spectral fields as SPEC_nnn
+        if let Some(nm) = upper.strip_prefix("SPEC_") {
+            return Field::from_str(&format!("SPECTRAL_{}", nm));
+        }
+
+        match upper.as_ref() {
             "SAMPLE_ID"   | "SAMPLEID" | "SAMPLE" => Ok(SAMPLE_ID),
             "SAMPLE_NAME" | "SAMPLENAME" => Ok(SAMPLE_NAME),
+            "SAMPLE_LOC"  => Ok(SAMPLE_LOC),
+            "BACKGROUND"  => Ok(BACKGROUND),
+            "TINT"        => Ok(TINT),
             "" | "BLANK" => Ok(BLANK),
 
+            "RGB_I"   => Ok(RGB_I),
             "RGB_R"   => Ok(RGB_R),
             "RGB_G"   => Ok(RGB_G),
             "RGB_B"   => Ok(RGB_B),
             
+            "CMYK_I"  => Ok(CMYK_I),
             "CMYK_C"  => Ok(CMYK_C),
             "CMYK_M"  => Ok(CMYK_M),
             "CMYK_Y"  => Ok(CMYK_Y),
@@ -198,6 +275,14 @@ impl FromStr for Field {
             "XYY_Y"   => Ok(XYY_Y),
             "XYY_CAPY" => Ok(XYY_CAPY),
 
+            "STDEV_X"  => Ok(STDEV_X),
+            "STDEV_Y"  => Ok(STDEV_Y),
+            "STDEV_Z"  => Ok(STDEV_Z),
+            "STDEV_L"  => Ok(STDEV_L),
+            "STDEV_A"  => Ok(STDEV_A),
+            "STDEV_B"  => Ok(STDEV_B),
+            "STDEV_DE" => Ok(STDEV_DE),
+
             "D_RED"   => Ok(D_RED),
             "D_GREEN" => Ok(D_GREEN),
             "D_BLUE"  => Ok(D_BLUE),
@@ -225,6 +310,20 @@ impl FromStr for Field {
             "LAB_DE_2001" | "DE2000" | "DE00" | "DE_2000" | "DE_00"
                 => Ok(DE_2000),
 
+            "DE_ITP" | "DEITP" | "ITP" => Ok(DE_ITP),
+            "DE_CAM16UCS" | "DECAM16UCS" | "DE_CAM16" | "CAM16UCS" => Ok(DE_CAM16UCS),
+
+            "DELTA_L" | "DL" | "DL*" => Ok(DELTA_L),
+            "DELTA_A" | "DA" | "DA*" => Ok(DELTA_A),
+            "DELTA_B" | "DB" | "DB*" => Ok(DELTA_B),
+            "DELTA_C" | "DC" | "DC*" => Ok(DELTA_C),
+            "DELTA_H" | "DH" | "DH*" => Ok(DELTA_H),
+
+            "DE2000_L" | "DE00_L" => Ok(DE2000_L),
+            "DE2000_C" | "DE00_C" => Ok(DE2000_C),
+            "DE2000_H" | "DE00_H" => Ok(DE2000_H),
+            "DE2000_RT" | "DE00_RT" => Ok(DE2000_RT),
+
             "SPECTRAL_340" => Ok(SPECTRAL_340),
             "SPECTRAL_350" => Ok(SPECTRAL_350),
             "SPECTRAL_360" => Ok(SPECTRAL_360),
@@ -0,0 +1,488 @@
+use super::*;
+
+use crate::curve::curve_file_inputs;
+use deltae::color::LabValue;
+use std::fmt;
+
+// The G7 NPDC as a tone value between paper and solid:
+// (nominal %, (Y paper - Y) / (Y paper - Y solid)).
+// G7 scales the curve of its reference condition (GRACoL 2013, CRPC6) in Y
+// to the paper and solid of each press; these are its reference values.
+const NPDC_TONE: &[(Float, Float)] = &[
+    (0.0, 0.0000), (2.0, 0.0396), (3.0, 0.0595), (5.0, 0.0962), (7.0, 0.1326),
+    (10.0, 0.1850), (15.0, 0.2650), (20.0, 0.3372), (25.0, 0.4091), (30.0, 0.4729),
+    (40.0, 0.5900), (50.0, 0.6905), (60.0, 0.7805), (70.0, 0.8569), (75.0, 0.8904),
+    (80.0, 0.9211), (85.0, 0.9477), (90.0, 0.9699), (95.0, 0.9878), (98.0, 0.9959),
+    (100.0, 1.0000),
+];
+
+// Nominal G7 gray balance: cyan % -> magenta and yellow % (from the P2P51 gray ramp)
+const GRAY_BALANCE: &[(Float, Float)] = &[
+    (0.0, 0.0), (2.0, 1.493), (4.0, 2.983), (6.0, 4.474), (8.0, 5.965),
+    (10.0, 7.458), (15.0, 11.212), (20.0, 15.011), (25.0, 18.878), (30.0, 22.835),
+    (35.0, 26.903), (40.0, 31.106), (45.0, 35.464), (50.0, 40.0), (55.0, 44.736),
+    (60.0, 49.694), (65.0, 54.897), (70.0, 60.365), (75.0, 66.122), (80.0, 72.189),
+    (85.0, 78.588), (90.0, 85.342), (95.0, 92.472), (98.0, 96.939), (100.0, 100.0),
+];
+
+// G7 Grayscale tolerances for the weighted averages and maximums
+pub const G7_WDL_AVG: Float = 1.5;
+pub const G7_WDL_MAX: Float = 3.0;
+pub const G7_WDCH_AVG: Float = 1.5;
+pub const G7_WDCH_MAX: Float = 3.0;
+
+// Gray balance corrections are reduced to nothing between these tints
+const GRAY_TAPER_START: Float = 87.5;
+const GRAY_TAPER_END: Float = 100.0;
+
+/// The two neutral ramps G7 calibrates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum G7Ramp {
+    /// Three color gray: cyan with G7 balanced magenta and yellow
+    Cmy,
+    /// Black only
+    K,
+}
+
+impl fmt::Display for G7Ramp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            G7Ramp::Cmy => write!(f, "CMY"),
+            G7Ramp::K => write!(f, "K"),
+        }
+    }
+}
+
+/// One measured step of a neutral ramp compared with its G7 target
+#[derive(Debug, Clone, PartialEq)]
+pub struct G7Step {
+    pub nominal: Float,
+    pub measured: LabValue,
+    pub target: LabValue,
+    pub delta_l: Float,
+    pub delta_ch: Float,
+    pub weight: Float,
+}
+
+/// Neutral print density curve of a ramp against the G7 target
+#[derive(Debug, Clone, PartialEq)]
+pub struct G7Npdc {
+    pub ramp: G7Ramp,
+    pub steps: Vec<G7Step>,
+}
+
+impl G7Npdc {
+    fn new(ramp: G7Ramp, measured: Vec<(Float, LabValue)>, paper: &LabValue) -> Result<G7Npdc> {
+        let solid = match measured.last() {
+            Some((_, lab)) => lab.clone(),
+            None => return Err(Error::IncompleteData),
+        };
+
+        // The target runs from the paper to the solid of the data being analysed
+        let tone = npdc_tone();
+        let y_paper = luminance(paper.l);
+        let y_solid = luminance(solid.l);
+
+        let steps = measured.into_iter().map(|(nominal, lab)| {
+            let y_target = y_paper - (y_paper - y_solid) * tone.interpolate(nominal);
+            // G7 keeps 15 % of the paper tint in the solid
+            let fade = 1.0 - 0.85 * nominal / 100.0;
+            let target = LabValue {
+                l: lightness(y_target),
+                a: paper.a * fade,
+                b: paper.b * fade,
+            };
+
+            G7Step {
+                nominal,
+                delta_l: lab.l - target.l,
+                delta_ch: ((lab.a - target.a).powi(2) + (lab.b - target.b).powi(2)).sqrt(),
+                weight: weight(nominal),
+                measured: lab,
+                target,
+            }
+        }).collect();
+
+        Ok(G7Npdc { ramp, steps })
+    }
+
+    pub fn weighted_delta_l(&self) -> [Float; 2] {
+    //! Weighted average and weighted maximum |ΔL*| against the NPDC target
+        weighted_stats(self.steps.iter().map(|s| (s.weight, s.delta_l.abs())))
+    }
+
+    pub fn weighted_delta_ch(&self) -> [Float; 2] {
+    //! Weighted average and weighted maximum ΔCh against the gray balance target
+        weighted_stats(self.steps.iter().map(|s| (s.weight, s.delta_ch)))
+    }
+
+    pub fn measured_density(&self) -> Result<Curve> {
+    //! Nominal % -> measured neutral density
+        Curve::new(self.steps.iter().map(|s| (s.nominal, neutral_density(s.measured.l))).collect())
+    }
+
+    pub fn target_density(&self) -> Result<Curve> {
+    //! Nominal % -> target neutral density
+        Curve::new(self.steps.iter().map(|s| (s.nominal, neutral_density(s.target.l))).collect())
+    }
+
+    fn correction(&self) -> Result<Curve> {
+    // Input % -> the input % that prints the target density
+        let measured = self.measured_density()?;
+        let target = self.target_density()?;
+
+        let inverse = match measured.inverse() {
+            Ok(inverse) => inverse,
+            Err(_) => return Err(Error::Other(format!("{} ramp density is not monotone!", self.ramp))),
+        };
+
+        Curve::new(curve_file_inputs()
+            .map(|x| (x, inverse.interpolate(target.interpolate(x)).clamp(0.0, 100.0)))
+            .collect())
+    }
+}
+
+impl fmt::Display for G7Npdc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [dl_avg, dl_max] = self.weighted_delta_l();
+        writeln!(f, "{} NPDC - ({} steps)", self.ramp, self.steps.len())?;
+        writeln!(f, "\t{:>8}: {:0.4}", "wΔL avg", dl_avg)?;
+        writeln!(f, "\t{:>8}: {:0.4}", "wΔL max", dl_max)?;
+
+        if self.ramp == G7Ramp::Cmy {
+            let [dch_avg, dch_max] = self.weighted_delta_ch();
+            writeln!(f, "\t{:>8}: {:0.4}", "wΔCh avg", dch_avg)?;
+            writeln!(f, "\t{:>8}: {:0.4}", "wΔCh max", dch_max)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// G7 gray balance and NPDC analysis of a P2P style data set
+#[derive(Debug, Clone, PartialEq)]
+pub struct G7Analysis {
+    pub paper: LabValue,
+    pub cmy: G7Npdc,
+    pub k: G7Npdc,
+    magenta_a: Option<Curve>,
+    yellow_b: Option<Curve>,
+}
+
+impl G7Analysis {
+    pub fn passes_grayscale(&self) -> bool {
+    //! Test the weighted ΔL of both ramps and the weighted ΔCh of the CMY ramp
+    //! against the G7 Grayscale tolerances
+        let [cmy_dl_avg, cmy_dl_max] = self.cmy.weighted_delta_l();
+        let [k_dl_avg, k_dl_max] = self.k.weighted_delta_l();
+        let [dch_avg, dch_max] = self.cmy.weighted_delta_ch();
+
+        cmy_dl_avg <= G7_WDL_AVG && cmy_dl_max <= G7_WDL_MAX &&
+        k_dl_avg <= G7_WDL_AVG && k_dl_max <= G7_WDL_MAX &&
+        dch_avg <= G7_WDCH_AVG && dch_max <= G7_WDCH_MAX
+    }
+
+    pub fn correction_curves(&self) -> Result<CurveSet> {
+    //! Generate the CMYK curves that move the press onto the G7 NPDC and gray balance.
+    //! Magenta and yellow are corrected for the a* and b* error of the gray ramp
+    //! when the data set has magenta and yellow ramps to estimate their effect.
+        let black = self.k.correction()?;
+        let cyan = self.cmy.correction()?;
+        let balance = gray_balance();
+
+        let mut magenta_points = Vec::new();
+        let mut yellow_points = Vec::new();
+
+        for (input, output) in cyan.points() {
+            let (delta_a, delta_b) = self.gray_error(*input);
+            let nominal = balance.interpolate(*input);
+            let base = balance.interpolate(*output);
+            let taper = gray_taper(*input);
+
+            let magenta = base - taper * correction_step(delta_a, self.magenta_a.as_ref(), base);
+            let yellow = base - taper * correction_step(delta_b, self.yellow_b.as_ref(), base);
+
+            magenta_points.push((nominal, magenta.clamp(0.0, 100.0)));
+            yellow_points.push((nominal, yellow.clamp(0.0, 100.0)));
+        }
+
+        let magenta = Curve::new(magenta_points)?.resample(curve_file_inputs())?;
+        let yellow = Curve::new(yellow_points)?.resample(curve_file_inputs())?;
+
+        let mut curves = CurveSet::new();
+        curves.insert(Channel::Cyan, monotone(cyan)?);
+        curves.insert(Channel::Magenta, monotone(magenta)?);
+        curves.insert(Channel::Yellow, monotone(yellow)?);
+        curves.insert(Channel::Black, monotone(black)?);
+
+        Ok(curves)
+    }
+
+    fn gray_error(&self, nominal: Float) -> (Float, Float) {
+    // Interpolated a* and b* error of the gray ramp at a cyan %
+        let delta = |f: &dyn Fn(&G7Step) -> Float| Curve::new(
+            self.cmy.steps.iter().map(|s| (s.nominal, f(s))).collect()
+        ).map(|c| c.interpolate(nominal)).unwrap_or(0.0);
+
+        (
+            delta(&|s| s.measured.a - s.target.a),
+            delta(&|s| s.measured.b - s.target.b),
+        )
+    }
+}
+
+impl fmt::Display for G7Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Paper: {}", self.paper)?;
+        writeln!(f, "G7 Grayscale: {}\n", if self.passes_grayscale() { "PASS" } else { "FAIL" })?;
+        writeln!(f, "{}", self.cmy)?;
+        writeln!(f, "{}", self.k)
+    }
+}
+
+impl Cgats {
+    pub fn gray_ramp(&self) -> Result<Vec<(Float, Vec<usize>)>> {
+    //! Find the three color gray ramp: samples without black whose magenta and
+    //! yellow are equal and close to the G7 balance for their cyan, grouped by cyan.
+    //! Paper white is the first step and the CMY solid the last.
+        if !self.has_cmyk() {
+            return Err(Error::IncompleteData);
+        }
+
+        let balance = gray_balance();
+        let mut ramp: Vec<(Float, Float, Vec<usize>)> = Vec::new();
+
+        for key in self.data_map.keys() {
+            let cmyk = match Channel::CMYK.iter()
+                .map(|ch| self.get_float(*key, &ch.field()))
+                .collect::<Option<Vec<_>>>() {
+                    Some(cmyk) => cmyk,
+                    None => continue,
+                };
+
+            let (c, m, y, k) = (cmyk[0], cmyk[1], cmyk[2], cmyk[3]);
+            let offset = (m - balance.interpolate(c)).abs();
+
+            if k.abs() > 0.01 || (m - y).abs() > 0.01 || offset > (0.15 * c).max(1.0) {
+                continue;
+            }
+
+            match ramp.iter_mut().find(|(cyan, _, _)| (cyan - c).abs() < 0.01) {
+                Some(step) if (step.1 - offset).abs() < 0.01 => step.2.push(*key),
+                Some(step) if offset < step.1 => *step = (c, offset, vec![*key]),
+                Some(_) => continue,
+                None => ramp.push((c, offset, vec![*key])),
+            }
+        }
+
+        ramp.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Tint is NaN!"));
+
+        let has_paper = ramp.first().map(|(c, _, _)| c.abs() < 0.01).unwrap_or(false);
+        let has_solid = ramp.last().map(|(c, _, _)| (c - 100.0).abs() < 0.01).unwrap_or(false);
+
+        if !has_paper || !has_solid || ramp.len() < 3 {
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(ramp.into_iter().map(|(c, _, keys)| (c, keys)).collect())
+    }
+
+    pub fn g7_analysis(&self) -> Result<G7Analysis> {
+    //! Compare the gray and black ramps of a measured data set with the G7 targets.
+    //! Returns an Error if the data set has no CMYK, no LAB or XYZ, or either ramp.
+        let cmy = self.ramp_lab(self.gray_ramp()?)?;
+        let k = self.ramp_lab(self.channel_ramp(Channel::Black)?)?;
+
+        let paper = cmy[0].1.clone();
+
+        let magenta_a = self.channel_ramp(Channel::Magenta)
+            .and_then(|ramp| self.ramp_lab(ramp))
+            .and_then(|ramp| Curve::new(ramp.iter().map(|(t, lab)| (*t, lab.a)).collect()))
+            .ok();
+        let yellow_b = self.channel_ramp(Channel::Yellow)
+            .and_then(|ramp| self.ramp_lab(ramp))
+            .and_then(|ramp| Curve::new(ramp.iter().map(|(t, lab)| (*t, lab.b)).collect()))
+            .ok();
+
+        Ok(G7Analysis {
+            cmy: G7Npdc::new(G7Ramp::Cmy, cmy, &paper)?,
+            k: G7Npdc::new(G7Ramp::K, k, &paper)?,
+            paper,
+            magenta_a,
+            yellow_b,
+        })
+    }
+
+    fn ramp_lab(&self, ramp: Vec<(Float, Vec<usize>)>) -> Result<Vec<(Float, LabValue)>> {
+    // Average the LAB of the samples at each step of a ramp
+        ramp.into_iter().map(|(tint, keys)| {
+            let labs = keys.iter()
+                .map(|key| self.sample_lab(*key))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::IncompleteData)?;
+            let n = labs.len() as Float;
+
+            Ok((tint, LabValue {
+                l: labs.iter().map(|lab| lab.l).sum::<Float>() / n,
+                a: labs.iter().map(|lab| lab.a).sum::<Float>() / n,
+                b: labs.iter().map(|lab| lab.b).sum::<Float>() / n,
+            }))
+        }).collect()
+    }
+}
+
+pub(crate) fn npdc_tone() -> Curve {
+    Curve::new(NPDC_TONE.to_vec()).expect("NPDC tone is a valid Curve!")
+}
+
+fn gray_balance() -> Curve {
+    Curve::new(GRAY_BALANCE.to_vec()).expect("Gray balance is a valid Curve!")
+}
+
+pub(crate) fn luminance(l: Float) -> Float {
+//! Luminance factor Y (0.0...1.0) of L*
+    color::lab_to_xyz(&LabValue { l, a: 0.0, b: 0.0 })[1]
+}
+
+pub(crate) fn neutral_density(l: Float) -> Float {
+//! Neutral (visual) density from L*
+    color::density(luminance(l))
+}
+
+fn lightness(luminance: Float) -> Float {
+//! L* of a luminance factor
+    116.0 * color::lab_f(luminance) - 16.0
+}
+
+fn weight(nominal: Float) -> Float {
+//! Highlights and midtones count fully, the weight falls to 0.25 at the solid
+    if nominal <= 50.0 {
+        1.0
+    } else {
+        1.0 - 0.75 * (nominal - 50.0) / 50.0
+    }
+}
+
+fn weighted_stats<I: Iterator<Item = (Float, Float)>>(values: I) -> [Float; 2] {
+    let (mut sum, mut weights, mut max) = (0.0, 0.0, 0.0 as Float);
+    for (weight, value) in values {
+        sum += weight * value;
+        weights += weight;
+        max = max.max(weight * value);
+    }
+
+    if weights > 0.0 { [sum / weights, max] } else { [0.0, 0.0] }
+}
+
+fn gray_taper(nominal: Float) -> Float {
+    if nominal <= GRAY_TAPER_START {
+        1.0
+    } else {
+        ((GRAY_TAPER_END - nominal) / (GRAY_TAPER_END - GRAY_TAPER_START)).max(0.0)
+    }
+}
+
+fn correction_step(error: Float, response: Option<&Curve>, tint: Float) -> Float {
+//! Device % change that removes an a* or b* error, from the slope of a single channel ramp
+    let response = match response {
+        Some(response) => response,
+        None => return 0.0,
+    };
+
+    let slope = (response.interpolate(tint + 2.5) - response.interpolate(tint - 2.5)) / 5.0;
+
+    if slope.abs() < 0.05 {
+        0.0
+    } else {
+        error / slope
+    }
+}
+
+fn monotone(curve: Curve) -> Result<Curve> {
+//! Clamp a correction Curve so that it never decreases
+    let mut highest: Float = 0.0;
+    curve.map(|_, y| {
+        highest = highest.max(y);
+        highest
+    })
+}
+
+#[test]
+fn g7_npdc() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let g7 = crpc6.g7_analysis()?;
+    println!("{}", g7);
+
+    assert_eq!(g7.paper.l, 95.0);
+    assert_eq!(g7.k.steps.first().map(|s| s.nominal), Some(0.0));
+    assert_eq!(g7.cmy.steps.last().map(|s| s.nominal), Some(100.0));
+
+    // The target runs from the paper to the solid of the data
+    let (first, last) = (&g7.k.steps[0], g7.k.steps.last().expect("K steps"));
+    assert!((first.target.l - g7.paper.l).abs() < 0.01);
+    assert!((last.target.l - last.measured.l).abs() < 0.01);
+
+    // The gray target keeps 15 % of the paper tint in the solid
+    let solid = g7.cmy.steps.last().expect("CMY steps");
+    assert!((solid.target.a - 0.15 * g7.paper.a).abs() < 1e-4);
+    assert!((solid.target.b - 0.15 * g7.paper.b).abs() < 1e-4);
+    let half = g7.cmy.steps.iter().find(|s| s.nominal == 50.0).expect("50 % gray");
+    assert!((half.target.b - 0.575 * g7.paper.b).abs() < 1e-4);
+
+    let curves = g7.correction_curves()?;
+    let black = curves.get(Channel::Black).expect("Black curve");
+    assert!((black.interpolate(50.0) - 50.0).abs() < 0.5);
+    assert_eq!(curves.apply(Channel::Cyan, 100.0), 100.0);
+
+    let curve3 = curves.to_curve3();
+    assert_eq!(curve3.sample_count(), 21);
+    assert_eq!(curve3.vendor, Vendor::Curve);
+
+    Ok(())
+}
+
+#[test]
+fn g7_other_papers() -> Result<()> {
+    // The G7 based conditions on other papers, with other solids, follow their own NPDC
+    for n in &[5, 7] {
+        let g7 = Cgats::from_file(format!("reference/ISO15339-CRPC{}.txt", n))?.g7_analysis()?;
+        assert!(g7.paper.l != 95.0);
+        assert!(g7.k.weighted_delta_l()[1] < 0.25);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn g7_off_target() -> Result<()> {
+    // Darken the midtones of the K ramp by up to 5 L*
+    let mut heavy = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let l_index = heavy.field_index(&Field::LAB_L).expect("LAB_L");
+    for sample in heavy.data_map.values_mut() {
+        let cmyk: Vec<Float> = sample.values[1..5].iter().filter_map(|value| value.float).collect();
+        if cmyk[..3] == [0.0; 3] && cmyk[3] > 0.0 && cmyk[3] < 100.0 {
+            let l = sample.values[l_index].float.expect("LAB_L") - 5.0 * (cmyk[3] / 100.0 * std::f32::consts::PI).sin();
+            sample.values[l_index] = CgatsValue::from_float(l);
+        }
+    }
+
+    let g7 = heavy.g7_analysis()?;
+    let half = g7.k.steps.iter().find(|s| s.nominal == 50.0).expect("50 % K");
+    assert!((half.delta_l + 5.0).abs() < 0.1);
+    assert!(g7.k.weighted_delta_l()[0] > G7_WDL_AVG);
+    assert!(!g7.passes_grayscale());
+
+    // Less black prints the target
+    let curves = g7.correction_curves()?;
+    assert!(curves.apply(Channel::Black, 50.0) < 45.0);
+
+    Ok(())
+}
+
+#[test]
+fn g7_missing_ramps() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/cgats1.tsv")?;
+    assert!(cgats.g7_analysis().is_err());
+    Ok(())
+}
+
@@ -0,0 +1,385 @@
+use super::*;
+
+use deltae::color::LabValue;
+use std::f64::consts::PI;
+use std::fmt;
+
+// Device value steps along each edge of a sampled face of the device cube
+const FACE_STEPS: usize = 11;
+
+// Segments of the boundary from the white pole to the black pole
+const POLAR_SEGMENTS: usize = 18;
+
+// Segments of the boundary around the hue circle
+const HUE_SEGMENTS: usize = 36;
+
+/// The gamut boundary of a characterization data set.
+/// The boundary is the surface of the device value hull mapped to LAB, described by
+/// its distance from a center on the L* axis in each of a set of directions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gamut {
+    surface: Vec<Triangle>,
+    center: Float,
+    radii: Vec<Float>,
+}
+
+// A triangle of the boundary surface in LAB
+type Triangle = [[f64; 3]; 3];
+
+impl Gamut {
+    pub fn from_model(model: &CharacterizationModel) -> Result<Gamut> {
+    //! Find the gamut boundary of a forward model from the outer surface of the device
+    //! value hull: every 2-dimensional face of the device cube, meshed and mapped to LAB.
+    //! Returns an Error if the model has fewer than 3 device channels.
+        let dimensions = model.device_fields().len();
+        if dimensions < 3 {
+            return Err(Error::IncompleteData);
+        }
+
+        let mut surface = Vec::new();
+        for first in 0..dimensions {
+            for second in (first + 1)..dimensions {
+                let others: Vec<usize> = (0..dimensions).filter(|d| *d != first && *d != second).collect();
+
+                for corner in 0..(1usize << others.len()) {
+                    let mut device = vec![0.0; dimensions];
+                    for (bit, other) in others.iter().enumerate() {
+                        device[*other] = if corner & (1 << bit) == 0 { 0.0 } else { 100.0 };
+                    }
+
+                    let mut face = Vec::with_capacity(FACE_STEPS * FACE_STEPS);
+                    for i in 0..FACE_STEPS {
+                        for j in 0..FACE_STEPS {
+                            device[first] = 100.0 * i as Float / (FACE_STEPS - 1) as Float;
+                            device[second] = 100.0 * j as Float / (FACE_STEPS - 1) as Float;
+                            let lab = model.lab(&device)?;
+                            face.push([lab.l as f64, lab.a as f64, lab.b as f64]);
+                        }
+                    }
+
+                    for i in 0..FACE_STEPS - 1 {
+                        for j in 0..FACE_STEPS - 1 {
+                            let vertex = |i: usize, j: usize| face[i * FACE_STEPS + j];
+                            surface.push([vertex(i, j), vertex(i + 1, j), vertex(i + 1, j + 1)]);
+                            surface.push([vertex(i, j), vertex(i + 1, j + 1), vertex(i, j + 1)]);
+                        }
+                    }
+                }
+            }
+        }
+
+        let (min, max) = surface.iter().flatten()
+            .fold((f64::MAX, f64::MIN), |(min, max), lab| (min.min(lab[0]), max.max(lab[0])));
+        let center = ((min + max) / 2.0) as Float;
+        let radii = boundary_radii(&surface, center);
+
+        Ok(Gamut { surface, center, radii })
+    }
+
+    pub fn volume(&self) -> Float {
+    //! The gamut volume in cubic Delta E (CIE 1976) units
+        volume(&self.radii)
+    }
+
+    pub fn intersection_volume(&self, other: &Gamut) -> Float {
+    //! The volume that both gamuts have in common
+        self.overlap(other).0
+    }
+
+    pub fn coverage(&self, other: &Gamut) -> Float {
+    //! The percentage of the other gamut's volume that this gamut covers
+        let (common, theirs) = self.overlap(other);
+        if theirs <= 0.0 {
+            return 0.0;
+        }
+        (100.0 * common / theirs).min(100.0)
+    }
+
+    fn overlap(&self, other: &Gamut) -> (Float, Float) {
+    // The common volume and the other gamut's volume, both measured from the same center
+        let center = (self.center + other.center) / 2.0;
+        let mine = boundary_radii(&self.surface, center);
+        let theirs = boundary_radii(&other.surface, center);
+
+        let common: Vec<Float> = mine.iter().zip(&theirs).map(|(a, b)| a.min(*b)).collect();
+        (volume(&common), volume(&theirs))
+    }
+
+    pub fn contains(&self, lab: &LabValue) -> bool {
+    //! Test if a LAB value is inside the gamut boundary
+        let (radius, segment) = direction([lab.l, lab.a, lab.b], self.center);
+        radius <= self.radii[segment]
+    }
+
+    pub fn hue_slice(&self, hue: Float) -> Vec<(Float, Float)> {
+    //! The boundary in the L*C* plane at a hue angle (degrees),
+    //! from the white end to the black end of the L* axis
+        let position = hue.rem_euclid(360.0) / 360.0 * HUE_SEGMENTS as Float - 0.5;
+        let first = position.floor().rem_euclid(HUE_SEGMENTS as Float) as usize;
+        let second = (first + 1) % HUE_SEGMENTS;
+        let weight = position - position.floor();
+
+        (0..POLAR_SEGMENTS).map(|polar| {
+            let radius = self.radii[polar * HUE_SEGMENTS + first] * (1.0 - weight)
+                + self.radii[polar * HUE_SEGMENTS + second] * weight;
+            let angle = (polar as f64 + 0.5) * PI / POLAR_SEGMENTS as f64;
+
+            (
+                self.center + radius * angle.cos() as Float,
+                radius * angle.sin() as Float,
+            )
+        }).collect()
+    }
+
+    pub fn hue_slices(&self, step: Float) -> Cgats {
+    //! Export the boundary at every `step` degrees of hue as LAB and LCH
+        let mut cgats = Cgats::new();
+        cgats.meta = DataVec::from(vec![
+            DataLine::from(vec!["CGATS.17".to_string()]),
+            DataLine::from(vec!["DESCRIPTOR".to_string(), "\"Gamut boundary hue slices\"".to_string()]),
+        ]);
+        cgats.fields = vec![
+            Field::SAMPLE_ID,
+            Field::LAB_L, Field::LAB_A, Field::LAB_B,
+            Field::LAB_C, Field::LAB_H,
+        ];
+
+        let count = (360.0 / step.max(1.0)).ceil() as usize;
+        for hue in (0..count).map(|i| i as Float * step.max(1.0)) {
+            let (sin, cos) = hue.to_radians().sin_cos();
+            for (l, c) in self.hue_slice(hue) {
+                let key = cgats.data_map.len();
+                cgats.data_map.insert(key, Sample {
+                    values: [key as Float, l, c * cos, c * sin, c, hue].iter()
+                        .map(|value| CgatsValue::from_float(round_to(*value, 4)))
+                        .collect(),
+                });
+            }
+        }
+
+        cgats.meta.lines.push(DataLine::from(vec![
+            "NUMBER_OF_FIELDS".to_string(), cgats.fields.len().to_string()
+        ]));
+        cgats.meta.lines.push(DataLine::from(vec![
+            "NUMBER_OF_SETS".to_string(), cgats.data_map.len().to_string()
+        ]));
+
+        cgats
+    }
+}
+
+impl fmt::Display for Gamut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Gamut volume: {:0.0}", self.volume())
+    }
+}
+
+impl Cgats {
+    pub fn gamut(&self) -> Result<Gamut> {
+    //! Find the gamut boundary of a data set with device values and LAB (or XYZ)
+        Gamut::from_model(&self.characterization_model()?)
+    }
+}
+
+fn direction(lab: [Float; 3], center: Float) -> (Float, usize) {
+//! Distance from the center and the boundary segment a LAB point falls in
+    let (l, a, b) = ((lab[0] - center) as f64, lab[1] as f64, lab[2] as f64);
+    let radius = (l * l + a * a + b * b).sqrt();
+    if radius <= f64::EPSILON {
+        return (0.0, 0);
+    }
+
+    let polar = (l / radius).clamp(-1.0, 1.0).acos();
+    let hue = b.atan2(a).rem_euclid(2.0 * PI);
+
+    let polar = ((polar / PI * POLAR_SEGMENTS as f64) as usize).min(POLAR_SEGMENTS - 1);
+    let hue = ((hue / (2.0 * PI) * HUE_SEGMENTS as f64) as usize).min(HUE_SEGMENTS - 1);
+
+    (radius as Float, polar * HUE_SEGMENTS + hue)
+}
+
+fn boundary_radii(surface: &[Triangle], center: Float) -> Vec<Float> {
+//! The distance to the outermost crossing of the surface from the center through the
+//! middle of each segment. Segments the surface does not cross take the mean of their neighbors.
+    let origin = [center as f64, 0.0, 0.0];
+    let mut radii: Vec<Option<Float>> = (0..POLAR_SEGMENTS * HUE_SEGMENTS).map(|segment| {
+        let polar = ((segment / HUE_SEGMENTS) as f64 + 0.5) * PI / POLAR_SEGMENTS as f64;
+        let hue = ((segment % HUE_SEGMENTS) as f64 + 0.5) * 2.0 * PI / HUE_SEGMENTS as f64;
+        let ray = [polar.cos(), polar.sin() * hue.cos(), polar.sin() * hue.sin()];
+
+        surface.iter()
+            .filter_map(|triangle| crossing(origin, ray, triangle))
+            .fold(None, |max: Option<f64>, t| Some(max.map_or(t, |max| max.max(t))))
+            .map(|t| t as Float)
+    }).collect();
+
+    while radii.iter().any(Option::is_none) {
+        let filled = radii.clone();
+        if filled.iter().all(Option::is_none) {
+            return vec![0.0; radii.len()];
+        }
+
+        for (segment, radius) in radii.iter_mut().enumerate().filter(|(_, r)| r.is_none()) {
+            let (polar, hue) = (segment / HUE_SEGMENTS, segment % HUE_SEGMENTS);
+            let mut neighbors = vec![
+                polar * HUE_SEGMENTS + (hue + 1) % HUE_SEGMENTS,
+                polar * HUE_SEGMENTS + (hue + HUE_SEGMENTS - 1) % HUE_SEGMENTS,
+            ];
+            if polar > 0 {
+                neighbors.push(segment - HUE_SEGMENTS);
+            }
+            if polar + 1 < POLAR_SEGMENTS {
+                neighbors.push(segment + HUE_SEGMENTS);
+            }
+
+            let known: Vec<Float> = neighbors.iter().filter_map(|n| filled[*n]).collect();
+            if !known.is_empty() {
+                *radius = Some(known.iter().sum::<Float>() / known.len() as Float);
+            }
+        }
+    }
+
+    radii.into_iter().map(|r| r.unwrap_or_default()).collect()
+}
+
+fn crossing(origin: [f64; 3], ray: [f64; 3], triangle: &Triangle) -> Option<f64> {
+//! How far along a ray it crosses a triangle (Möller-Trumbore)
+    let sub = |a: [f64; 3], b: [f64; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let cross = |a: [f64; 3], b: [f64; 3]| [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+
+    let (edge1, edge2) = (sub(triangle[1], triangle[0]), sub(triangle[2], triangle[0]));
+    let p = cross(ray, edge2);
+    let determinant = dot(edge1, p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+
+    let offset = sub(origin, triangle[0]);
+    let u = dot(offset, p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = cross(offset, edge1);
+    let v = dot(ray, q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = dot(edge2, q) / determinant;
+    if t > 0.0 { Some(t) } else { None }
+}
+
+fn volume(radii: &[Float]) -> Float {
+//! Sum the spherical sectors of every segment
+    let hue_angle = 2.0 * PI / HUE_SEGMENTS as f64;
+
+    radii.iter().enumerate().map(|(segment, radius)| {
+        let polar = (segment / HUE_SEGMENTS) as f64;
+        let top = (polar * PI / POLAR_SEGMENTS as f64).cos();
+        let bottom = ((polar + 1.0) * PI / POLAR_SEGMENTS as f64).cos();
+        hue_angle * (top - bottom) * (*radius as f64).powi(3) / 3.0
+    }).sum::<f64>() as Float
+}
+
+#[test]
+fn gamut_volume() -> Result<()> {
+    let crpc1 = Cgats::from_file("reference/ISO15339-CRPC1.txt")?.gamut()?;
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?.gamut()?;
+    println!("CRPC1 {}\nCRPC6 {}", crpc1, crpc6);
+
+    assert!(crpc6.volume() > 2.0 * crpc1.volume());
+    assert!(crpc6.volume() > 300_000.0 && crpc6.volume() < 700_000.0);
+
+    assert!(crpc6.coverage(&crpc1) > 95.0);
+    assert!(crpc1.coverage(&crpc6) < 60.0);
+    assert!(crpc6.intersection_volume(&crpc1) <= crpc1.volume() * 1.01);
+
+    assert!(crpc6.contains(&LabValue { l: 50.0, a: 0.0, b: 0.0 }));
+    assert!(!crpc6.contains(&LabValue { l: 50.0, a: 100.0, b: 100.0 }));
+
+    Ok(())
+}
+
+#[test]
+fn gamut_centers() -> Result<()> {
+    // CRPC6 shrunk to half its size around L* 30, a*b* 0: the L* centers differ
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let mut shrunk = crpc6.clone();
+    let indexes: Vec<usize> = [Field::LAB_L, Field::LAB_A, Field::LAB_B].iter()
+        .map(|field| shrunk.field_index(field).expect("LAB field"))
+        .collect();
+    for sample in shrunk.data_map.values_mut() {
+        for (index, origin) in indexes.iter().zip(&[30.0, 0.0, 0.0]) {
+            let value = sample.values[*index].float.expect("LAB value");
+            sample.values[*index] = CgatsValue::from_float(origin + 0.5 * (value - origin));
+        }
+    }
+
+    let (large, small) = (crpc6.gamut()?, shrunk.gamut()?);
+    assert!((large.center - small.center).abs() > 10.0);
+    assert!((small.volume() / large.volume() - 0.125).abs() < 0.01);
+
+    assert!(large.coverage(&small) > 98.0 && large.coverage(&small) <= 100.0);
+    assert!((small.coverage(&large) - 12.5).abs() < 1.0);
+    assert_eq!(large.coverage(&large), 100.0);
+
+    Ok(())
+}
+
+#[test]
+fn gamut_hull() -> Result<()> {
+    // A sparse data set: only the solids, overprints and paper of CRPC6
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let mut corners = crpc6.clone();
+    corners.data_map = crpc6.data_map.values()
+        .filter(|sample| sample.values[1..5].iter().all(|value| matches!(value.float, Some(v) if v == 0.0 || v == 100.0)))
+        .cloned()
+        .enumerate()
+        .collect();
+
+    // The boundary still reaches out to the hull between the measured corners
+    let sparse = corners.gamut()?;
+    let full = crpc6.gamut()?;
+    assert!(sparse.volume() > 0.8 * full.volume());
+
+    let model = corners.characterization_model()?;
+    assert_eq!(model.sample_count(), 16);
+    let edge = model.lab(&[100.0, 50.0, 0.0, 0.0])?;
+    let inside = |lab: &LabValue, scale: Float| LabValue {
+        l: sparse.center + scale * (lab.l - sparse.center),
+        a: scale * lab.a,
+        b: scale * lab.b,
+    };
+    assert!(sparse.contains(&inside(&edge, 0.9)));
+    assert!(!sparse.contains(&inside(&edge, 1.1)));
+
+    Ok(())
+}
+
+#[test]
+fn gamut_slices() -> Result<()> {
+    let gamut = Cgats::from_file("reference/ISO15339-CRPC6.txt")?.gamut()?;
+
+    let slice = gamut.hue_slice(90.0);
+    assert_eq!(slice.len(), POLAR_SEGMENTS);
+    assert!(slice.first().expect("Slice is empty!").0 > slice.last().expect("Slice is empty!").0);
+    assert!(slice.iter().any(|(_, c)| *c > 70.0));
+
+    let slices = gamut.hue_slices(30.0);
+    assert_eq!(slices.sample_count(), 12 * POLAR_SEGMENTS);
+
+    let temp = test::mktemp()?;
+    slices.write_to_file(&temp)?;
+    let reconstructed = Cgats::from_file(&temp)?;
+    assert_eq!(reconstructed.fields, slices.fields);
+    assert_eq!(reconstructed.sample_count(), slices.sample_count());
+
+    std::fs::remove_file(temp)?;
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use super::*;
+
+use deltae::color::LabValue;
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+// Width and height of each chart in SVG user units
+const CHART_WIDTH: Float = 800.0;
+const CHART_HEIGHT: Float = 300.0;
+const AB_SIZE: Float = 500.0;
+const MARGIN: Float = 40.0;
+
+// Swatches per row and the size of each swatch
+const SWATCH_COLUMNS: usize = 12;
+const SWATCH_SIZE: Float = 64.0;
+
+const PASS_COLOR: &str = "#2e7d32";
+const WARNING_COLOR: &str = "#f9a825";
+const FAIL_COLOR: &str = "#c62828";
+
+/// One compared patch of the report
+#[derive(Debug, Clone)]
+struct Patch {
+    id: String,
+    reference: LabValue,
+    sample: LabValue,
+    de: Float,
+}
+
+/// A self-contained HTML page with inline SVG charts comparing two data sets
+#[derive(Debug)]
+pub struct HtmlReport {
+    title:        String,
+    reference:    Cgats,
+    sample:       Cgats,
+    patches:      Vec<Patch>,
+    report:       DeReport,
+    warning:      Float,
+    limit:        Float,
+    verification: Option<Verification>,
+}
+
+impl HtmlReport {
+    pub fn new(reference: &Cgats, sample: &Cgats, formula: DeltaEFormula) -> Result<HtmlReport> {
+    //! Compare a measured sample with its reference.
+    //! Returns an Error if the data sets cannot be compared.
+        let cgv = CgatsVec::from(vec![reference.clone(), sample.clone()]);
+        let delta = cgv.deltae_formula(formula)?;
+        let report = DeReport::with_options(&delta, &DeReportOptions::new().percentiles(&[50.0, 95.0]))?;
+        let (index, _) = delta.de_formula()?;
+
+        let patches = delta.data_map.iter().filter_map(|(key, values)| Some(Patch {
+            id: reference.field_index(&Field::SAMPLE_ID)
+                .and_then(|id| reference.data_map.get(key)?.values.get(id))
+                .map(|id| id.string.clone())
+                .unwrap_or_else(|| key.to_string()),
+            reference: reference.sample_lab(*key)?,
+            sample: sample.sample_lab(*key)?,
+            de: values.values.get(index)?.float?,
+        })).collect();
+
+        Ok(HtmlReport {
+            title: "Color QC Report".to_string(),
+            reference: reference.clone(),
+            sample: sample.clone(),
+            patches,
+            report,
+            warning: 2.0,
+            limit: 5.0,
+            verification: None,
+        })
+    }
+
+    pub fn with_title(mut self, title: &str) -> HtmlReport {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn with_tolerances(mut self, warning: Float, limit: Float) -> HtmlReport {
+    //! Delta E bars are green up to `warning`, amber up to `limit` and red above it
+        self.warning = warning;
+        self.limit = limit;
+        self
+    }
+
+    pub fn with_verification(mut self, profile: &ToleranceProfile) -> Result<HtmlReport> {
+    //! Include the verdicts of a tolerance profile for the compared data sets. The bar colors
+    //! take the profile's AVERAGE_DE as the warning and MAX_DE (or P95_DE) as the limit when it has them.
+    //! Returns an Error if the profile uses a different Delta E formula than the report.
+        if profile.formula() != self.report.formula() {
+            return Err(Error::Other(format!(
+                "Tolerance profile '{}' uses {}, but the report uses {}!",
+                profile.name(), profile.formula(), self.report.formula(),
+            )));
+        }
+
+        let tolerance = |criterion| profile.tolerances().iter()
+            .find(|(c, _)| *c == criterion)
+            .map(|(_, tolerance)| *tolerance);
+
+        if let Some(warning) = tolerance(Criterion::AverageDe) {
+            self.warning = warning;
+        }
+        if let Some(limit) = tolerance(Criterion::MaxDe).or_else(|| tolerance(Criterion::P95De)) {
+            self.limit = limit;
+        }
+
+        self.verification = Some(profile.verify(&self.reference, &self.sample)?);
+        Ok(self)
+    }
+
+    pub fn report(&self) -> &DeReport {
+        &self.report
+    }
+
+    pub fn render(&self) -> String {
+    //! Render the complete HTML document
+        let mut html = String::new();
+        self.write_html(&mut html).expect("Cannot write to String!");
+        html
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.render().as_bytes())?;
+        Ok(())
+    }
+
+    fn write_html(&self, html: &mut String) -> fmt::Result {
+        writeln!(html, "<!DOCTYPE html>")?;
+        writeln!(html, "<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(html, "<title>{}</title>", escape(&self.title))?;
+        writeln!(html, "<style>\n\
+            body {{ font-family: sans-serif; margin: 2em; color: #222; }}\n\
+            h2 {{ margin-top: 1.5em; }}\n\
+            svg {{ background: #fff; border: 1px solid #ccc; max-width: 100%; height: auto; }}\n\
+            svg text {{ font-size: 11px; fill: #444; }}\n\
+            table {{ border-collapse: collapse; }}\n\
+            td, th {{ border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: right; }}\n\
+            .pass {{ color: {}; }} .fail {{ color: {}; }}\n\
+            </style>\n</head>\n<body>", PASS_COLOR, FAIL_COLOR)?;
+        writeln!(html, "<h1>{}</h1>", escape(&self.title))?;
+
+        if let Some(verification) = &self.verification {
+            self.write_verification(html, verification)?;
+        }
+
+        writeln!(html, "<h2>Summary</h2>\n<pre>{}</pre>", escape(&self.report.to_string()))?;
+        writeln!(html, "<h2>a*b*</h2>")?;
+        self.write_ab_plot(html)?;
+        writeln!(html, "<h2>L*</h2>")?;
+        self.write_lightness_chart(html)?;
+        writeln!(html, "<h2>{}</h2>", escape(&self.report.formula().to_string()))?;
+        self.write_de_chart(html)?;
+        writeln!(html, "<h2>Swatches</h2>")?;
+        self.write_swatches(html)?;
+
+        writeln!(html, "</body>\n</html>")
+    }
+
+    fn write_verification(&self, html: &mut String, verification: &Verification) -> fmt::Result {
+        let class = |verdict: Verdict| if verdict == Verdict::Pass { "pass" } else { "fail" };
+
+        writeln!(html, "<h2>{}: <span class=\"{}\">{}</span></h2>",
+            escape(&verification.profile),
+            class(if verification.passed() { Verdict::Pass } else { Verdict::Fail }),
+            if verification.passed() { Verdict::Pass } else { Verdict::Fail })?;
+        writeln!(html, "<table>\n<tr><th>Criterion</th><th>Value</th><th>Tolerance</th><th>Verdict</th></tr>")?;
+        for result in &verification.results {
+            writeln!(html, "<tr><td>{}</td><td>{}</td><td>{:0.2}</td><td class=\"{}\">{}</td></tr>",
+                result.criterion,
+                result.value.map(|v| format!("{:0.2}", v)).unwrap_or_else(|| "-".to_string()),
+                result.tolerance,
+                class(result.verdict),
+                result.verdict)?;
+        }
+        writeln!(html, "</table>")
+    }
+
+    fn write_ab_plot(&self, html: &mut String) -> fmt::Result {
+        // Square axes from -range to +range in steps of 20
+        let range = self.patches.iter()
+            .flat_map(|p| [p.reference.a, p.reference.b, p.sample.a, p.sample.b])
+            .fold(40.0, |max: Float, v| max.max(v.abs()));
+        let range = (range / 20.0).ceil() * 20.0;
+        let scale = (AB_SIZE - 2.0 * MARGIN) / (2.0 * range);
+        let x = |a: Float| AB_SIZE / 2.0 + a * scale;
+        let y = |b: Float| AB_SIZE / 2.0 - b * scale;
+
+        writeln!(html, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">", AB_SIZE)?;
+
+        let mut tick = -range;
+        while tick <= range {
+            writeln!(html, "<line x1=\"{0:.1}\" y1=\"{1}\" x2=\"{0:.1}\" y2=\"{2}\" stroke=\"#eee\"/>", x(tick), MARGIN, AB_SIZE - MARGIN)?;
+            writeln!(html, "<line x1=\"{1}\" y1=\"{0:.1}\" x2=\"{2}\" y2=\"{0:.1}\" stroke=\"#eee\"/>", y(tick), MARGIN, AB_SIZE - MARGIN)?;
+            writeln!(html, "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>", x(tick), AB_SIZE - MARGIN + 14.0, tick)?;
+            writeln!(html, "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>", MARGIN - 4.0, y(tick) + 4.0, tick)?;
+            tick += 20.0;
+        }
+        writeln!(html, "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"#999\"/>", x(0.0), MARGIN, AB_SIZE - MARGIN)?;
+        writeln!(html, "<line x1=\"{1}\" y1=\"{0}\" x2=\"{2}\" y2=\"{0}\" stroke=\"#999\"/>", y(0.0), MARGIN, AB_SIZE - MARGIN)?;
+        writeln!(html, "<text x=\"{}\" y=\"{}\">a*</text>", AB_SIZE - MARGIN + 4.0, y(0.0) + 4.0)?;
+        writeln!(html, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">b*</text>", x(0.0), MARGIN - 6.0)?;
+
+        for patch in &self.patches {
+            let (x0, y0) = (x(patch.reference.a), y(patch.reference.b));
+            let (x1, y1) = (x(patch.sample.a), y(patch.sample.b));
+            writeln!(html, "<g><title>{}: {:0.2}</title>\
+                <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#666\"/>\
+                <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"none\" stroke=\"{}\"/>\
+                <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\" stroke=\"#333\" stroke-width=\"0.5\"/></g>",
+                escape(&patch.id), patch.de,
+                x0, y0, x1, y1,
+                x0, y0, hex(&patch.reference),
+                x1, y1, hex(&patch.sample))?;
+        }
+
+        writeln!(html, "</svg>")
+    }
+
+    fn write_lightness_chart(&self, html: &mut String) -> fmt::Result {
+        let step = self.patch_step();
+        let y = |l: Float| CHART_HEIGHT - MARGIN - l.clamp(0.0, 100.0) / 100.0 * (CHART_HEIGHT - 2.0 * MARGIN);
+        let points = |lab: fn(&Patch) -> Float| self.patches.iter().enumerate()
+            .map(|(i, patch)| format!("{:.1},{:.1}", MARGIN + (i as Float + 0.5) * step, y(lab(patch))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.write_chart_open(html)?;
+        for l in (0..=100).step_by(25) {
+            let l = l as Float;
+            writeln!(html, "<line x1=\"{0}\" y1=\"{1:.1}\" x2=\"{2}\" y2=\"{1:.1}\" stroke=\"#eee\"/>", MARGIN, y(l), CHART_WIDTH - MARGIN)?;
+            writeln!(html, "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>", MARGIN - 4.0, y(l) + 4.0, l)?;
+        }
+        writeln!(html, "<polyline points=\"{}\" fill=\"none\" stroke=\"#999\" stroke-width=\"2\"/>", points(|p| p.reference.l))?;
+        writeln!(html, "<polyline points=\"{}\" fill=\"none\" stroke=\"#1565c0\" stroke-width=\"1\"/>", points(|p| p.sample.l))?;
+        writeln!(html, "<text x=\"{}\" y=\"{}\">reference</text>", CHART_WIDTH - MARGIN - 120.0, MARGIN - 10.0)?;
+        writeln!(html, "<text x=\"{}\" y=\"{}\" style=\"fill: #1565c0\">measured</text>", CHART_WIDTH - MARGIN - 50.0, MARGIN - 10.0)?;
+        writeln!(html, "</svg>")
+    }
+
+    fn write_de_chart(&self, html: &mut String) -> fmt::Result {
+        let step = self.patch_step();
+        let top = self.patches.iter().fold(self.limit * 1.2, |max, patch| max.max(patch.de));
+        let y = |de: Float| CHART_HEIGHT - MARGIN - de / top * (CHART_HEIGHT - 2.0 * MARGIN);
+
+        self.write_chart_open(html)?;
+        for (i, patch) in self.patches.iter().enumerate() {
+            let color = if patch.de <= self.warning {
+                PASS_COLOR
+            } else if patch.de <= self.limit {
+                WARNING_COLOR
+            } else {
+                FAIL_COLOR
+            };
+            writeln!(html, "<rect x=\"{:.2}\" y=\"{:.1}\" width=\"{:.2}\" height=\"{:.1}\" fill=\"{}\"><title>{}: {:0.2}</title></rect>",
+                MARGIN + i as Float * step, y(patch.de), (step * 0.8).max(0.5), y(0.0) - y(patch.de),
+                color, escape(&patch.id), patch.de)?;
+        }
+        for (tolerance, color) in [(self.warning, WARNING_COLOR), (self.limit, FAIL_COLOR)] {
+            writeln!(html, "<line x1=\"{0}\" y1=\"{1:.1}\" x2=\"{2}\" y2=\"{1:.1}\" stroke=\"{3}\" stroke-dasharray=\"4 3\"/>", MARGIN, y(tolerance), CHART_WIDTH - MARGIN, color)?;
+            writeln!(html, "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>", MARGIN - 4.0, y(tolerance) + 4.0, tolerance)?;
+        }
+        writeln!(html, "<line x1=\"{0}\" y1=\"{1:.1}\" x2=\"{2}\" y2=\"{1:.1}\" stroke=\"#999\"/>", MARGIN, y(0.0), CHART_WIDTH - MARGIN)?;
+        writeln!(html, "</svg>")
+    }
+
+    fn write_swatches(&self, html: &mut String) -> fmt::Result {
+        let rows = self.patches.len().div_ceil(SWATCH_COLUMNS);
+        let width = SWATCH_COLUMNS as Float * SWATCH_SIZE;
+        let height = rows as Float * (SWATCH_SIZE + 16.0);
+
+        writeln!(html, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", width, height)?;
+        for (i, patch) in self.patches.iter().enumerate() {
+            let x = (i % SWATCH_COLUMNS) as Float * SWATCH_SIZE;
+            let y = (i / SWATCH_COLUMNS) as Float * (SWATCH_SIZE + 16.0);
+            let half = SWATCH_SIZE / 2.0;
+            writeln!(html, "<g><title>{id}: {de:0.2}</title>\
+                <rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" fill=\"{reference}\"/>\
+                <rect x=\"{x}\" y=\"{y2}\" width=\"{w}\" height=\"{h}\" fill=\"{sample}\"/>\
+                <text x=\"{tx}\" y=\"{ty}\" text-anchor=\"middle\">{id} ({de:0.1})</text></g>",
+                id = escape(&patch.id), de = patch.de,
+                x = x + 2.0, y = y + 2.0, y2 = y + 2.0 + half - 2.0, w = SWATCH_SIZE - 4.0, h = half - 2.0,
+                reference = hex(&patch.reference), sample = hex(&patch.sample),
+                tx = x + half, ty = y + SWATCH_SIZE + 10.0)?;
+        }
+        writeln!(html, "</svg>")
+    }
+
+    fn write_chart_open(&self, html: &mut String) -> fmt::Result {
+        writeln!(html, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", CHART_WIDTH, CHART_HEIGHT)
+    }
+
+    fn patch_step(&self) -> Float {
+    // Horizontal space for each patch in the charts
+        (CHART_WIDTH - 2.0 * MARGIN) / self.patches.len().max(1) as Float
+    }
+}
+
+impl fmt::Display for HtmlReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+fn hex(lab: &LabValue) -> String {
+    let [r, g, b] = color::lab_to_srgb(lab);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn html_report() -> Result<()> {
+    let reference = Cgats::from_file("test_files/colorburst2.lin")?;
+    let sample = Cgats::from_file("test_files/colorburst3.lin")?;
+
+    let html = HtmlReport::new(&reference, &sample, DeltaEFormula::default())?
+        .with_title("Press <check>")
+        .with_tolerances(2.0, 6.0)
+        .render();
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.trim_end().ends_with("</html>"));
+    assert!(html.contains("<title>Press &lt;check&gt;</title>"));
+    assert_eq!(html.matches("<svg ").count(), 4);
+    assert!(!html.contains("src=") && !html.contains("href="));
+    assert!(html.contains(PASS_COLOR) && html.contains(WARNING_COLOR) && html.contains(FAIL_COLOR));
+    assert!(html.contains("Number of Samples: 126"));
+
+    let crpc5 = Cgats::from_file("reference/ISO15339-CRPC5.txt")?;
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let profile = ToleranceProfile::iso_12647_2();
+    let mismatch = HtmlReport::new(&crpc6, &crpc5, DeltaEFormula::De1976)?
+        .with_verification(&profile.clone().with_formula(DeltaEFormula::De1994T));
+    assert!(mismatch.is_err());
+
+    let verified = HtmlReport::new(&crpc6, &crpc5, profile.formula())?
+        .with_verification(&profile)?;
+    assert_eq!(verified.limit, 6.0);
+
+    let verification = verified.verification.as_ref().unwrap();
+    assert_eq!(verification.formula, verified.report.formula());
+    let average = verification.result(Criterion::AverageDe).and_then(|r| r.value).unwrap();
+    assert!((average - verified.report.overall().mean).abs() < 1e-4);
+
+    let temp = test::mktemp()?;
+    verified.write_to_file(&temp)?;
+    let written = std::fs::read_to_string(&temp)?;
+    assert!(written.contains("<td>PAPER_DE</td>"));
+    assert!(written.contains("<span class=\"fail\">FAIL</span>"));
+
+    std::fs::remove_file(temp)?;
+    Ok(())
+}
@@ -0,0 +1,435 @@
+use super::*;
+
+use crate::model::solve;
+use deltae::color::LabValue;
+use deltae::{DeltaE, DEMethod};
+use std::fmt;
+
+// Device value step used to estimate the local derivatives of the forward model
+const JACOBIAN_STEP: Float = 0.5;
+
+// Iteration limit for one damped least squares solve
+const MAX_ITERATIONS: usize = 40;
+
+// Number of measured samples used as starting points for the solver
+const STARTING_POINTS: usize = 3;
+
+/// How the black channel is chosen when solving for CMYK
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum BlackGeneration {
+    /// Black is solved along with the other channels
+    #[default]
+    Free,
+    /// Black is held at a fixed value (0...100)
+    Fixed(Float),
+    /// Black is set from the L* of the target through a Curve (L* -> K %),
+    /// e.g. no black in the highlights and more of it towards the shadows
+    Curve(Curve),
+    /// Targets with a chroma at or below the threshold are printed with black only;
+    /// all other targets are solved freely
+    KOnlyGray { chroma: Float },
+}
+
+/// The device values found for a target LAB
+#[derive(Debug, Clone, PartialEq)]
+pub struct InverseResult {
+    pub target: LabValue,
+    pub device: Vec<Float>,
+    /// The LAB the forward model predicts for `device`
+    pub lab: LabValue,
+    pub delta_e: Float,
+    pub in_gamut: bool,
+}
+
+impl fmt::Display for InverseResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = self.device.iter()
+            .map(|value| format!("{:0.2}", value))
+            .collect::<Vec<_>>()
+            .join("\t");
+
+        write!(f, "{}\tDE: {:0.4}{}", device, self.delta_e,
+            if self.in_gamut { "" } else { "\t(out of gamut)" })
+    }
+}
+
+/// Solves for the device values that reproduce a target LAB through a CharacterizationModel
+#[derive(Debug, Clone, PartialEq)]
+pub struct InverseModel {
+    model: CharacterizationModel,
+    black: BlackGeneration,
+    max_tac: Option<Float>,
+    method: DEMethod,
+    tolerance: Float,
+}
+
+impl InverseModel {
+    pub fn new(model: CharacterizationModel) -> InverseModel {
+    //! Create an unconstrained inverse model.
+    //! Targets further than DE2000 1.0 from the result are out of gamut.
+        InverseModel {
+            model,
+            black: BlackGeneration::default(),
+            max_tac: None,
+            method: DEMethod::DE2000,
+            tolerance: 1.0,
+        }
+    }
+
+    pub fn from_cgats(cgats: &Cgats) -> Result<InverseModel> {
+    //! Create an inverse model from characterization data
+        Ok(InverseModel::new(CharacterizationModel::new(cgats)?))
+    }
+
+    pub fn with_black_generation(mut self, black: BlackGeneration) -> InverseModel {
+    //! Constrain the black channel. Only applies to CMYK models.
+        self.black = black;
+        self
+    }
+
+    pub fn with_max_tac(mut self, max_tac: Float) -> InverseModel {
+    //! Limit the total area coverage (sum of all device values)
+        self.max_tac = Some(max_tac);
+        self
+    }
+
+    pub fn with_tolerance(mut self, method: DEMethod, tolerance: Float) -> InverseModel {
+    //! Set the Delta E method and the largest error still considered in gamut
+        self.method = method;
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn model(&self) -> &CharacterizationModel {
+    //! The forward model being inverted
+        &self.model
+    }
+
+    pub fn solve(&self, target: &LabValue) -> Result<InverseResult> {
+    //! Find the device values whose predicted LAB is closest to the target.
+    //! Returns an Error if a black constraint is used without a CMYK model.
+        let (fixed, free) = self.constraints(target)?;
+
+        let mut best: Option<(Vec<Float>, LabValue, Float)> = None;
+        for start in self.starting_points(target, &fixed, &free)? {
+            let (device, lab) = self.refine(target, start, &free)?;
+            let distance = DeltaE::new(target, &lab, DEMethod::DE1976).value;
+
+            if best.as_ref().map(|(_, _, d)| distance < *d).unwrap_or(true) {
+                best = Some((device, lab, distance));
+            }
+
+            if distance < 0.01 {
+                break;
+            }
+        }
+
+        let (device, lab, _) = best.ok_or(Error::IncompleteData)?;
+        let delta_e = DeltaE::new(target, &lab, self.method).value;
+
+        Ok(InverseResult {
+            target: target.clone(),
+            device: device.iter().map(|value| round_to(*value, 2)).collect(),
+            lab,
+            delta_e,
+            in_gamut: delta_e <= self.tolerance,
+        })
+    }
+
+    pub fn convert(&self, cgats: &Cgats) -> Result<Vec<InverseResult>> {
+    //! Solve every sample of a CGATS object with LAB (or XYZ)
+        cgats.data_map.keys()
+            .map(|key| {
+                let lab = cgats.sample_lab(*key).ok_or(Error::IncompleteData)?;
+                self.solve(&lab)
+            })
+            .collect()
+    }
+
+    pub fn convert_cgats(&self, cgats: &Cgats) -> Result<Cgats> {
+    //! Add the device values and the achieved Delta E to every sample of a
+    //! CGATS object with LAB (or XYZ). Existing device values are replaced.
+        let results = self.convert(cgats)?;
+
+        let mut converted = cgats.clone();
+        let de_field = Field::from_de_method(self.method);
+        let mut added = self.model.device_fields().clone();
+        added.push(de_field);
+
+        for field in &added {
+            if converted.field_index(field).is_none() {
+                converted.fields.push(*field);
+                for sample in converted.data_map.values_mut() {
+                    sample.values.push(CgatsValue::default());
+                }
+            }
+        }
+
+        let indexes: Vec<usize> = added.iter()
+            .map(|field| converted.field_index(field).expect("Field was just added!"))
+            .collect();
+
+        for (sample, result) in converted.data_map.values_mut().zip(&results) {
+            let values = result.device.iter().chain(std::iter::once(&result.delta_e));
+            for (index, value) in indexes.iter().zip(values) {
+                sample.values[*index] = CgatsValue::from_float(round_to(*value, 4));
+            }
+        }
+
+        converted.meta.meta_renumber_fields(converted.fields.len());
+
+        Ok(converted)
+    }
+
+    fn constraints(&self, target: &LabValue) -> Result<(Vec<Option<Float>>, Vec<usize>)> {
+    // Fixed device values (None if free) and the indexes of the free channels
+        let fields = self.model.device_fields();
+        let mut fixed: Vec<Option<Float>> = vec![None; fields.len()];
+
+        if self.black != BlackGeneration::Free {
+            let black = fields.iter().position(|field| *field == Field::CMYK_K)
+                .ok_or_else(|| Error::Other("Black generation requires a CMYK model!".to_string()))?;
+
+            match &self.black {
+                BlackGeneration::Fixed(k) => fixed[black] = Some(k.clamp(0.0, 100.0)),
+                BlackGeneration::Curve(curve) => fixed[black] = Some(curve.interpolate(target.l).clamp(0.0, 100.0)),
+                BlackGeneration::KOnlyGray { chroma } => {
+                    if target.a.hypot(target.b) <= *chroma {
+                        for (index, value) in fixed.iter_mut().enumerate() {
+                            if index != black {
+                                *value = Some(0.0);
+                            }
+                        }
+                    }
+                },
+                BlackGeneration::Free => (),
+            }
+        }
+
+        let free = (0..fields.len()).filter(|index| fixed[*index].is_none()).collect();
+
+        Ok((fixed, free))
+    }
+
+    fn starting_points(&self, target: &LabValue, fixed: &[Option<Float>], free: &[usize]) -> Result<Vec<Vec<Float>>> {
+    // The measured samples closest to the target, moved onto the constraints
+        let mut candidates: Vec<(Float, Vec<Float>)> = self.model.samples()
+            .map(|(device, lab)| {
+                let lab = LabValue { l: lab[0], a: lab[1], b: lab[2] };
+                let mut device: Vec<Float> = device.iter().zip(fixed)
+                    .map(|(value, fixed)| fixed.unwrap_or(*value))
+                    .collect();
+                self.limit(&mut device, free);
+                (DeltaE::new(target, &lab, DEMethod::DE1976).value, device)
+            })
+            .collect();
+
+        candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Delta E is NaN!"));
+        candidates.dedup_by(|a, b| a.1 == b.1);
+
+        Ok(candidates.into_iter().take(STARTING_POINTS).map(|(_, device)| device).collect())
+    }
+
+    fn refine(&self, target: &LabValue, mut device: Vec<Float>, free: &[usize]) -> Result<(Vec<Float>, LabValue)> {
+    // Levenberg-Marquardt on the LAB residual, projected onto the constraints after every step
+        let mut lab = self.model.lab(&device)?;
+        let mut error = residual(target, &lab);
+        let mut damping = 1e-3;
+
+        if free.is_empty() {
+            return Ok((device, lab));
+        }
+
+        for _ in 0..MAX_ITERATIONS {
+            let jacobian = self.jacobian(&device, &lab, free)?;
+            let residuals = [
+                (target.l - lab.l) as f64,
+                (target.a - lab.a) as f64,
+                (target.b - lab.b) as f64,
+            ];
+
+            let size = free.len();
+            let mut improved = false;
+
+            while damping < 1e6 {
+                let mut normal = vec![vec![0.0f64; size + 1]; size];
+                for i in 0..size {
+                    for j in 0..size {
+                        normal[i][j] = (0..3).map(|c| jacobian[c][i] * jacobian[c][j]).sum();
+                    }
+                    normal[i][i] += damping * (normal[i][i] + 1e-6);
+                    normal[i][size] = (0..3).map(|c| jacobian[c][i] * residuals[c]).sum();
+                }
+
+                let step = match solve(normal, size) {
+                    Some(step) => step,
+                    None => break,
+                };
+
+                let mut candidate = device.clone();
+                for (index, delta) in free.iter().zip(&step) {
+                    candidate[*index] += delta[0] as Float;
+                }
+                self.limit(&mut candidate, free);
+
+                let candidate_lab = self.model.lab(&candidate)?;
+                let candidate_error = residual(target, &candidate_lab);
+
+                if candidate_error < error {
+                    improved = (error - candidate_error) > 1e-6;
+                    device = candidate;
+                    lab = candidate_lab;
+                    error = candidate_error;
+                    damping = (damping / 10.0).max(1e-7);
+                    break;
+                }
+
+                damping *= 10.0;
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        Ok((device, lab))
+    }
+
+    fn jacobian(&self, device: &[Float], lab: &LabValue, free: &[usize]) -> Result<Vec<Vec<f64>>> {
+    // d(L, a, b) / d(free channel), by one-sided finite differences pointing into the device range
+        let mut jacobian = vec![vec![0.0f64; free.len()]; 3];
+
+        for (column, index) in free.iter().enumerate() {
+            let step = if device[*index] + JACOBIAN_STEP > 100.0 { -JACOBIAN_STEP } else { JACOBIAN_STEP };
+            let mut moved = device.to_vec();
+            moved[*index] += step;
+
+            let moved_lab = self.model.lab(&moved)?;
+            jacobian[0][column] = ((moved_lab.l - lab.l) / step) as f64;
+            jacobian[1][column] = ((moved_lab.a - lab.a) / step) as f64;
+            jacobian[2][column] = ((moved_lab.b - lab.b) / step) as f64;
+        }
+
+        Ok(jacobian)
+    }
+
+    fn limit(&self, device: &mut [Float], free: &[usize]) {
+    // Clamp the free channels to 0...100 and remove any coverage above the TAC limit
+        for index in free {
+            device[*index] = device[*index].clamp(0.0, 100.0);
+        }
+
+        let max_tac = match self.max_tac {
+            Some(max_tac) => max_tac,
+            None => return,
+        };
+
+        // Take the excess evenly from the free channels that still have coverage
+        for _ in 0..free.len() {
+            let excess = device.iter().sum::<Float>() - max_tac;
+            let inked: Vec<usize> = free.iter().copied().filter(|index| device[*index] > 0.0).collect();
+            if excess <= 1e-4 || inked.is_empty() {
+                return;
+            }
+
+            let share = excess / inked.len() as Float;
+            for index in inked {
+                device[index] = (device[index] - share).max(0.0);
+            }
+        }
+    }
+}
+
+impl Cgats {
+    pub fn inverse_model(&self) -> Result<InverseModel> {
+    //! Build an inverse LAB -> device model from this characterization data
+        InverseModel::from_cgats(self)
+    }
+}
+
+fn residual(target: &LabValue, lab: &LabValue) -> Float {
+    (target.l - lab.l).powi(2) + (target.a - lab.a).powi(2) + (target.b - lab.b).powi(2)
+}
+
+#[test]
+fn inverse_roundtrip() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let inverse = crpc6.inverse_model()?;
+
+    let target = crpc6.characterization_model()?.lab(&[37.0, 12.0, 5.0, 0.0])?;
+    let result = inverse.solve(&target)?;
+    println!("{}", result);
+    assert!(result.in_gamut);
+    assert!(result.delta_e < 0.5);
+
+    // Far more saturated than any process color
+    let result = inverse.solve(&LabValue { l: 50.0, a: 100.0, b: 100.0 })?;
+    println!("{}", result);
+    assert!(!result.in_gamut);
+
+    Ok(())
+}
+
+#[test]
+fn inverse_constraints() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let gray = LabValue { l: 60.0, a: 0.0, b: -1.0 };
+
+    let fixed = crpc6.inverse_model()?
+        .with_black_generation(BlackGeneration::Fixed(20.0))
+        .solve(&gray)?;
+    assert_eq!(fixed.device[3], 20.0);
+    assert!(fixed.in_gamut);
+
+    let k_only = crpc6.inverse_model()?
+        .with_black_generation(BlackGeneration::KOnlyGray { chroma: 3.0 })
+        .solve(&gray)?;
+    assert_eq!(&k_only.device[..3], &[0.0, 0.0, 0.0]);
+    assert!(k_only.device[3] > 30.0);
+
+    // Black follows the lightness of the target
+    let curve = Curve::new(vec![(20.0, 80.0), (50.0, 20.0), (70.0, 0.0), (100.0, 0.0)])?;
+    let generated = crpc6.inverse_model()?.with_black_generation(BlackGeneration::Curve(curve.clone()));
+    let mut previous = -1.0;
+    for l in &[85.0, 65.0, 50.0, 35.0, 25.0] {
+        let result = generated.solve(&LabValue { l: *l, a: 0.0, b: -1.0 })?;
+        assert!((result.device[3] - curve.interpolate(*l)).abs() < 0.01);
+        assert!(result.device[3] >= previous);
+        assert!(result.in_gamut);
+        previous = result.device[3];
+    }
+    assert!(previous > 60.0);
+
+    let dark = LabValue { l: 15.0, a: 0.0, b: 0.0 };
+    let limited = crpc6.inverse_model()?.with_max_tac(260.0).solve(&dark)?;
+    assert!(limited.device.iter().sum::<Float>() <= 260.01);
+
+    Ok(())
+}
+
+#[test]
+fn inverse_convert() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let inverse = crpc6.inverse_model()?;
+
+    let mut targets = Cgats::new();
+    targets.fields = vec![Field::SAMPLE_ID, Field::LAB_L, Field::LAB_A, Field::LAB_B];
+    for (key, lab) in [[95.0, 1.0, -4.0], [56.0, -37.0, -50.0], [48.0, 74.0, -3.0]].iter().enumerate() {
+        targets.data_map.insert(key, Sample {
+            values: std::iter::once(key as Float).chain(lab.iter().copied())
+                .map(CgatsValue::from_float)
+                .collect(),
+        });
+    }
+
+    let converted = inverse.convert_cgats(&targets)?;
+    println!("{}", converted);
+
+    assert_eq!(converted.fields.len(), 9);
+    assert_eq!(converted.get_float(0, &Field::CMYK_C), Some(0.0));
+    assert!(converted.get_float(1, &Field::CMYK_C).expect("Cyan was added!") > 90.0);
+    assert!(converted.get_float(2, &Field::CMYK_M).expect("Magenta was added!") > 90.0);
+    assert!(converted.get_float(2, &Field::DE_2000).expect("DE was added!") < 1.0);
+
+    Ok(())
+}
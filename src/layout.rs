@@ -0,0 +1,311 @@
+use super::*;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Patch names with more letters are words, not rows (ZZZ is row 18277)
+const MAX_ROW_LETTERS: usize = 3;
+
+/// The order in which the patches of a chart are scanned or listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScanOrder {
+    /// Row by row, left to right
+    #[default]
+    RowMajor,
+    /// Column by column, top to bottom
+    ColumnMajor,
+}
+
+impl FromStr for ScanOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ScanOrder> {
+        match s.trim().to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "row" | "rows" | "rowmajor" => Ok(ScanOrder::RowMajor),
+            "column" | "columns" | "columnmajor" => Ok(ScanOrder::ColumnMajor),
+            _ => Err(Error::Other(format!("Unknown scan order: '{}'", s))),
+        }
+    }
+}
+
+/// The row and column of a patch on the printed chart, counted from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PatchPosition {
+    pub row: usize,
+    pub column: usize,
+}
+
+impl PatchPosition {
+    pub fn new(row: usize, column: usize) -> PatchPosition {
+        PatchPosition { row, column }
+    }
+
+    pub fn from_name(name: &str) -> Option<PatchPosition> {
+    //! Parse an alphanumeric patch name: the letters are the row (A, B, ... Z, AA, AB, ...)
+    //! and the number is the column, so "B12" is row 1, column 11.
+    //! Names with more than 3 letters, like "Cyan100", are not positions.
+        let name = name.trim().trim_matches('"');
+        let split = name.find(|c: char| !c.is_ascii_alphabetic())?;
+        let (letters, number) = name.split_at(split);
+        if letters.is_empty() || letters.len() > MAX_ROW_LETTERS || !number.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let row = letters.to_ascii_uppercase().bytes()
+            .fold(0, |row, letter| row * 26 + (letter - b'A') as usize + 1) - 1;
+        let column = number.parse::<usize>().ok()?.checked_sub(1)?;
+
+        Some(PatchPosition { row, column })
+    }
+
+    pub fn name(&self) -> String {
+    //! The alphanumeric patch name, e.g. "B12"
+        let mut letters = Vec::new();
+        let mut row = self.row + 1;
+        while row > 0 {
+            letters.push(b'A' + ((row - 1) % 26) as u8);
+            row = (row - 1) / 26;
+        }
+        letters.reverse();
+
+        format!("{}{}", String::from_utf8_lossy(&letters), self.column + 1)
+    }
+}
+
+impl fmt::Display for PatchPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&self.name())
+    }
+}
+
+/// The position of each sample on a printed chart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChartLayout {
+    rows: usize,
+    columns: usize,
+    positions: BTreeMap<usize, PatchPosition>,
+}
+
+impl ChartLayout {
+    pub fn new(samples: usize, row_length: usize, order: ScanOrder) -> Result<ChartLayout> {
+    //! An explicit layout of `samples` patches, `row_length` to a row,
+    //! with the samples listed in scan `order`
+        if row_length == 0 {
+            return Err(Error::Other("The row length of a chart must not be zero!".to_string()));
+        }
+
+        let rows = samples.div_ceil(row_length);
+        let positions = (0..samples)
+            .map(|key| {
+                let position = match order {
+                    ScanOrder::RowMajor => PatchPosition::new(key / row_length, key % row_length),
+                    ScanOrder::ColumnMajor => PatchPosition::new(key % rows, key / rows),
+                };
+                (key, position)
+            })
+            .collect();
+
+        Ok(ChartLayout { rows, columns: row_length, positions })
+    }
+
+    pub fn from_cgats(cgats: &Cgats) -> Result<ChartLayout> {
+    //! The layout from alphanumeric SAMPLE_NAMEs (e.g. "A12"), or else from
+    //! LGOROWLENGTH with the samples in row-major order
+        if let Some(layout) = ChartLayout::from_names(cgats)? {
+            return Ok(layout);
+        }
+
+        let row_length = cgats.keyword("LGOROWLENGTH")
+            .and_then(|length| length.parse::<usize>().ok())
+            .ok_or_else(|| Error::Other("Cannot find LGOROWLENGTH or patch names!".to_string()))?;
+
+        ChartLayout::from_row_length(cgats, row_length)
+    }
+
+    pub fn from_row_length(cgats: &Cgats, row_length: usize) -> Result<ChartLayout> {
+    //! The samples in rows of `row_length`, in the order of the data
+        let layout = ChartLayout::new(cgats.sample_count(), row_length, ScanOrder::RowMajor)?;
+        let positions = cgats.data_map.keys()
+            .zip(layout.positions.values())
+            .map(|(key, position)| (*key, *position))
+            .collect();
+
+        Ok(ChartLayout { positions, ..layout })
+    }
+
+    fn from_names(cgats: &Cgats) -> Result<Option<ChartLayout>> {
+        let index = match cgats.field_index(&Field::SAMPLE_NAME) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let mut positions = BTreeMap::new();
+        for (key, sample) in cgats.data_map.iter() {
+            let position = match sample.values.get(index).and_then(|name| PatchPosition::from_name(&name.string)) {
+                Some(position) => position,
+                None => return Ok(None),
+            };
+            positions.insert(*key, position);
+        }
+
+        let layout = ChartLayout::from_positions(positions)?;
+        Ok(Some(layout))
+    }
+
+    pub fn from_positions(positions: BTreeMap<usize, PatchPosition>) -> Result<ChartLayout> {
+    //! An explicit layout from the position of each sample key.
+    //! Returns an Error if two samples share a position.
+        let mut seen = std::collections::BTreeSet::new();
+        if let Some(position) = positions.values().find(|position| !seen.insert(**position)) {
+            return Err(Error::Other(format!("Two patches at position {}!", position)));
+        }
+
+        let rows = positions.values().map(|p| p.row + 1).max().unwrap_or_default();
+        let columns = positions.values().map(|p| p.column + 1).max().unwrap_or_default();
+
+        Ok(ChartLayout { rows, columns, positions })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+    //! The number of patches in a row (LGOROWLENGTH)
+        self.columns
+    }
+
+    pub fn position(&self, key: usize) -> Option<PatchPosition> {
+    //! The position of the sample at `key`
+        self.positions.get(&key).copied()
+    }
+
+    pub fn sample_at(&self, position: PatchPosition) -> Option<usize> {
+    //! The key of the sample at a position
+        self.positions.iter()
+            .find(|(_, p)| **p == position)
+            .map(|(key, _)| *key)
+    }
+
+    pub fn scan_order(&self, order: ScanOrder) -> Vec<usize> {
+    //! The sample keys in scan order
+        let mut keys: Vec<(PatchPosition, usize)> = self.positions.iter()
+            .map(|(key, position)| (*position, *key))
+            .collect();
+        keys.sort_by_key(|(position, _)| match order {
+            ScanOrder::RowMajor => (position.row, position.column),
+            ScanOrder::ColumnMajor => (position.column, position.row),
+        });
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
+
+    pub fn neighbours(&self, key: usize) -> Vec<usize> {
+    //! The keys of the patches above, left of, right of and below a sample
+        let position = match self.position(key) {
+            Some(position) => position,
+            None => return Vec::new(),
+        };
+        let (row, column) = (position.row, position.column);
+
+        [
+            row.checked_sub(1).map(|row| PatchPosition::new(row, column)),
+            column.checked_sub(1).map(|column| PatchPosition::new(row, column)),
+            Some(PatchPosition::new(row, column + 1)),
+            Some(PatchPosition::new(row + 1, column)),
+        ]
+        .iter()
+        .flatten()
+        .filter_map(|position| self.sample_at(*position))
+        .collect()
+    }
+}
+
+impl Cgats {
+    pub fn layout(&self) -> Result<ChartLayout> {
+    //! The chart layout from the patch names or LGOROWLENGTH
+        ChartLayout::from_cgats(self)
+    }
+
+    pub fn reorder(&self, layout: &ChartLayout, order: ScanOrder) -> Cgats {
+    //! Re-order the samples to the scan order of a chart layout.
+    //! Samples that are not in the layout follow in their original order.
+        let mut cgats = self.derive();
+
+        let keys = layout.scan_order(order).into_iter()
+            .filter(|key| self.data_map.contains_key(key))
+            .chain(self.data_map.keys().copied().filter(|key| layout.position(*key).is_none()));
+
+        for (index, key) in keys.enumerate() {
+            cgats.data_map.insert(index, self.data_map[&key].clone());
+        }
+
+        cgats.set_keyword("LGOROWLENGTH", &layout.columns().to_string());
+
+        cgats
+    }
+}
+
+#[test]
+fn patch_names() {
+    assert_eq!(PatchPosition::from_name("A1"), Some(PatchPosition::new(0, 0)));
+    assert_eq!(PatchPosition::from_name("B12"), Some(PatchPosition::new(1, 11)));
+    assert_eq!(PatchPosition::from_name("aa3"), Some(PatchPosition::new(26, 2)));
+    assert_eq!(PatchPosition::from_name("A0"), None);
+    assert_eq!(PatchPosition::from_name("12"), None);
+    assert_eq!(PatchPosition::from_name("Cyan"), None);
+    assert_eq!(PatchPosition::from_name("ZZZ1"), Some(PatchPosition::new(18277, 0)));
+    assert_eq!(PatchPosition::from_name("Cyan100"), None);
+    assert_eq!(PatchPosition::from_name("MagentaSolidPatch100"), None);
+    assert_eq!(PatchPosition::new(26, 2).name(), "AA3");
+    assert_eq!(PatchPosition::new(25, 0).to_string(), "Z1");
+}
+
+#[test]
+fn p2p51_layout() -> Result<()> {
+    let p2p = Cgats::from_file("reference/P2P51.txt")?;
+    let layout = p2p.layout()?;
+
+    assert_eq!((layout.rows(), layout.columns()), (25, 12));
+    assert_eq!(layout.position(0), Some(PatchPosition::new(0, 11)));
+    assert_eq!(layout.sample_at(PatchPosition::new(1, 11)), Some(1));
+
+    // The data is listed column by column; re-order it row by row
+    let rows = p2p.reorder(&layout, ScanOrder::RowMajor);
+    let names = |cgats: &Cgats, keys: std::ops::Range<usize>| -> Vec<String> {
+        keys.map(|key| cgats.data_map[&key].values[1].string.clone()).collect()
+    };
+    assert_eq!(names(&rows, 0..3), vec!["A1", "A2", "A3"]);
+    assert_eq!(names(&rows, 12..13), vec!["B1"]);
+    assert_eq!(rows.keyword("LGOROWLENGTH").as_deref(), Some("12"));
+    assert_eq!(rows.sample_count(), p2p.sample_count());
+
+    let columns = rows.reorder(&rows.layout()?, ScanOrder::ColumnMajor);
+    assert_eq!(names(&columns, 0..3), vec!["A1", "B1", "C1"]);
+    assert_eq!(columns.data_map, p2p.reorder(&layout, ScanOrder::ColumnMajor).data_map);
+
+    Ok(())
+}
+
+#[test]
+fn row_length_layout() -> Result<()> {
+    let xrite = Cgats::from_file("test_files/xrite0.txt")?;
+    let mut cgats = xrite.clone();
+    cgats.fields[0] = Field::SAMPLE_ID;
+
+    // Without patch names, the samples are in rows of LGOROWLENGTH
+    let layout = cgats.layout()?;
+    assert_eq!(layout, xrite.layout()?);
+    assert_eq!(layout.neighbours(0), vec![1, 2]);
+    assert_eq!(layout.neighbours(3), vec![1, 2]);
+
+    let columns = ChartLayout::new(5, 2, ScanOrder::ColumnMajor)?;
+    assert_eq!((columns.rows(), columns.columns()), (3, 2));
+    assert_eq!(columns.position(3), Some(PatchPosition::new(0, 1)));
+    assert_eq!(columns.neighbours(1), vec![0, 4, 2]);
+    assert_eq!(columns.scan_order(ScanOrder::RowMajor), vec![0, 3, 1, 4, 2]);
+
+    assert!(Cgats::from_file("test_files/cgats1.tsv")?.layout().is_err());
+
+    Ok(())
+}
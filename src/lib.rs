@@ -5,16 +5,70 @@ mod data_map;
 mod data_vec;
 mod error;
 mod compare;
+mod average;
 mod de_report;
+mod difference;
+mod color;
+mod curve;
+mod tone;
+mod g7;
+mod linearize;
+mod model;
+mod inverse;
+mod gamut;
+mod verify;
+mod html_report;
+mod argyll;
+mod delimited;
+mod metadata;
+mod dialect;
+mod condition;
+mod condition_set;
+mod layout;
+mod target;
+mod randomize;
+#[cfg(feature = "render")]
+mod render;
+#[cfg(feature = "cxf")]
+mod cxf;
+
+#[cfg(feature = "reference")]
+pub mod reference;
 
 #[cfg(test)]
 mod test;
 
 pub use self::cgats::Cgats;
 pub use self::compare::CgatsVec;
+pub use average::{AverageMethod, AverageOptions, Rejection, RobustAverage};
 pub use error::{Result, Error};
-pub use de_report::DeReport;
-use vendor::Vendor;
+pub use de_report::{DeReport, DeReportOptions, DeSplit, DeSummary, HistogramBin, ReportFormat, WorstSample};
+pub use difference::{Ciede2000, DeltaEFormula};
+pub use curve::{Curve, CurveSet};
+pub use tone::{Channel, ToneMethod, ToneStep, ToneCurve, ToneAnalysis};
+pub use g7::{G7Analysis, G7Npdc, G7Ramp, G7Step};
+pub use linearize::{Linearization, ToneTarget};
+pub use model::{CharacterizationModel, Interpolation, Prediction};
+pub use inverse::{BlackGeneration, InverseModel, InverseResult};
+pub use gamut::Gamut;
+pub use verify::{Criterion, CriterionResult, ToleranceProfile, Verdict, Verification};
+pub use html_report::HtmlReport;
+pub use argyll::{ArgyllKind, ArgyllTarget, SpectralBands};
+pub use delimited::Delimiter;
+pub use metadata::VendorMetadata;
+pub use vendor::Vendor;
+pub use condition::{ConditionMismatch, MeasurementCondition};
+pub use condition_set::ConditionSet;
+pub use layout::{ChartLayout, PatchPosition, ScanOrder};
+pub use target::{DeviceSpace, TargetGenerator};
+pub use randomize::RandomizeOptions;
+#[cfg(feature = "render")]
+pub use render::{BitDepth, ImageFormat, RenderOptions, RgbRange};
+pub use dialect::{
+    ArgyllDialect, BarbieriDialect, ColorBurstDialect, CurveDialect, Dialect, DialectRegistry,
+    KonicaMinoltaDialect, TechkonDialect, XRiteDialect,
+};
+pub use data_vec::{DataLine, DataVec};
+pub use field::Field;
 use field::*;
 use data_map::*;
-use data_vec::*;
@@ -0,0 +1,290 @@
+use super::*;
+
+use crate::curve::curve_file_inputs;
+use crate::tone::COLORBURST_STEPS;
+use deltae::{DeltaE, DEMethod};
+use std::fmt;
+use std::str::FromStr;
+
+/// The tone response a linearization aims for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneTarget {
+    /// Density above paper rises linearly with the device value
+    #[default]
+    LinearDensity,
+    /// ΔE 1976 from paper rises linearly with the device value
+    LinearDeltaE,
+    /// Luminance follows the G7 NPDC between paper and solid
+    G7Npdc,
+}
+
+impl FromStr for ToneTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ToneTarget> {
+        match s.to_lowercase().as_str() {
+            "density" | "linear-density" | "lineardensity" => Ok(ToneTarget::LinearDensity),
+            "de" | "deltae" | "linear-de" | "linear-deltae" | "lineardeltae" => Ok(ToneTarget::LinearDeltaE),
+            "g7" | "npdc" | "g7npdc" | "g7-npdc" => Ok(ToneTarget::G7Npdc),
+            _ => Err(Error::Other(format!("Unknown tone target: '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for ToneTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl ToneTarget {
+    fn target(self, nominal: Float) -> Float {
+    //! Normalized target response (0...100) for a nominal device value
+        match self {
+            ToneTarget::LinearDensity | ToneTarget::LinearDeltaE => nominal,
+            ToneTarget::G7Npdc => 100.0 * g7::npdc_tone().interpolate(nominal),
+        }
+    }
+}
+
+/// The measured response of one channel and the curve that corrects it to a ToneTarget
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linearization {
+    pub channel: Channel,
+    pub target: ToneTarget,
+    /// Nominal % -> measured response, normalized from paper (0) to solid (100)
+    pub response: Curve,
+    /// Input % -> output % sent to the device
+    pub correction: Curve,
+}
+
+impl Linearization {
+    fn new(channel: Channel, target: ToneTarget, measured: Vec<(Float, Float)>) -> Result<Linearization> {
+        let paper = measured.first().ok_or(Error::IncompleteData)?.1;
+        let solid = measured.last().ok_or(Error::IncompleteData)?.1;
+
+        if (solid - paper).abs() <= Float::EPSILON {
+            return Err(Error::IncompleteData);
+        }
+
+        let response = Curve::new(measured.iter()
+            .map(|(nominal, value)| (*nominal, 100.0 * (value - paper) / (solid - paper)))
+            .collect())?;
+
+        let inverse = strictly_increasing(&response)?.inverse()?;
+        let correction = Curve::new(curve_file_inputs()
+            .map(|input| (input, inverse.interpolate(target.target(input)).clamp(0.0, 100.0)))
+            .collect())?;
+
+        Ok(Linearization { channel, target, response, correction })
+    }
+
+    pub fn apply(&self, input: Float) -> Float {
+    //! The corrected device value for an input value
+        self.correction.interpolate(input).clamp(0.0, 100.0)
+    }
+}
+
+impl fmt::Display for Linearization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} - {}", self.channel, self.target)?;
+        for (input, output) in self.correction.points() {
+            writeln!(f, "\t{:>6.2}% -> {:>6.2}%", input, output)?;
+        }
+        Ok(())
+    }
+}
+
+impl Cgats {
+    pub fn linearization(&self, channel: Channel, target: ToneTarget) -> Result<Linearization> {
+    //! Compute the correction Curve that brings the measured ramp of a Channel onto a ToneTarget.
+    //! Returns an Error if there is no ramp or it does not respond to the device value.
+        let ramp = self.channel_ramp(channel)?;
+        let paper_key = ramp[0].1[0];
+        let paper = self.sample_lab(paper_key).ok_or(Error::IncompleteData)?;
+
+        let mut measured = Vec::with_capacity(ramp.len());
+        for (tint, keys) in ramp.iter() {
+            let mut sum = 0.0;
+            for key in keys {
+                sum += match target {
+                    ToneTarget::LinearDensity => self.channel_density(*key, channel),
+                    ToneTarget::LinearDeltaE => self.sample_lab(*key)
+                        .map(|lab| DeltaE::new(&paper, &lab, DEMethod::DE1976).value),
+                    ToneTarget::G7Npdc => self.sample_lab(*key)
+                        .map(|lab| g7::luminance(lab.l)),
+                }.ok_or(Error::IncompleteData)?;
+            }
+            measured.push((*tint, sum / keys.len() as Float));
+        }
+
+        Linearization::new(channel, target, measured)
+    }
+
+    pub fn linearization_curves(&self, target: ToneTarget) -> Result<CurveSet> {
+    //! Linearize every CMYK channel that has a ramp.
+    //! Returns an Error if no channel can be linearized.
+        let mut curves = CurveSet::new();
+
+        for channel in Channel::CMYK.iter() {
+            if let Ok(lin) = self.linearization(*channel, target) {
+                curves.insert(*channel, lin.correction);
+            }
+        }
+
+        if curves.is_empty() {
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(curves)
+    }
+
+    pub fn to_colorburst_measurements(&self) -> Result<Cgats> {
+    //! Export the measured CMYK ramps as a ColorBurst LinFile:
+    //! 21 steps per channel with densities and LAB, in CMYK order.
+    //! Densities missing from the data are derived from colorimetry.
+    //! Returns an Error if any channel has no ramp.
+        self.to_colorburst_lin(&[])
+    }
+
+    pub fn to_colorburst_lin(&self, linearizations: &[Linearization]) -> Result<Cgats> {
+    //! Write the ramps as a ColorBurst LinFile after linearization: each step of a
+    //! linearized channel is the measurement at its corrected device value, so the
+    //! file holds the response of the ToneTarget. Channels without a Linearization
+    //! are written as measured. The Linearizations must come from this data set.
+    //! Returns an Error if any channel has no ramp.
+        let mut cgats = Cgats::new_with_vendor(Vendor::ColorBurst);
+        cgats.meta = DataVec::from(vec![DataLine::from(vec!["ColorBurst".to_string()])]);
+        cgats.fields = field::ColorBurstFormat();
+
+        for channel in Channel::CMYK.iter() {
+            let ramp = self.channel_ramp(*channel)?;
+
+            let mut columns: Vec<Vec<(Float, Float)>> = vec![Vec::new(); cgats.fields.len()];
+            for (tint, keys) in ramp.iter() {
+                for key in keys {
+                    let values = self.colorburst_values(*key).ok_or(Error::IncompleteData)?;
+                    for (column, value) in columns.iter_mut().zip(values.iter()) {
+                        column.push((*tint, *value));
+                    }
+                }
+            }
+
+            let curves = columns.into_iter()
+                .map(Curve::new)
+                .collect::<Result<Vec<_>>>()?;
+            let linearization = linearizations.iter().find(|lin| lin.channel == *channel);
+
+            for step in 0..COLORBURST_STEPS {
+                let nominal = step as Float * 100.0 / (COLORBURST_STEPS - 1) as Float;
+                let tint = linearization.map_or(nominal, |lin| lin.apply(nominal));
+                let values = curves.iter()
+                    .map(|curve| CgatsValue::from_float(round_to(curve.interpolate(tint), 4)))
+                    .collect();
+                cgats.data_map.insert(cgats.data_map.len(), Sample { values });
+            }
+        }
+
+        Ok(cgats)
+    }
+
+    fn colorburst_values(&self, key: usize) -> Option<Vec<Float>> {
+    // D_RED, D_GREEN, D_BLUE, D_VIS, LAB_L, LAB_A, LAB_B of a sample
+        let lab = self.sample_lab(key)?;
+        let visual = match self.get_float(key, &Field::D_VIS) {
+            Some(density) => density,
+            None => color::density(self.sample_xyz(key)?[1]),
+        };
+
+        Some(vec![
+            self.channel_density(key, Channel::Cyan)?,
+            self.channel_density(key, Channel::Magenta)?,
+            self.channel_density(key, Channel::Yellow)?,
+            visual,
+            lab.l, lab.a, lab.b,
+        ])
+    }
+}
+
+fn strictly_increasing(curve: &Curve) -> Result<Curve> {
+//! Remove measurement noise that would keep a response from being inverted
+    let mut previous = Float::MIN;
+    curve.map(|_, y| {
+        previous = if y > previous { y } else { previous + 1e-4 };
+        previous
+    })
+}
+
+#[test]
+fn linearize_colorburst() -> Result<()> {
+    let colorburst = Cgats::from_file("test_files/colorburst1.lin")?;
+
+    for target in &[ToneTarget::LinearDensity, ToneTarget::LinearDeltaE, ToneTarget::G7Npdc] {
+        let lin = colorburst.linearization(Channel::Black, *target)?;
+        println!("{}", lin);
+
+        assert_eq!(lin.apply(0.0), 0.0);
+        assert!((lin.apply(100.0) - 100.0).abs() < 0.01);
+        assert!(lin.correction.is_increasing());
+    }
+
+    // Dot gain means the midtones must be reduced for a linear density response
+    let density = colorburst.linearization(Channel::Cyan, ToneTarget::LinearDensity)?;
+    assert!(density.apply(50.0) < 50.0);
+
+    Ok(())
+}
+
+#[test]
+fn write_colorburst_measurements() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let lin = crpc6.to_colorburst_measurements()?;
+
+    let temp = test::mktemp()?;
+    lin.write_to_file(&temp)?;
+    let reconstructed = Cgats::from_file(&temp)?;
+
+    assert_eq!(reconstructed.vendor, Vendor::ColorBurst);
+    assert_eq!(reconstructed.sample_count(), 4 * COLORBURST_STEPS);
+    assert_eq!(reconstructed.sample_lab(0), crpc6.sample_lab(0));
+    assert_eq!(reconstructed.get_float(83, &Field::LAB_L), Some(16.0));
+
+    let curves = reconstructed.linearization_curves(ToneTarget::G7Npdc)?;
+    assert!(curves.get(Channel::Black).is_some());
+
+    std::fs::remove_file(temp)?;
+    Ok(())
+}
+
+#[test]
+fn write_colorburst_lin() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let cyan = crpc6.linearization(Channel::Cyan, ToneTarget::LinearDensity)?;
+    let black = crpc6.linearization(Channel::Black, ToneTarget::G7Npdc)?;
+    let lin = crpc6.to_colorburst_lin(&[cyan, black])?;
+
+    let temp = test::mktemp()?;
+    lin.write_to_file(&temp)?;
+    let reconstructed = Cgats::from_file(&temp)?;
+    std::fs::remove_file(temp)?;
+
+    assert_eq!(reconstructed.vendor, Vendor::ColorBurst);
+    assert_eq!(reconstructed.sample_count(), 4 * COLORBURST_STEPS);
+
+    // The linearized channels now follow their targets
+    let cyan = reconstructed.linearization(Channel::Cyan, ToneTarget::LinearDensity)?;
+    let black = reconstructed.linearization(Channel::Black, ToneTarget::G7Npdc)?;
+    for nominal in &[10.0, 25.0, 50.0, 75.0, 90.0] {
+        assert!((cyan.response.interpolate(*nominal) - nominal).abs() < 1.0);
+        assert!((black.response.interpolate(*nominal) - ToneTarget::G7Npdc.target(*nominal)).abs() < 1.0);
+        assert!((cyan.apply(*nominal) - nominal).abs() < 1.0);
+    }
+
+    // The others are written as measured
+    let measured = crpc6.to_colorburst_measurements()?;
+    for key in COLORBURST_STEPS..3 * COLORBURST_STEPS {
+        assert_eq!(reconstructed.data_map[&key], measured.data_map[&key]);
+    }
+
+    Ok(())
+}
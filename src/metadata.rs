@@ -0,0 +1,276 @@
+use super::*;
+
+// Markers that introduce an instrument serial number in INSTRUMENTATION
+const SERIAL_MARKERS: &[&str] = &["serial number", "serial", "s/n", "sn=", "sn:"];
+
+/// Measurement metadata parsed from the vendor-specific header keywords
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VendorMetadata {
+    /// Software that wrote the file (ORIGINATOR)
+    pub software: Option<String>,
+    /// Instrument model, without its serial number
+    pub instrument: Option<String>,
+    /// Instrument serial number
+    pub serial: Option<String>,
+    /// ISO 13655 measurement condition, e.g. "M1"
+    pub measurement_condition: Option<String>,
+    /// Measuring aperture in millimeters
+    pub aperture: Option<Float>,
+    pub illuminant: Option<String>,
+    pub observer: Option<String>,
+    pub filter: Option<String>,
+    /// Instrument calibration standard, e.g. "XRGA"
+    pub calibration_standard: Option<String>,
+    /// Patches per row of the chart (LGOROWLENGTH)
+    pub row_length: Option<usize>,
+    /// Every `key=value` pair found in the vendor keywords
+    pub properties: Vec<(String, String)>,
+}
+
+impl VendorMetadata {
+    pub fn property(&self, key: &str) -> Option<&str> {
+    //! The value of a `key=value` pair, ignoring case, spaces and underscores
+        let key = normalize(key);
+        self.properties.iter()
+            .find(|(k, _)| normalize(k) == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn fill_from_properties(&mut self) {
+    //! Fill the fields that are still missing from the `key=value` pairs
+        let get = |keys: &[&str]| keys.iter().find_map(|key| self.property(key)).map(String::from);
+
+        let condition = get(&["Measurement Condition", "MeasurementCondition", "Condition", "MeasurementMode"]);
+        let aperture = get(&["Aperture", "Measuring Aperture"]);
+        let illuminant = get(&["Illuminant", "Illumination"]);
+        let observer = get(&["Observer", "ObserverAngle"]);
+        let filter = get(&["Filter"]);
+
+        self.measurement_condition = self.measurement_condition.take().or(condition);
+        self.aperture = self.aperture.or_else(|| parse_millimeters(&aperture?));
+        self.illuminant = self.illuminant.take().or(illuminant);
+        self.observer = self.observer.take().or_else(|| Some(parse_observer(&observer?)));
+        self.filter = self.filter.take().or(filter);
+    }
+}
+
+impl Cgats {
+    pub fn metadata(&self) -> VendorMetadata {
+    //! Parse the measurement metadata of the header, including the
+    //! vendor-specific keywords of X-Rite, Barbieri, Konica Minolta and Techkon
+        let mut meta = VendorMetadata {
+            software: self.keyword("ORIGINATOR"),
+            measurement_condition: self.keyword("MEASUREMENT_CONDITION"),
+            aperture: self.keyword("APERTURE").and_then(|a| parse_millimeters(&a)),
+            illuminant: self.keyword("ILLUMINATION_NAME").or_else(|| self.keyword("ILLUMINANT")),
+            observer: self.keyword("OBSERVER_ANGLE").map(|o| parse_observer(&o)),
+            filter: self.keyword("FILTER"),
+            serial: self.keyword("SERIAL_NUMBER"),
+            row_length: self.keyword("LGOROWLENGTH").and_then(|l| l.parse().ok()),
+            ..VendorMetadata::default()
+        };
+
+        if let Some(instrumentation) = self.keyword("INSTRUMENTATION") {
+            let (instrument, serial) = split_serial(&instrumentation);
+            meta.instrument = Some(instrument);
+            meta.serial = meta.serial.take().or(serial);
+        }
+
+        if let Some(source) = self.keyword("MEASUREMENT_SOURCE") {
+            meta.properties.extend(pairs(&source));
+        }
+
+        match self.vendor {
+            Vendor::XRite => self.xrite_metadata(&mut meta),
+            Vendor::Barbieri => self.barbieri_metadata(&mut meta),
+            Vendor::KonicaMinolta => self.konica_minolta_metadata(&mut meta),
+            Vendor::Techkon => self.techkon_metadata(&mut meta),
+            _ => (),
+        }
+
+        meta.fill_from_properties();
+        meta
+    }
+
+    fn xrite_metadata(&self, meta: &mut VendorMetadata) {
+        // i1Profiler and ColorPort only name the filter in MEASUREMENT_SOURCE,
+        // where "Filter=No" is M0
+        meta.calibration_standard = self.keyword("DEVCALSTD");
+        if meta.measurement_condition.is_none() {
+            meta.measurement_condition = meta.property("Filter")
+                .and_then(|filter| match filter.to_lowercase().as_str() {
+                    "no" | "none" => Some(MeasurementCondition::M0),
+                    _ => MeasurementCondition::from_filter(filter),
+                })
+                .map(|condition| condition.to_string());
+        }
+    }
+
+    fn barbieri_metadata(&self, meta: &mut VendorMetadata) {
+        // BARBIERI_INFO_0...n is one comma separated list split over several keywords
+        let info = self.meta.lines.iter()
+            .filter(|line| line.keyword().starts_with("BARBIERI_INFO"))
+            .map(|line| line.value().trim_matches('"').to_string())
+            .collect::<Vec<String>>()
+            .join("");
+        meta.properties.extend(pairs(&info));
+
+        // "Spectropad  ver:6.26-6.10 R DOC,SN=B5101140,MN=B11J0026,1"
+        if let Some(instrument) = meta.instrument.take() {
+            let model = instrument.split(&[',', '\t'][..]).next().unwrap_or_default();
+            let model = model.split("ver:").next().unwrap_or_default();
+            meta.instrument = Some(model.trim().to_string());
+        }
+    }
+
+    fn konica_minolta_metadata(&self, meta: &mut VendorMetadata) {
+        meta.aperture = meta.aperture
+            .or_else(|| parse_millimeters(&self.keyword("APERTURE_SIZE")?));
+        meta.serial = meta.serial.take()
+            .or_else(|| self.keyword("INSTRUMENT_SERIAL"));
+    }
+
+    fn techkon_metadata(&self, meta: &mut VendorMetadata) {
+        for line in self.meta.lines.iter().filter(|line| line.keyword().starts_with("TECHKON_")) {
+            meta.properties.extend(pairs(line.value().trim_matches('"')));
+        }
+    }
+}
+
+fn normalize(key: &str) -> String {
+    key.chars()
+        .filter(|c| !matches!(c, ' ' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn pairs(text: &str) -> Vec<(String, String)> {
+    // Pairs are separated by commas or tabs; keys may contain spaces
+    // ("Measurement Condition=M1"), but a run of pairs may also be
+    // separated by spaces ("Illumination=D50 ObserverAngle=2")
+    text.split(&[',', '\t'][..])
+        .flat_map(|segment| {
+            if segment.matches('=').count() > 1 {
+                segment.split_whitespace().map(String::from).collect::<Vec<String>>()
+            } else {
+                vec![segment.to_string()]
+            }
+        })
+        .filter_map(|pair| {
+            let mut split = pair.splitn(2, '=');
+            let key = split.next()?.trim();
+            let value = split.next()?.trim().trim_matches('"');
+            if key.is_empty() {
+                None
+            } else {
+                Some((key.to_string(), value.to_string()))
+            }
+        })
+        .collect()
+}
+
+fn split_serial(instrumentation: &str) -> (String, Option<String>) {
+    let found = SERIAL_MARKERS.iter()
+        .find_map(|marker| Some((find_ignore_ascii_case(instrumentation, marker)?, marker.len())));
+
+    match found {
+        Some((index, len)) => {
+            let serial = instrumentation[index + len..]
+                .trim_start_matches(&[' ', ':', '=', '#'][..])
+                .split(&[',', ' ', ';'][..])
+                .next()
+                .filter(|s| !s.is_empty())
+                .map(String::from);
+            let instrument = instrumentation[..index]
+                .trim_end_matches(&[' ', ',', ';'][..])
+                .to_string();
+            (instrument, serial)
+        },
+        None => (instrumentation.to_string(), None),
+    }
+}
+
+fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+// The byte offset of an ASCII needle in the original string, so it can be sliced there
+    haystack.char_indices()
+        .map(|(index, _)| index)
+        .find(|index| haystack.as_bytes()[*index..]
+            .get(..needle.len())
+            .is_some_and(|bytes| bytes.eq_ignore_ascii_case(needle.as_bytes())))
+}
+
+fn parse_millimeters(s: &str) -> Option<Float> {
+    let number = s.trim()
+        .trim_end_matches(|c: char| c.is_alphabetic())
+        .trim();
+    number.parse().ok()
+}
+
+fn parse_observer(s: &str) -> String {
+    // "10degree", "2°" and "2_Degree" are all just the angle
+    s.trim_end_matches(|c: char| !c.is_ascii_digit()).to_string()
+}
+
+#[test]
+fn barbieri() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/cgats0.txt")?;
+    assert_eq!(cgats.vendor, Vendor::Barbieri);
+
+    let meta = cgats.metadata();
+    assert_eq!(meta.instrument.as_deref(), Some("Spectropad"));
+    assert_eq!(meta.serial.as_deref(), Some("B5101140"));
+    assert_eq!(meta.measurement_condition.as_deref(), Some("M1"));
+    assert_eq!(meta.aperture, Some(6.0));
+    assert_eq!(meta.illuminant.as_deref(), Some("D50"));
+    assert_eq!(meta.observer.as_deref(), Some("10"));
+    assert_eq!(meta.property("Patches_X"), Some("24"));
+
+    Ok(())
+}
+
+#[test]
+fn xrite() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/xrite0.txt")?;
+    assert_eq!(cgats.vendor, Vendor::XRite);
+    assert_eq!(cgats.fields[0], Field::SAMPLE_NAME);
+
+    let meta = cgats.metadata();
+    assert_eq!(meta.software.as_deref(), Some("i1Profiler"));
+    assert_eq!(meta.instrument.as_deref(), Some("i1Pro 3"));
+    assert_eq!(meta.serial.as_deref(), Some("1012345"));
+    assert_eq!(meta.measurement_condition.as_deref(), Some("M2"));
+    assert_eq!(meta.calibration_standard.as_deref(), Some("XRGA"));
+    assert_eq!(meta.row_length, Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn konica_minolta_and_techkon() -> Result<()> {
+    let konica = Cgats::from_file("test_files/konica0.txt")?;
+    assert_eq!(konica.vendor, Vendor::KonicaMinolta);
+    let meta = konica.metadata();
+    assert_eq!(meta.instrument.as_deref(), Some("FD-9"));
+    assert_eq!(meta.serial.as_deref(), Some("20071234"));
+    assert_eq!(meta.measurement_condition.as_deref(), Some("M1"));
+    assert_eq!(meta.aperture, Some(3.5));
+
+    let techkon = Cgats::from_file("test_files/techkon0.txt")?;
+    assert_eq!(techkon.vendor, Vendor::Techkon);
+    let meta = techkon.metadata();
+    assert_eq!(meta.instrument.as_deref(), Some("SpectroDens"));
+    assert_eq!(meta.serial.as_deref(), Some("4711"));
+    assert_eq!(meta.measurement_condition.as_deref(), Some("M2"));
+    assert_eq!(meta.aperture, Some(3.0));
+    assert_eq!(meta.observer.as_deref(), Some("2"));
+
+    Ok(())
+}
+
+#[test]
+fn non_ascii_serial() {
+    // Lowercasing 'İ' changes its length, so the offsets must come from the original string
+    assert_eq!(split_serial("İİİ Serial 4711"), ("İİİ".to_string(), Some("4711".to_string())));
+    assert_eq!(split_serial("Spectro İİİ, S/N: B51"), ("Spectro İİİ".to_string(), Some("B51".to_string())));
+    assert_eq!(split_serial("İİİ Serıal"), ("İİİ Serıal".to_string(), None));
+}
@@ -0,0 +1,536 @@
+use super::*;
+
+use deltae::color::LabValue;
+use deltae::DEMethod;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Device values are matched to grid nodes at this precision
+const NODE_SCALE: Float = 1000.0;
+
+// Number of neighbors per device dimension used by the scattered-data fallback
+const NEIGHBORS_PER_DIMENSION: usize = 4;
+
+// Number of axis values on either side of a device value tried when looking for a grid cell
+const CELL_CANDIDATES: usize = 3;
+
+// Number of candidate cells tried before giving up on the grid
+const MAX_CELLS: usize = 64;
+
+// Device values and the LAB they print
+type ModelSample = (Vec<Float>, [Float; 3]);
+
+/// How values are interpolated inside a cell of the recovered grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Simplex interpolation: tetrahedral in 3 dimensions, needs n + 1 nodes
+    #[default]
+    Simplex,
+    /// Multilinear interpolation: needs all 2^n corners of the cell
+    Multilinear,
+}
+
+/// Where a model value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prediction {
+    /// An exact measured node
+    Node,
+    /// Interpolated inside the regular grid
+    Grid,
+    /// Fitted from the nearest scattered samples
+    Scattered,
+}
+
+impl fmt::Display for Prediction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A forward device -> LAB model interpolating characterization data
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterizationModel {
+    device_fields: DataFormat,
+    interpolation: Interpolation,
+    axes: Vec<Vec<Float>>,
+    nodes: HashMap<Vec<i64>, [Float; 3]>,
+    // The first 1...n node key values of every node
+    prefixes: Vec<HashSet<Vec<i64>>>,
+    samples: Vec<ModelSample>,
+}
+
+impl CharacterizationModel {
+    pub fn new(cgats: &Cgats) -> Result<CharacterizationModel> {
+    //! Build a model from a CGATS object with device values and LAB or XYZ.
+    //! Repeated device values are averaged.
+    //! Returns an Error if there are no device values, no color data,
+    //! or fewer samples than needed to fit the device space.
+        let device_fields = Field::device_fields(&cgats.fields).ok_or(Error::IncompleteData)?;
+        let dimensions = device_fields.len();
+
+        let mut sums: HashMap<Vec<i64>, (Vec<Float>, [Float; 3], usize)> = HashMap::new();
+        for key in cgats.data_map.keys() {
+            let device = match cgats.device_values(*key, &device_fields) {
+                Some(device) => device,
+                None => continue,
+            };
+            let lab = match cgats.sample_lab(*key) {
+                Some(lab) => [lab.l, lab.a, lab.b],
+                None => continue,
+            };
+
+            let entry = sums.entry(node_key(&device))
+                .or_insert_with(|| (device, [0.0; 3], 0));
+            for (sum, value) in entry.1.iter_mut().zip(lab.iter()) {
+                *sum += value;
+            }
+            entry.2 += 1;
+        }
+
+        if sums.len() <= dimensions {
+            return Err(Error::IncompleteData);
+        }
+
+        let mut samples = Vec::with_capacity(sums.len());
+        let mut nodes = HashMap::with_capacity(sums.len());
+        for (key, (device, sum, count)) in sums {
+            let lab = [sum[0] / count as Float, sum[1] / count as Float, sum[2] / count as Float];
+            nodes.insert(key, lab);
+            samples.push((device, lab));
+        }
+
+        samples.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Device value is NaN!"));
+
+        let axes = (0..dimensions).map(|d| {
+            let mut axis: Vec<Float> = samples.iter().map(|(device, _)| device[d]).collect();
+            axis.sort_by(|a, b| a.partial_cmp(b).expect("Device value is NaN!"));
+            axis.dedup_by(|a, b| (*a - *b).abs() < 1.0 / NODE_SCALE);
+            axis
+        }).collect();
+
+        let prefixes = (1..=dimensions)
+            .map(|length| nodes.keys().map(|key| key[..length].to_vec()).collect())
+            .collect();
+
+        Ok(CharacterizationModel {
+            device_fields,
+            interpolation: Interpolation::default(),
+            axes,
+            nodes,
+            prefixes,
+            samples,
+        })
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> CharacterizationModel {
+    //! Choose how values inside the grid are interpolated
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn device_fields(&self) -> &DataFormat {
+    //! The device value fields the model expects, in order
+        &self.device_fields
+    }
+
+    pub fn sample_count(&self) -> usize {
+    //! Number of distinct device values in the model
+        self.samples.len()
+    }
+
+    pub(crate) fn samples(&self) -> impl Iterator<Item = &ModelSample> {
+    //! The distinct device values and the LAB they were measured with
+        self.samples.iter()
+    }
+
+    pub fn is_regular_grid(&self) -> bool {
+    //! Test if every combination of the axis values was measured
+        self.axes.iter().map(Vec::len).product::<usize>() == self.nodes.len()
+    }
+
+    pub fn lab(&self, device: &[Float]) -> Result<LabValue> {
+    //! Predict the LAB value that a set of device values prints
+        self.predict(device).map(|(lab, _)| lab)
+    }
+
+    pub fn predict(&self, device: &[Float]) -> Result<(LabValue, Prediction)> {
+    //! Predict the LAB value of a set of device values, and how it was found.
+    //! Returns an Error if the number of device values does not match the model.
+        if device.len() != self.device_fields.len() {
+            return Err(Error::Other(format!(
+                "Expected {} device values, found {}!", self.device_fields.len(), device.len()
+            )));
+        }
+
+        let (lab, prediction) = match self.nodes.get(&node_key(device)) {
+            Some(lab) => (*lab, Prediction::Node),
+            None => match self.grid(device) {
+                Some(lab) => (lab, Prediction::Grid),
+                None => (self.scattered(device), Prediction::Scattered),
+            },
+        };
+
+        Ok((LabValue { l: lab[0], a: lab[1], b: lab[2] }, prediction))
+    }
+
+    pub fn predict_cgats(&self, cgats: &Cgats) -> Result<Cgats> {
+    //! Add or replace the LAB of every sample in a CGATS object with device values
+        let mut predicted = cgats.clone();
+
+        for field in &[Field::LAB_L, Field::LAB_A, Field::LAB_B] {
+            if predicted.field_index(field).is_none() {
+                predicted.fields.push(*field);
+                for sample in predicted.data_map.values_mut() {
+                    sample.values.push(CgatsValue::default());
+                }
+            }
+        }
+
+        let indexes = Field::lab_indexes(&predicted.fields).expect("LAB was just added!");
+        for key in cgats.data_map.keys() {
+            let device = cgats.device_values(*key, &self.device_fields).ok_or(Error::IncompleteData)?;
+            let lab = self.lab(&device)?;
+            let sample = predicted.data_map.get_mut(key).expect("Key is in the map!");
+            for (index, value) in indexes.iter().zip(&[lab.l, lab.a, lab.b]) {
+                sample.values[*index] = CgatsValue::from_float(round_to(*value, 4));
+            }
+        }
+
+        predicted.meta.meta_renumber_fields(predicted.fields.len());
+
+        Ok(predicted)
+    }
+
+    pub fn holdout(cgats: &Cgats, every: usize, method: DEMethod) -> Result<Cgats> {
+    //! Estimate the model error: build a model without every `every`th sample,
+    //! predict the held-out samples, and return their Delta E to the measurements.
+        if every < 2 {
+            return Err(Error::Other("Hold out at least every 2nd sample!".to_string()));
+        }
+
+        let mut training = cgats.clone();
+        let mut measured = cgats.derive();
+
+        for (position, key) in cgats.data_map.keys().enumerate() {
+            if position % every == 0 {
+                let sample = training.data_map.remove(key).expect("Key is in the map!");
+                measured.data_map.insert(*key, sample);
+            }
+        }
+
+        measured.meta.meta_renumber_sets(measured.data_map.len());
+
+        let model = CharacterizationModel::new(&training)?;
+        let predicted = model.predict_cgats(&measured)?;
+
+        CgatsVec::from(vec![measured, predicted]).deltae(method)
+    }
+
+    fn grid(&self, device: &[Float]) -> Option<[Float; 3]> {
+    // Interpolate inside the smallest complete grid cell that contains the device values.
+    // Characterization targets are often partial grids, so wider cells built from
+    // the next few axis values on either side are tried as well.
+    // Returns None outside of the grid or if no enclosing cell is complete.
+        let tolerance = 1.0 / NODE_SCALE;
+
+        let mut brackets = Vec::with_capacity(device.len());
+        for (axis, value) in self.axes.iter().zip(device) {
+            let lower: Vec<Float> = axis.iter().rev()
+                .filter(|v| **v <= *value + tolerance)
+                .take(CELL_CANDIDATES).copied().collect();
+            let upper: Vec<Float> = axis.iter()
+                .filter(|v| **v >= *value - tolerance)
+                .take(CELL_CANDIDATES).copied().collect();
+
+            if lower.is_empty() || upper.is_empty() {
+                return None;
+            }
+
+            let mut pairs: Vec<(Float, Float)> = lower.iter()
+                .flat_map(|lo| upper.iter().map(move |hi| (*lo, *hi)))
+                .collect();
+            pairs.sort_by(|a, b| (a.1 - a.0).partial_cmp(&(b.1 - b.0)).expect("Axis value is NaN!"));
+            brackets.push(pairs);
+        }
+
+        // Only cells whose lower and upper corners were measured can be complete
+        let mut cells = Vec::new();
+        self.enclosing_cells(&brackets, &mut Vec::new(), &mut cells);
+        cells.sort_by(|a, b| cell_size(a).partial_cmp(&cell_size(b)).expect("Axis value is NaN!"));
+
+        for cell in cells.iter().take(MAX_CELLS) {
+            let lower: Vec<Float> = cell.iter().map(|(lo, _)| *lo).collect();
+            let upper: Vec<Float> = cell.iter().map(|(_, hi)| *hi).collect();
+            let fraction: Vec<Float> = cell.iter().zip(device)
+                .map(|((lo, hi), value)| if hi - lo < tolerance { 0.0 } else { (value - lo) / (hi - lo) })
+                .collect();
+
+            let lab = match self.interpolation {
+                Interpolation::Simplex => self.simplex(&lower, &upper, &fraction),
+                Interpolation::Multilinear => self.multilinear(&lower, &upper, &fraction),
+            };
+
+            if lab.is_some() {
+                return lab;
+            }
+        }
+
+        None
+    }
+
+    fn enclosing_cells(&self, brackets: &[Vec<(Float, Float)>], cell: &mut Vec<(Float, Float)>, cells: &mut Vec<Vec<(Float, Float)>>) {
+    // Choose a bracket for one dimension at a time, dropping cells whose corners
+    // do not match the first dimensions of any measured node
+        let depth = cell.len();
+        if depth == brackets.len() {
+            cells.push(cell.clone());
+            return;
+        }
+
+        for pair in &brackets[depth] {
+            cell.push(*pair);
+
+            let lower: Vec<Float> = cell.iter().map(|(lo, _)| *lo).collect();
+            let upper: Vec<Float> = cell.iter().map(|(_, hi)| *hi).collect();
+            if self.prefixes[depth].contains(&node_key(&lower)) && self.prefixes[depth].contains(&node_key(&upper)) {
+                self.enclosing_cells(brackets, cell, cells);
+            }
+
+            cell.pop();
+        }
+    }
+
+    fn simplex(&self, lower: &[Float], upper: &[Float], fraction: &[Float]) -> Option<[Float; 3]> {
+    // Walk from the lower corner to the upper corner, largest fraction first
+        let mut order: Vec<usize> = (0..fraction.len()).collect();
+        order.sort_by(|a, b| fraction[*b].partial_cmp(&fraction[*a]).expect("Fraction is NaN!"));
+
+        let mut vertex = lower.to_vec();
+        let mut previous = 1.0;
+        let mut lab = [0.0; 3];
+
+        for step in 0..=order.len() {
+            let next = order.get(step).map(|d| fraction[*d]).unwrap_or(0.0);
+            let weight = previous - next;
+
+            if weight > 0.0 {
+                let node = self.nodes.get(&node_key(&vertex))?;
+                for (sum, value) in lab.iter_mut().zip(node) {
+                    *sum += weight * value;
+                }
+            }
+
+            if let Some(d) = order.get(step) {
+                vertex[*d] = upper[*d];
+            }
+            previous = next;
+        }
+
+        Some(lab)
+    }
+
+    fn multilinear(&self, lower: &[Float], upper: &[Float], fraction: &[Float]) -> Option<[Float; 3]> {
+    // Weight all 2^n corners of the cell
+        let mut lab = [0.0; 3];
+
+        for corner in 0..(1usize << lower.len()) {
+            let mut weight = 1.0;
+            let mut vertex = Vec::with_capacity(lower.len());
+
+            for d in 0..lower.len() {
+                if corner & (1 << d) == 0 {
+                    weight *= 1.0 - fraction[d];
+                    vertex.push(lower[d]);
+                } else {
+                    weight *= fraction[d];
+                    vertex.push(upper[d]);
+                }
+            }
+
+            if weight > 0.0 {
+                let node = self.nodes.get(&node_key(&vertex))?;
+                for (sum, value) in lab.iter_mut().zip(node) {
+                    *sum += weight * value;
+                }
+            }
+        }
+
+        Some(lab)
+    }
+
+    fn scattered(&self, device: &[Float]) -> [Float; 3] {
+    // Locally weighted linear regression over the nearest samples.
+    // Falls back to inverse distance weighting if the fit is degenerate.
+        let dimensions = device.len();
+        let count = (NEIGHBORS_PER_DIMENSION * dimensions).max(dimensions + 2).min(self.samples.len());
+
+        let mut neighbors: Vec<(f64, &ModelSample)> = self.samples.iter()
+            .map(|sample| (distance_squared(device, &sample.0), sample))
+            .collect();
+        if count < neighbors.len() {
+            neighbors.select_nth_unstable_by(count, |a, b| a.0.partial_cmp(&b.0).expect("Distance is NaN!"));
+            neighbors.truncate(count);
+        }
+        neighbors.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Distance is NaN!"));
+
+        let radius = neighbors.last().map(|(d, _)| *d).unwrap_or(1.0).max(1e-6);
+        let weights: Vec<f64> = neighbors.iter()
+            .map(|(d, _)| (1.0 - d / (radius * 1.0001)).powi(2) / (d + 1e-6))
+            .collect();
+
+        // Weighted least squares for [1, x0, x1, ...] -> L, a, b
+        let size = dimensions + 1;
+        let mut normal = vec![vec![0.0f64; size + 3]; size];
+        for ((_, (x, lab)), w) in neighbors.iter().zip(&weights) {
+            let row: Vec<f64> = std::iter::once(1.0)
+                .chain(x.iter().zip(device).map(|(v, d)| (*v - *d) as f64))
+                .collect();
+            for i in 0..size {
+                for j in 0..size {
+                    normal[i][j] += w * row[i] * row[j];
+                }
+                for (c, value) in lab.iter().enumerate() {
+                    normal[i][size + c] += w * row[i] * *value as f64;
+                }
+            }
+        }
+
+        match solve(normal, size) {
+            Some(solution) => [solution[0][0] as Float, solution[0][1] as Float, solution[0][2] as Float],
+            None => {
+                let total: f64 = weights.iter().sum();
+                let mut lab = [0.0; 3];
+                for ((_, (_, value)), w) in neighbors.iter().zip(&weights) {
+                    for (sum, v) in lab.iter_mut().zip(value) {
+                        *sum += (w / total) as Float * v;
+                    }
+                }
+                lab
+            },
+        }
+    }
+}
+
+impl Cgats {
+    pub fn device_values(&self, key: usize, fields: &[Field]) -> Option<Vec<Float>> {
+    //! Returns the values of the given device fields in the sample at `key`
+        fields.iter().map(|field| self.get_float(key, field)).collect()
+    }
+
+    pub fn characterization_model(&self) -> Result<CharacterizationModel> {
+    //! Build a forward device -> LAB model from this data set
+        CharacterizationModel::new(self)
+    }
+}
+
+fn node_key(device: &[Float]) -> Vec<i64> {
+    device.iter().map(|v| (v * NODE_SCALE).round() as i64).collect()
+}
+
+fn cell_size(cell: &[(Float, Float)]) -> Float {
+    cell.iter().map(|(lo, hi)| hi - lo).sum()
+}
+
+fn distance_squared(a: &[Float], b: &[Float]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| ((a - b) as f64).powi(2)).sum()
+}
+
+pub(crate) fn solve(mut matrix: Vec<Vec<f64>>, size: usize) -> Option<Vec<Vec<f64>>> {
+//! Gauss-Jordan elimination with partial pivoting of an augmented matrix
+//! with any number of right-hand sides. Returns None if the system is singular.
+    for col in 0..size {
+        let pivot = (col..size)
+            .max_by(|a, b| matrix[*a][col].abs().partial_cmp(&matrix[*b][col].abs()).expect("NaN in matrix!"))?;
+        if matrix[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        matrix.swap(col, pivot);
+
+        let divisor = matrix[col][col];
+        for value in matrix[col].iter_mut() {
+            *value /= divisor;
+        }
+
+        for row in 0..size {
+            if row != col {
+                let factor = matrix[row][col];
+                let pivot_row = matrix[col].clone();
+                for (value, p) in matrix[row].iter_mut().zip(pivot_row) {
+                    *value -= factor * p;
+                }
+            }
+        }
+    }
+
+    Some(matrix.into_iter().map(|row| row[size..].to_vec()).collect())
+}
+
+#[test]
+fn forward_model() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let model = crpc6.characterization_model()?;
+
+    assert_eq!(model.device_fields(), &vec![Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K]);
+    assert!(!model.is_regular_grid());
+
+    // Measured nodes are returned as-is
+    let (paper, prediction) = model.predict(&[0.0, 0.0, 0.0, 0.0])?;
+    assert_eq!(prediction, Prediction::Node);
+    assert_eq!(paper.l, 95.0);
+
+    // Inside a complete cell of the CMY grid at K=0
+    let (_, prediction) = model.predict(&[15.0, 15.0, 15.0, 0.0])?;
+    assert_eq!(prediction, Prediction::Grid);
+
+    let lab = model.lab(&[37.0, 12.0, 5.0, 60.0])?;
+    assert!(lab.l > 30.0 && lab.l < 60.0 && lab.a < 0.0);
+
+    assert!(model.lab(&[0.0, 0.0, 0.0]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn holdout_error() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+    let de = CharacterizationModel::holdout(&crpc6, 10, DEMethod::DE2000)?;
+    println!("{}", DeReport::new(&de)?);
+
+    let index = de.field_index(&Field::DE_2000).expect("DE_2000 is in the fields!");
+    let mean = de.data_map.values()
+        .filter_map(|sample| sample.values[index].float)
+        .sum::<Float>() / de.sample_count() as Float;
+
+    assert_eq!(de.sample_count(), 162);
+    assert!(mean < 2.0, "Mean hold-out DE2000 is {}", mean);
+
+    Ok(())
+}
+
+#[test]
+fn multilinear_grid() -> Result<()> {
+    let mut cgats = Cgats::new();
+    cgats.fields = vec![Field::RGB_R, Field::RGB_G, Field::RGB_B, Field::LAB_L, Field::LAB_A, Field::LAB_B];
+    for (key, index) in (0..8).enumerate() {
+        let rgb = [(index & 1) as Float * 100.0, ((index >> 1) & 1) as Float * 100.0, ((index >> 2) & 1) as Float * 100.0];
+        let l = (rgb[0] + rgb[1] + rgb[2]) / 3.0;
+        cgats.data_map.insert(key, Sample {
+            values: rgb.iter().chain(&[l, rgb[0] - rgb[1], rgb[1] - rgb[2]])
+                .map(|v| CgatsValue::from_float(*v))
+                .collect()
+        });
+    }
+
+    for interpolation in &[Interpolation::Simplex, Interpolation::Multilinear] {
+        let model = CharacterizationModel::new(&cgats)?.with_interpolation(*interpolation);
+        assert!(model.is_regular_grid());
+
+        let (lab, prediction) = model.predict(&[50.0, 25.0, 75.0])?;
+        assert_eq!(prediction, Prediction::Grid);
+        assert!((lab.l - 50.0).abs() < 0.001);
+        assert!((lab.a - 25.0).abs() < 0.001);
+        assert!((lab.b + 50.0).abs() < 0.001);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,434 @@
+use super::*;
+
+use std::collections::BTreeMap;
+
+// The canonical (1-based) index of each sample of a randomized data set
+const RANDOMIZED_ORDER: &str = "RANDOMIZED_ORDER";
+// The SAMPLE_IDs and SAMPLE_NAMEs of the samples in canonical order,
+// before `randomize` relabeled them
+const RANDOMIZED_IDS: &str = "RANDOMIZED_IDS";
+const RANDOMIZED_NAMES: &str = "RANDOMIZED_NAMES";
+// The LGOROWLENGTH of the canonical layout when `randomize` changed it, or 0 when there was none
+const RANDOMIZED_ROWLENGTH: &str = "RANDOMIZED_ROWLENGTH";
+const RANDOMIZED_KEYWORDS: [&str; 4] = [RANDOMIZED_ORDER, RANDOMIZED_IDS, RANDOMIZED_NAMES, RANDOMIZED_ROWLENGTH];
+
+/// Options for a reproducible random patch order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RandomizeOptions {
+    seed: u64,
+    row_length: Option<usize>,
+    paper_white_per_row: bool,
+}
+
+impl RandomizeOptions {
+    pub fn new(seed: u64) -> RandomizeOptions {
+    //! The same seed always gives the same order
+        RandomizeOptions { seed, ..RandomizeOptions::default() }
+    }
+
+    pub fn row_length(mut self, row_length: usize) -> RandomizeOptions {
+    //! Patches per row of the chart [default: the layout of the data set]
+        self.row_length = Some(row_length);
+        self
+    }
+
+    pub fn paper_white_per_row(mut self, paper_white_per_row: bool) -> RandomizeOptions {
+    //! Put at least one paper white patch in every row of the chart
+        self.paper_white_per_row = paper_white_per_row;
+        self
+    }
+}
+
+impl Cgats {
+    pub fn randomize(&self, options: &RandomizeOptions) -> Result<Cgats> {
+    //! Shuffle the samples into a random, reproducible order. The canonical
+    //! order is kept in the RANDOMIZED_ORDER keyword for `derandomize`.
+    //! SAMPLE_IDs are renumbered and alphanumeric SAMPLE_NAMEs are renamed
+    //! to the new chart positions, row by row; the original ones are kept in
+    //! the RANDOMIZED_IDS and RANDOMIZED_NAMES keywords, and a replaced
+    //! LGOROWLENGTH in RANDOMIZED_ROWLENGTH.
+    //! Returns an Error if a row cannot get a paper white patch.
+        if self.data_map.is_empty() {
+            return Err(Error::NoData);
+        }
+
+        let row_length = match options.row_length {
+            Some(row_length) => row_length,
+            None => self.layout()
+                .map(|layout| layout.columns())
+                .unwrap_or_else(|_| (self.sample_count() as Float).sqrt().ceil() as usize),
+        }.max(1);
+
+        let keys: Vec<usize> = self.data_map.keys().copied().collect();
+        let mut rng = SplitMix64(options.seed);
+
+        let shuffled = if options.paper_white_per_row {
+            let paper = self.paper_white()?;
+            let (mut whites, others): (Vec<usize>, Vec<usize>) = keys.iter().partition(|key| paper.contains(key));
+            let rows = keys.len().div_ceil(row_length);
+            if whites.len() < rows {
+                return Err(Error::Other(format!(
+                    "{} paper white patches cannot fill {} rows!", whites.len(), rows
+                )));
+            }
+
+            rng.shuffle(&mut whites);
+            let mut rest: Vec<usize> = whites.split_off(rows).into_iter().chain(others).collect();
+            rng.shuffle(&mut rest);
+
+            let mut rest = rest.into_iter();
+            let mut shuffled = Vec::with_capacity(keys.len());
+            for (row, white) in whites.into_iter().enumerate() {
+                let length = row_length.min(keys.len() - row * row_length);
+                let mut patches: Vec<usize> = std::iter::once(white).chain(rest.by_ref().take(length - 1)).collect();
+                rng.shuffle(&mut patches);
+                shuffled.extend(patches);
+            }
+            shuffled
+        } else {
+            let mut shuffled = keys.clone();
+            rng.shuffle(&mut shuffled);
+            shuffled
+        };
+
+        // Randomizing a randomized data set keeps the original canonical order and labels
+        let canonical = match self.randomized_order()? {
+            Some(order) => order,
+            None => (1..=keys.len()).collect(),
+        };
+        let ids = match self.randomized_labels(RANDOMIZED_IDS)? {
+            Some(ids) => Some(ids),
+            None => self.labels(&Field::SAMPLE_ID),
+        };
+        let names = match self.randomized_labels(RANDOMIZED_NAMES)? {
+            Some(names) => Some(names),
+            None => self.labels(&Field::SAMPLE_NAME).filter(|names| {
+                names.iter().all(|name| PatchPosition::from_name(name).is_some())
+            }),
+        };
+        let row_lengths = match self.keyword(RANDOMIZED_ROWLENGTH) {
+            Some(canonical) => Some(canonical),
+            None if self.randomized_order()?.is_some() => self.keyword("LGOROWLENGTH"),
+            None => Some(self.keyword("LGOROWLENGTH").unwrap_or_else(|| "0".to_string())),
+        };
+        let index: BTreeMap<usize, usize> = keys.iter().enumerate().map(|(index, key)| (*key, index)).collect();
+
+        let mut cgats = self.derive();
+        let mut order = Vec::with_capacity(shuffled.len());
+        for (new, key) in shuffled.iter().enumerate() {
+            cgats.data_map.insert(new, self.data_map[key].clone());
+            order.push(canonical[index[key]]);
+        }
+
+        if cgats.keyword("LGOROWLENGTH") != Some(row_length.to_string()) {
+            cgats.set_row_length(&row_length.to_string());
+        }
+        match row_lengths {
+            Some(canonical) if canonical != row_length.to_string() => cgats.set_keyword(RANDOMIZED_ROWLENGTH, &canonical),
+            _ => cgats.meta.lines.retain(|line| line.keyword() != RANDOMIZED_ROWLENGTH),
+        }
+        cgats.set_keyword(RANDOMIZED_ORDER, &join_order(&order));
+        if let Some(ids) = ids {
+            cgats.set_keyword(RANDOMIZED_IDS, &ids.join(" "));
+        }
+        if let Some(names) = names {
+            cgats.set_keyword(RANDOMIZED_NAMES, &names.join(" "));
+        }
+        cgats.relabel(row_length)?;
+
+        Ok(cgats)
+    }
+
+    pub fn randomized_order(&self) -> Result<Option<Vec<usize>>> {
+    //! The canonical (1-based) index of each sample from RANDOMIZED_ORDER.
+    //! Returns an Error if it is not an order of all the samples.
+        let order = match self.keyword(RANDOMIZED_ORDER) {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+
+        let invalid = || Error::Other(format!("Invalid {}!", RANDOMIZED_ORDER));
+        let order = order.split_whitespace()
+            .map(|index| index.parse::<usize>().map_err(|_| invalid()))
+            .collect::<Result<Vec<usize>>>()?;
+
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        if sorted.len() != self.sample_count() || !sorted.iter().copied().eq(1..=self.sample_count()) {
+            return Err(invalid());
+        }
+
+        Ok(Some(order))
+    }
+
+    fn randomized_labels(&self, keyword: &str) -> Result<Option<Vec<String>>> {
+        // The original SAMPLE_IDs or SAMPLE_NAMEs in canonical order
+        let labels = match self.keyword(keyword) {
+            Some(labels) => labels,
+            None => return Ok(None),
+        };
+
+        let labels: Vec<String> = labels.split_whitespace().map(String::from).collect();
+        if labels.len() != self.sample_count() {
+            return Err(Error::Other(format!("Invalid {}!", keyword)));
+        }
+
+        Ok(Some(labels))
+    }
+
+    fn labels(&self, field: &Field) -> Option<Vec<String>> {
+        // The values of a field in the order of the data, if none is empty or has spaces
+        let index = self.field_index(field)?;
+        self.data_map.values()
+            .map(|sample| sample.values.get(index)
+                .map(|value| value.string.clone())
+                .filter(|label| !label.is_empty() && !label.contains(char::is_whitespace)))
+            .collect()
+    }
+
+    pub fn derandomize(&self) -> Result<Cgats> {
+    //! Restore the canonical order of a randomized data set from its RANDOMIZED_ORDER,
+    //! the SAMPLE_IDs and SAMPLE_NAMEs from RANDOMIZED_IDS and RANDOMIZED_NAMES,
+    //! and the LGOROWLENGTH from RANDOMIZED_ROWLENGTH
+        self.restore(self)
+    }
+
+    pub fn derandomize_with(&self, randomized: &Cgats) -> Result<Cgats> {
+    //! Restore the canonical order of measurements made from a randomized
+    //! target, for instruments that do not keep the RANDOMIZED_* keywords
+        if randomized.sample_count() != self.sample_count() {
+            return Err(Error::CannotCompare);
+        }
+        self.restore(randomized)
+    }
+
+    fn restore(&self, randomized: &Cgats) -> Result<Cgats> {
+        // Put the samples back in canonical order with their original labels
+        // from the keywords of the randomized data set
+        let order = randomized.randomized_order()?
+            .ok_or_else(|| Error::Other(format!("Cannot find {}!", RANDOMIZED_ORDER)))?;
+
+        let mut cgats = self.derive();
+        for (sample, index) in self.data_map.values().zip(&order) {
+            cgats.data_map.insert(index - 1, sample.clone());
+        }
+
+        for (keyword, field) in [(RANDOMIZED_IDS, Field::SAMPLE_ID), (RANDOMIZED_NAMES, Field::SAMPLE_NAME)] {
+            if let (Some(labels), Some(index)) = (randomized.randomized_labels(keyword)?, cgats.field_index(&field)) {
+                for (sample, label) in cgats.data_map.values_mut().zip(labels) {
+                    sample.values[index] = label.parse()?;
+                }
+            }
+        }
+
+        match randomized.keyword(RANDOMIZED_ROWLENGTH).as_deref() {
+            Some("0") => cgats.meta.lines.retain(|line| line.keyword() != "LGOROWLENGTH"),
+            Some(row_length) => cgats.set_row_length(row_length),
+            None => (),
+        }
+
+        cgats.meta.lines.retain(|line| !RANDOMIZED_KEYWORDS.contains(&line.keyword()));
+
+        Ok(cgats)
+    }
+
+    fn set_row_length(&mut self, row_length: &str) {
+        // LGOROWLENGTH is written without quotes, like `TargetGenerator` writes it
+        let line = DataLine::from(vec!["LGOROWLENGTH".to_string(), row_length.to_string()]);
+        match self.meta.lines.iter_mut().find(|line| line.keyword() == "LGOROWLENGTH") {
+            Some(existing) => *existing = line,
+            None => self.meta.lines.push(line),
+        }
+    }
+
+    fn relabel(&mut self, row_length: usize) -> Result<()> {
+        // Number the SAMPLE_IDs from 1 and rename alphanumeric SAMPLE_NAMEs by
+        // their chart position, row by row
+        if let Some(index) = self.field_index(&Field::SAMPLE_ID) {
+            for (key, sample) in self.data_map.iter_mut() {
+                sample.values[index] = CgatsValue::from_float((key + 1) as Float);
+            }
+        }
+
+        if let Some(index) = self.field_index(&Field::SAMPLE_NAME) {
+            let named = self.data_map.values()
+                .all(|sample| sample.values.get(index).and_then(|name| PatchPosition::from_name(&name.string)).is_some());
+            if named {
+                let layout = ChartLayout::new(self.sample_count(), row_length, ScanOrder::RowMajor)?;
+                for (key, sample) in self.data_map.iter_mut() {
+                    let name = layout.position(*key).expect("Key is in the layout").name();
+                    sample.values[index] = CgatsValue { string: name, float: None };
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn paper_white(&self) -> Result<Vec<usize>> {
+        // The keys of the samples without ink (CMYK 0) or at full RGB
+        let space = DeviceSpace::from_fields(&self.fields)
+            .ok_or_else(|| Error::Other("No CMYK or RGB device values to find paper white!".to_string()))?;
+        let indexes: Vec<usize> = space.fields().iter()
+            .map(|field| self.field_index(field).expect("Device field"))
+            .collect();
+
+        Ok(self.data_map.iter()
+            .filter(|(_, sample)| {
+                let values: Vec<Float> = indexes.iter().filter_map(|index| sample.values[*index].float).collect();
+                values.len() == indexes.len() && match space {
+                    DeviceSpace::Cmyk => values.iter().all(|value| *value == 0.0),
+                    DeviceSpace::Rgb => values.iter().all(|value| *value == 100.0)
+                        || values.iter().all(|value| *value == 255.0),
+                }
+            })
+            .map(|(key, _)| *key)
+            .collect())
+    }
+}
+
+fn join_order(order: &[usize]) -> String {
+    order.iter().map(|index| index.to_string()).collect::<Vec<String>>().join(" ")
+}
+
+// A small, fixed pseudo-random generator, so that a seed gives the same
+// order on every platform and crate version
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next() as u128 * n as u128) >> 64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        // Fisher-Yates
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[test]
+fn randomize_target() -> Result<()> {
+    let target = TargetGenerator::new(DeviceSpace::Cmyk)
+        .values(&vec![vec![0.0; 4]; 9])
+        .ramps(11)
+        .row_length(8)
+        .build()?;
+    assert_eq!(target.sample_count(), 59);
+
+    let options = RandomizeOptions::new(42).paper_white_per_row(true);
+    let random = target.randomize(&options)?;
+    assert_eq!(random, target.randomize(&options)?);
+    assert_ne!(random.data_map, target.randomize(&RandomizeOptions::new(43))?.data_map);
+    assert_ne!(random.data_map, target.data_map);
+
+    // Renumbered and renamed for the new positions, with paper white in every row
+    assert_eq!(random.data_map[&9].values[0].string, "10");
+    assert_eq!(random.data_map[&9].values[1].string, "B2");
+    let layout = random.layout()?;
+    let paper = random.paper_white()?;
+    assert_eq!(layout.rows(), 8);
+    for row in 0..layout.rows() {
+        assert!(paper.iter().any(|key| layout.position(*key).is_some_and(|position| position.row == row)));
+    }
+
+    assert_eq!(random.derandomize()?.format(), target.format());
+
+    // Randomizing twice still restores the canonical order
+    let twice = random.randomize(&RandomizeOptions::new(7))?;
+    assert_eq!(twice.derandomize()?.data_map, target.data_map);
+
+    // Measurements that lost the header are restored with the randomized target
+    let mut measured = random.clone();
+    measured.meta.lines.retain(|line| line.keyword() != RANDOMIZED_ORDER);
+    assert!(measured.derandomize().is_err());
+    assert_eq!(measured.derandomize_with(&random)?.data_map, target.data_map);
+
+    // Labels that are not positions are not renamed and need not be kept
+    let mut words = target.clone();
+    words.data_map.get_mut(&0).expect("Sample").values[1].string = "Paper White".to_string();
+    let random_words = words.randomize(&options)?;
+    assert!(random_words.keyword(RANDOMIZED_NAMES).is_none());
+    assert_eq!(random_words.derandomize()?.data_map, words.data_map);
+
+    assert!(target.randomize(&options.row_length(6)).is_err());
+    let mut invalid = random.clone();
+    invalid.set_keyword(RANDOMIZED_ORDER, "1 1 2");
+    assert!(invalid.randomized_order().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn randomize_p2p51() -> Result<()> {
+    // Listed column by column with SampleIDs that are not in the order of the data
+    let p2p = Cgats::from_file("reference/P2P51.txt")?;
+    let random = p2p.randomize(&RandomizeOptions::new(1))?;
+    assert_eq!(random.data_map[&0].values[1].string, "A1");
+
+    let restored = random.derandomize()?;
+    assert_eq!(restored.data_map, p2p.data_map);
+    assert_eq!(restored.data_map[&0].values[1].string, "A12");
+    assert_eq!(restored.keyword(RANDOMIZED_NAMES), None);
+
+    // Also after writing and reading the randomized target
+    let reread = Cgats::from_reader(random.format().as_bytes())?;
+    assert_eq!(reread.derandomize()?.data_map, p2p.data_map);
+
+    let mut measured = random.clone();
+    measured.meta.lines.retain(|line| !line.keyword().starts_with("RANDOMIZED_"));
+    assert_eq!(measured.derandomize_with(&random)?.data_map, p2p.data_map);
+
+    Ok(())
+}
+
+#[test]
+fn randomize_row_length() -> Result<()> {
+    let target = TargetGenerator::new(DeviceSpace::Cmyk)
+        .ramps(11)
+        .row_length(8)
+        .build()?;
+
+    // Randomized onto a chart with another row length, twice
+    let random = target.randomize(&RandomizeOptions::new(3).row_length(5))?;
+    assert_eq!(random.keyword("LGOROWLENGTH"), Some("5".to_string()));
+    assert_eq!(random.keyword(RANDOMIZED_ROWLENGTH), Some("8".to_string()));
+    assert_eq!(random.data_map[&5].values[1].string, "B1");
+
+    let twice = random.randomize(&RandomizeOptions::new(4).row_length(6))?;
+    assert_eq!(twice.keyword(RANDOMIZED_ROWLENGTH), Some("8".to_string()));
+    for randomized in [&random, &twice] {
+        let restored = randomized.derandomize()?;
+        assert_eq!(restored.format(), target.format());
+        assert_eq!(restored.layout()?.columns(), 8);
+
+        // Also when the measurements lost the keywords
+        let mut measured = randomized.clone();
+        measured.meta.lines.retain(|line| !line.keyword().starts_with("RANDOMIZED_"));
+        assert_eq!(measured.derandomize_with(randomized)?.keyword("LGOROWLENGTH"), Some("8".to_string()));
+    }
+
+    // Back on the canonical row length, there is nothing to restore
+    let back = random.randomize(&RandomizeOptions::new(5).row_length(8))?;
+    assert_eq!(back.keyword(RANDOMIZED_ROWLENGTH), None);
+    assert_eq!(back.derandomize()?.format(), target.format());
+
+    // A row length the data set did not have is removed again
+    let mut unspecified = target.clone();
+    unspecified.meta.lines.retain(|line| line.keyword() != "LGOROWLENGTH");
+    let random = unspecified.randomize(&RandomizeOptions::new(6).row_length(5))?;
+    assert_eq!(random.keyword(RANDOMIZED_ROWLENGTH), Some("0".to_string()));
+    assert_eq!(random.derandomize()?.format(), unspecified.format());
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! Reference characterization data bundled with the crate:
+//! the ISO 15339 Characterized Reference Printing Conditions (CRPC1...CRPC7)
+//! and the P2P51 G7 target layout.
+
+use super::*;
+
+use deltae::{DeltaE, DEMethod};
+use std::fmt;
+
+const CRPC: [&str; 7] = [
+    include_str!("../reference/ISO15339-CRPC1.txt"),
+    include_str!("../reference/ISO15339-CRPC2.txt"),
+    include_str!("../reference/ISO15339-CRPC3.txt"),
+    include_str!("../reference/ISO15339-CRPC4.txt"),
+    include_str!("../reference/ISO15339-CRPC5.txt"),
+    include_str!("../reference/ISO15339-CRPC6.txt"),
+    include_str!("../reference/ISO15339-CRPC7.txt"),
+];
+
+const P2P51: &str = include_str!("../reference/P2P51.txt");
+
+/// The number of ISO 15339 reference printing conditions
+pub const CRPC_COUNT: u8 = 7;
+
+pub fn crpc(n: u8) -> Result<Cgats> {
+//! The characterization data of ISO 15339 CRPC `n` (1...7)
+    match n {
+        1..=CRPC_COUNT => Cgats::from_reader(CRPC[n as usize - 1].as_bytes()),
+        _ => Err(Error::Other(format!("There is no CRPC{}! Expected 1 through {}", n, CRPC_COUNT))),
+    }
+}
+
+pub fn p2p51() -> Result<Cgats> {
+//! The P2P51 target layout (device values only)
+    Cgats::from_reader(P2P51.as_bytes())
+}
+
+/// How closely a measured data set matches one reference printing condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrpcMatch {
+    pub crpc: u8,
+    pub method: DEMethod,
+    /// Patches whose device values the reference measured
+    pub patches: usize,
+    /// Patches whose reference LAB was interpolated from the characterization data
+    pub predicted: usize,
+    pub mean: Float,
+    pub max: Float,
+}
+
+impl fmt::Display for CrpcMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CRPC{}\t{} patches\t{} predicted\tAverage {}: {:0.4}\tMax: {:0.4}",
+            self.crpc, self.patches, self.predicted, Field::from_de_method(self.method), self.mean, self.max)
+    }
+}
+
+pub fn rank_crpc(measured: &Cgats, method: DEMethod) -> Result<Vec<CrpcMatch>> {
+//! Rank the reference printing conditions by their average Delta E to a measured
+//! CMYK data set, best match first. Only patches whose device values the reference
+//! measured are compared.
+//! Returns an Error if the measured data has no CMYK and LAB (or XYZ),
+//! or none of its patches are in the reference data.
+    rank(measured, method, false)
+}
+
+pub fn rank_crpc_predicted(measured: &Cgats, method: DEMethod) -> Result<Vec<CrpcMatch>> {
+//! Like `rank_crpc`, but patches that the reference did not measure are predicted
+//! from its characterization data and compared as well.
+    rank(measured, method, true)
+}
+
+fn rank(measured: &Cgats, method: DEMethod, predict: bool) -> Result<Vec<CrpcMatch>> {
+    let keys: Vec<usize> = measured.data_map.keys()
+        .copied()
+        .filter(|key| measured.sample_lab(*key).is_some())
+        .collect();
+
+    if !measured.has_cmyk() || keys.is_empty() {
+        return Err(Error::IncompleteData);
+    }
+
+    let cmyk = [Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K];
+    let mut ranking = Vec::with_capacity(CRPC_COUNT as usize);
+
+    for n in 1..=CRPC_COUNT {
+        let model = crpc(n)?.characterization_model()?;
+
+        let mut patches = 0;
+        let mut predicted = 0;
+        let mut sum = 0.0;
+        let mut max: Float = 0.0;
+        for key in &keys {
+            let device = measured.device_values(*key, &cmyk).ok_or(Error::IncompleteData)?;
+            let (reference, prediction) = model.predict(&device)?;
+
+            match prediction {
+                Prediction::Node => patches += 1,
+                _ if predict => predicted += 1,
+                _ => continue,
+            }
+
+            let lab = measured.sample_lab(*key).ok_or(Error::IncompleteData)?;
+            let de = DeltaE::new(&lab, &reference, method).value;
+            sum += de;
+            max = max.max(de);
+        }
+
+        if patches + predicted == 0 {
+            return Err(Error::IncompleteData);
+        }
+
+        ranking.push(CrpcMatch {
+            crpc: n,
+            method,
+            patches,
+            predicted,
+            mean: sum / (patches + predicted) as Float,
+            max,
+        });
+    }
+
+    ranking.sort_by(|a, b| a.mean.partial_cmp(&b.mean).expect("Delta E is NaN!"));
+
+    Ok(ranking)
+}
+
+#[test]
+fn bundled_references() -> Result<()> {
+    for n in 1..=CRPC_COUNT {
+        let reference = crpc(n)?;
+        assert_eq!(reference, Cgats::from_file(format!("reference/ISO15339-CRPC{}.txt", n))?);
+        assert_eq!(reference.sample_count(), 1617);
+    }
+
+    assert!(crpc(0).is_err());
+    assert!(crpc(8).is_err());
+
+    let p2p = p2p51()?;
+    assert_eq!(p2p, Cgats::from_file("reference/P2P51.txt")?);
+    assert_eq!(p2p.sample_count(), 300);
+
+    Ok(())
+}
+
+#[test]
+fn nearest_crpc() -> Result<()> {
+    let ranking = rank_crpc(&crpc(5)?, DEMethod::DE2000)?;
+    for crpc_match in &ranking {
+        println!("{}", crpc_match);
+    }
+
+    assert_eq!(ranking.len(), CRPC_COUNT as usize);
+    assert_eq!(ranking[0].crpc, 5);
+    assert_eq!(ranking[0].mean, 0.0);
+    assert_eq!(ranking[0].patches, 1617);
+    assert_eq!(ranking[0].predicted, 0);
+    assert!(ranking[1].mean > 1.0);
+
+    let p2p = p2p51()?;
+    assert!(rank_crpc(&p2p, DEMethod::DE2000).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn predicted_crpc_patches() -> Result<()> {
+    let mut measured = crpc(5)?;
+    let index = measured.field_index(&Field::CMYK_C).expect("No CMYK_C!");
+    measured.data_map.get_mut(&0).expect("No sample 0!").values[index] = CgatsValue::from_float(33.3);
+
+    let matched = rank_crpc(&measured, DEMethod::DE2000)?;
+    assert_eq!(matched[0].crpc, 5);
+    assert_eq!(matched[0].patches, 1616);
+    assert_eq!(matched[0].predicted, 0);
+    assert_eq!(matched[0].mean, 0.0);
+
+    let predicted = rank_crpc_predicted(&measured, DEMethod::DE2000)?;
+    assert_eq!(predicted[0].crpc, 5);
+    assert_eq!(predicted[0].patches, 1616);
+    assert_eq!(predicted[0].predicted, 1);
+    assert!(predicted[0].mean > 0.0);
+
+    Ok(())
+}
@@ -0,0 +1,372 @@
+use super::*;
+
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::{Rational, TiffEncoder, TiffValue};
+use tiff::tags::ResolutionUnit;
+
+/// Image file format of a rendered chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    /// RGB only
+    Png,
+    #[default]
+    Tiff,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Tiff => "tif",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ImageFormat> {
+        match s.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "tif" | "tiff" => Ok(ImageFormat::Tiff),
+            _ => Err(Error::Other(format!("Unknown image format: '{}'", s))),
+        }
+    }
+}
+
+/// Bits per channel of a rendered chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn max(self) -> Float {
+        match self {
+            BitDepth::Eight => u8::MAX as Float,
+            BitDepth::Sixteen => u16::MAX as Float,
+        }
+    }
+}
+
+/// The range of the RGB device values of a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RgbRange {
+    /// 0...100, like CMYK
+    #[default]
+    Percent,
+    /// 0...255
+    Byte,
+}
+
+impl RgbRange {
+    fn max(self) -> Float {
+        match self {
+            RgbRange::Percent => 100.0,
+            RgbRange::Byte => u8::MAX as Float,
+        }
+    }
+}
+
+/// How to render the device values of a target as a printable chart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    format:        ImageFormat,
+    depth:         BitDepth,
+    rgb_range:     RgbRange,
+    patch_size:    u32,
+    gap:           u32,
+    margin:        u32,
+    row_length:    Option<usize>,
+    rows_per_page: Option<usize>,
+    dpi:           u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            format:        ImageFormat::Tiff,
+            depth:         BitDepth::Eight,
+            rgb_range:     RgbRange::Percent,
+            patch_size:    72,
+            gap:           0,
+            margin:        36,
+            row_length:    None,
+            rows_per_page: None,
+            dpi:           300,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> RenderOptions {
+    //! An 8-bit TIFF at 300 dpi with 72 pixel patches, the layout of the
+    //! data (see `Cgats::layout`) and all rows on one page
+        RenderOptions::default()
+    }
+
+    pub fn format(mut self, format: ImageFormat) -> RenderOptions {
+        self.format = format;
+        self
+    }
+
+    pub fn depth(mut self, depth: BitDepth) -> RenderOptions {
+        self.depth = depth;
+        self
+    }
+
+    pub fn rgb_range(mut self, range: RgbRange) -> RenderOptions {
+    //! The range of the RGB values in the data; CMYK is always in percent
+        self.rgb_range = range;
+        self
+    }
+
+    pub fn patch_size(mut self, pixels: u32) -> RenderOptions {
+    //! Width and height of a patch in pixels
+        self.patch_size = pixels.max(1);
+        self
+    }
+
+    pub fn gap(mut self, pixels: u32) -> RenderOptions {
+    //! Paper between the patches in pixels
+        self.gap = pixels;
+        self
+    }
+
+    pub fn margin(mut self, pixels: u32) -> RenderOptions {
+    //! Paper around the patches in pixels
+        self.margin = pixels;
+        self
+    }
+
+    pub fn row_length(mut self, row_length: usize) -> RenderOptions {
+    //! Lay the samples out in rows of `row_length` in the order of the data
+        self.row_length = Some(row_length);
+        self
+    }
+
+    pub fn rows_per_page(mut self, rows: usize) -> RenderOptions {
+    //! Split the chart into pages of at most `rows` rows
+        self.rows_per_page = Some(rows.max(1));
+        self
+    }
+
+    pub fn dpi(mut self, dpi: u32) -> RenderOptions {
+    //! Resolution written to the image file
+        self.dpi = dpi.max(1);
+        self
+    }
+}
+
+impl Cgats {
+    pub fn render(&self, options: &RenderOptions) -> Result<Vec<Vec<u8>>> {
+    //! Render the CMYK or RGB device values as chart images, one encoded image per page.
+    //! CMYK values are percentages; RGB values are in the `RgbRange` of the options.
+    //! Returns an Error if there are no device values or CMYK is rendered as PNG.
+        let space = DeviceSpace::from_fields(&self.fields)
+            .ok_or_else(|| Error::Other("No CMYK or RGB device values to render!".to_string()))?;
+        if self.data_map.is_empty() {
+            return Err(Error::NoData);
+        }
+        if space == DeviceSpace::Cmyk && options.format == ImageFormat::Png {
+            return Err(Error::Other("PNG cannot hold CMYK; render CMYK charts as TIFF!".to_string()));
+        }
+
+        let layout = match options.row_length {
+            Some(row_length) => ChartLayout::from_row_length(self, row_length)?,
+            None => self.layout().or_else(|_| {
+                let row_length = (self.sample_count() as Float).sqrt().ceil() as usize;
+                ChartLayout::from_row_length(self, row_length.max(1))
+            })?,
+        };
+
+        let indexes: Vec<usize> = space.fields().iter()
+            .map(|field| self.field_index(field).expect("Device field"))
+            .collect();
+        let scale = match space {
+            DeviceSpace::Cmyk => 100.0,
+            DeviceSpace::Rgb => options.rgb_range.max(),
+        };
+
+        // Paper is no ink, or full RGB
+        let paper: Vec<Float> = match space {
+            DeviceSpace::Cmyk => vec![0.0; 4],
+            DeviceSpace::Rgb => vec![1.0; 3],
+        };
+
+        let rows_per_page = options.rows_per_page.unwrap_or(layout.rows()).max(1);
+        let pages = layout.rows().div_ceil(rows_per_page).max(1);
+        let pitch = (options.patch_size + options.gap) as usize;
+        let width = 2 * options.margin as usize + layout.columns() * pitch - options.gap as usize;
+
+        (0..pages).map(|page| {
+            let first_row = page * rows_per_page;
+            let rows = rows_per_page.min(layout.rows() - first_row);
+            let height = 2 * options.margin as usize + rows * pitch - options.gap as usize;
+
+            let mut pixels: Vec<Float> = paper.iter().copied().cycle().take(width * height * paper.len()).collect();
+
+            for (key, sample) in self.data_map.iter() {
+                let position = match layout.position(*key) {
+                    Some(position) if (first_row..first_row + rows).contains(&position.row) => position,
+                    _ => continue,
+                };
+                let color: Vec<Float> = indexes.iter()
+                    .map(|index| (sample.values[*index].float.unwrap_or_default() / scale).clamp(0.0, 1.0))
+                    .collect();
+
+                let x0 = options.margin as usize + position.column * pitch;
+                let y0 = options.margin as usize + (position.row - first_row) * pitch;
+                for y in y0..y0 + options.patch_size as usize {
+                    let start = (y * width + x0) * color.len();
+                    let end = start + options.patch_size as usize * color.len();
+                    pixels[start..end].chunks_mut(color.len()).for_each(|pixel| pixel.copy_from_slice(&color));
+                }
+            }
+
+            encode(&pixels, width as u32, height as u32, space, options)
+        }).collect()
+    }
+
+    pub fn render_to_files<P: AsRef<Path>>(&self, path: P, options: &RenderOptions) -> Result<Vec<PathBuf>> {
+    //! Render the chart and write each page to a file. Pages after the first
+    //! get a page number, e.g. chart.tif, chart-2.tif, chart-3.tif.
+        let path = path.as_ref();
+        let pages = self.render(options)?;
+
+        pages.iter().enumerate().map(|(page, image)| {
+            let file = if page == 0 {
+                path.to_path_buf()
+            } else {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                path.with_file_name(format!("{}-{}.{}", stem, page + 1, options.format.extension()))
+            };
+            let mut buf = BufWriter::new(File::create(&file)?);
+            buf.write_all(image)?;
+            Ok(file)
+        }).collect()
+    }
+}
+
+fn encode(pixels: &[Float], width: u32, height: u32, space: DeviceSpace, options: &RenderOptions) -> Result<Vec<u8>> {
+    let max = options.depth.max();
+    let bytes: Vec<u8> = pixels.iter().map(|value| (value * max).round() as u8).collect();
+    let words: Vec<u16> = pixels.iter().map(|value| (value * max).round() as u16).collect();
+
+    match (options.format, space, options.depth) {
+        (ImageFormat::Png, _, BitDepth::Eight) => encode_png(&bytes, width, height, png::BitDepth::Eight, options.dpi),
+        (ImageFormat::Png, _, BitDepth::Sixteen) => {
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+            encode_png(&bytes, width, height, png::BitDepth::Sixteen, options.dpi)
+        },
+        (ImageFormat::Tiff, DeviceSpace::Cmyk, BitDepth::Eight) => encode_tiff::<colortype::CMYK8>(&bytes, width, height, options.dpi),
+        (ImageFormat::Tiff, DeviceSpace::Cmyk, BitDepth::Sixteen) => encode_tiff::<colortype::CMYK16>(&words, width, height, options.dpi),
+        (ImageFormat::Tiff, DeviceSpace::Rgb, BitDepth::Eight) => encode_tiff::<colortype::RGB8>(&bytes, width, height, options.dpi),
+        (ImageFormat::Tiff, DeviceSpace::Rgb, BitDepth::Sixteen) => encode_tiff::<colortype::RGB16>(&words, width, height, options.dpi),
+    }
+}
+
+fn encode_png(data: &[u8], width: u32, height: u32, depth: png::BitDepth, dpi: u32) -> Result<Vec<u8>> {
+    let png_error = |e: png::EncodingError| Error::Other(format!("Cannot encode PNG: {}", e));
+
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    let pixels_per_meter = (dpi as Float / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(data).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+
+    Ok(image)
+}
+
+fn encode_tiff<C: ColorType>(data: &[C::Inner], width: u32, height: u32, dpi: u32) -> Result<Vec<u8>>
+where
+    [C::Inner]: TiffValue,
+{
+    let tiff_error = |e: tiff::TiffError| Error::Other(format!("Cannot encode TIFF: {}", e));
+
+    let mut image = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut image).map_err(tiff_error)?;
+    let mut tiff = encoder.new_image::<C>(width, height).map_err(tiff_error)?;
+    tiff.resolution(ResolutionUnit::Inch, Rational { n: dpi, d: 1 });
+    tiff.write_data(data).map_err(tiff_error)?;
+
+    Ok(image.into_inner())
+}
+
+#[test]
+fn render_charts() -> Result<()> {
+    let target = TargetGenerator::new(DeviceSpace::Cmyk).ramps(6).row_length(6).build()?;
+    assert_eq!(target.sample_count(), 26);
+
+    let options = RenderOptions::new().patch_size(10).gap(2).margin(4);
+    let pages = target.render(&options)?;
+    assert_eq!(pages.len(), 1);
+    assert!(pages[0].starts_with(b"II*\0") || pages[0].starts_with(b"MM\0*"));
+
+    // 5 rows: 2 + 2 + 1 on three pages
+    let pages = target.render(&options.clone().rows_per_page(2).depth(BitDepth::Sixteen))?;
+    assert_eq!(pages.len(), 3);
+    assert!(pages[1].len() > pages[2].len());
+
+    // The second patch is 20 % cyan, after a margin of 4 and a gap of 2 pixels
+    let mut decoder = tiff::decoder::Decoder::new(Cursor::new(&pages[0])).map_err(|_| Error::FileError)?;
+    assert_eq!(decoder.dimensions().map_err(|_| Error::FileError)?, (4 + 6 * 12 - 2 + 4, 4 + 2 * 12 - 2 + 4));
+    let pixels = match decoder.read_image().map_err(|_| Error::FileError)? {
+        tiff::decoder::DecodingResult::U16(pixels) => pixels,
+        _ => return Err(Error::FileError),
+    };
+    let pixel = |x: usize, y: usize| pixels[(y * 78 + x) * 4..(y * 78 + x) * 4 + 4].to_vec();
+    assert_eq!(pixel(4 + 12, 4), vec![13107, 0, 0, 0]);
+    assert_eq!(pixel(4 + 10, 4), vec![0, 0, 0, 0]);
+
+    assert!(target.render(&options.clone().format(ImageFormat::Png)).is_err());
+
+    let rgb = TargetGenerator::new(DeviceSpace::Rgb).grid(2).build()?;
+    let png = rgb.render(&RenderOptions::new().format(ImageFormat::Png).patch_size(4).margin(0))?;
+    let mut reader = png::Decoder::new(png[0].as_slice()).read_info().map_err(|_| Error::FileError)?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).map_err(|_| Error::FileError)?;
+    assert_eq!(pixels[..3], [0, 0, 0]);
+    assert_eq!(pixels[4 * 3..4 * 3 + 3], [0, 0, 255]);
+
+    // RGB is not guessed to be 0...255 from its values
+    let bytes = TargetGenerator::new(DeviceSpace::Rgb).values(&[vec![51.0, 100.0, 100.0]]).build()?;
+    let png_options = RenderOptions::new().format(ImageFormat::Png).patch_size(1).margin(0);
+    let first_pixel = |options: &RenderOptions| -> Result<Vec<u8>> {
+        let png = bytes.render(options)?;
+        let mut reader = png::Decoder::new(png[0].as_slice()).read_info().map_err(|_| Error::FileError)?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).map_err(|_| Error::FileError)?;
+        Ok(pixels[..3].to_vec())
+    };
+    assert_eq!(first_pixel(&png_options.clone().rgb_range(RgbRange::Byte))?, vec![51, 100, 100]);
+    assert_eq!(first_pixel(&png_options)?, vec![130, 255, 255]);
+
+    let temp = test::mktemp()?;
+    let files = target.render_to_files(&temp, &options.rows_per_page(3))?;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0], temp);
+    assert!(files[1].to_string_lossy().ends_with("-2.tif"));
+    for file in &files {
+        assert!(std::fs::metadata(file)?.len() > 0);
+        std::fs::remove_file(file)?;
+    }
+
+    Ok(())
+}
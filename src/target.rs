@@ -0,0 +1,296 @@
+use super::*;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+// Tone levels of the ISO 12642-2 characterization data
+const ISO_12642_LEVELS: &[Float] = &[0.0, 10.0, 20.0, 40.0, 70.0, 100.0];
+const ISO_12642_BLACK: &[Float] = &[40.0, 70.0, 100.0];
+const ISO_12642_BLACK_RAMP: &[Float] = &[
+    3.0, 7.0, 10.0, 15.0, 20.0, 25.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0, 90.0, 100.0,
+];
+
+/// The device color space of a generated target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeviceSpace {
+    #[default]
+    Cmyk,
+    Rgb,
+}
+
+impl DeviceSpace {
+    pub fn fields(self) -> DataFormat {
+    //! The device value fields
+        match self {
+            DeviceSpace::Cmyk => vec![Field::CMYK_C, Field::CMYK_M, Field::CMYK_Y, Field::CMYK_K],
+            DeviceSpace::Rgb => vec![Field::RGB_R, Field::RGB_G, Field::RGB_B],
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        self.fields().len()
+    }
+
+    pub fn from_fields(fields: &DataFormat) -> Option<DeviceSpace> {
+    //! The CMYK or RGB device space of a DATA_FORMAT
+        [DeviceSpace::Cmyk, DeviceSpace::Rgb].iter()
+            .copied()
+            .find(|space| space.fields().iter().all(|field| fields.contains(field)))
+    }
+}
+
+impl FromStr for DeviceSpace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<DeviceSpace> {
+        match s.trim().to_uppercase().as_str() {
+            "CMYK" => Ok(DeviceSpace::Cmyk),
+            "RGB" => Ok(DeviceSpace::Rgb),
+            _ => Err(Error::Other(format!("Unknown device space: '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for DeviceSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            DeviceSpace::Cmyk => "CMYK",
+            DeviceSpace::Rgb => "RGB",
+        })
+    }
+}
+
+/// Generates the device values of a test target, in percent
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TargetGenerator {
+    space: DeviceSpace,
+    values: Vec<Vec<Float>>,
+    row_length: Option<usize>,
+}
+
+impl TargetGenerator {
+    pub fn new(space: DeviceSpace) -> TargetGenerator {
+        TargetGenerator { space, ..TargetGenerator::default() }
+    }
+
+    pub fn from_cgats(cgats: &Cgats) -> Result<TargetGenerator> {
+    //! A target with the device values of an existing data set
+        let space = DeviceSpace::from_fields(&cgats.fields)
+            .ok_or_else(|| Error::Other("No CMYK or RGB device values!".to_string()))?;
+        let indexes = space.fields().iter()
+            .map(|field| cgats.field_index(field).expect("Device field"))
+            .collect::<Vec<usize>>();
+
+        let values = cgats.data_map.values()
+            .map(|sample| indexes.iter()
+                .map(|index| sample.values[*index].float.ok_or(Error::IncompleteData))
+                .collect::<Result<Vec<Float>>>())
+            .collect::<Result<Vec<Vec<Float>>>>()?;
+
+        let row_length = cgats.keyword("LGOROWLENGTH").and_then(|length| length.parse().ok());
+
+        Ok(TargetGenerator { space, values, row_length })
+    }
+
+    pub fn space(&self) -> DeviceSpace {
+        self.space
+    }
+
+    pub fn device_values(&self) -> &[Vec<Float>] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn row_length(mut self, row_length: usize) -> TargetGenerator {
+    //! Patches per row of the chart [default: the square root of the patch count]
+        self.row_length = Some(row_length);
+        self
+    }
+
+    pub fn grid(mut self, steps: usize) -> TargetGenerator {
+    //! Add every combination of `steps` equally spaced levels of each channel
+        let levels = levels(steps);
+        let mut grid: Vec<Vec<Float>> = vec![Vec::new()];
+        for _ in 0..self.space.channels() {
+            grid = grid.iter()
+                .flat_map(|values| levels.iter().map(move |level| {
+                    let mut values = values.clone();
+                    values.push(*level);
+                    values
+                }))
+                .collect();
+        }
+
+        self.extend_unique(grid);
+        self
+    }
+
+    pub fn ramps(mut self, steps: usize) -> TargetGenerator {
+    //! Add a ramp of `steps` levels for each channel and a neutral ramp of the
+    //! chromatic channels (CMY or RGB), like the ramps of a P2P target
+        let channels = self.space.channels();
+        let chromatic = match self.space {
+            DeviceSpace::Cmyk => 3,
+            DeviceSpace::Rgb => channels,
+        };
+
+        let mut ramps = Vec::new();
+        for channel in 0..channels {
+            for level in levels(steps) {
+                let mut values = vec![0.0; channels];
+                values[channel] = level;
+                ramps.push(values);
+            }
+        }
+        for level in levels(steps) {
+            let mut values = vec![0.0; channels];
+            values[..chromatic].iter_mut().for_each(|value| *value = level);
+            ramps.push(values);
+        }
+
+        self.extend_unique(ramps);
+        self
+    }
+
+    pub fn iso_12642(mut self) -> TargetGenerator {
+    //! Add a characterization subset built from the ISO 12642-2 tone levels:
+    //! the CMY combinations of 0, 10, 20, 40, 70 and 100 % without black and
+    //! with 40, 70 and 100 % black, and a black ramp. An RGB target gets the
+    //! RGB combinations of the same levels.
+        let mut values = Vec::new();
+        let cmy = || ISO_12642_LEVELS.iter().flat_map(|c|
+            ISO_12642_LEVELS.iter().flat_map(move |m|
+                ISO_12642_LEVELS.iter().map(move |y| [*c, *m, *y])
+            )
+        );
+
+        match self.space {
+            DeviceSpace::Rgb => values.extend(cmy().map(|rgb| rgb.to_vec())),
+            DeviceSpace::Cmyk => {
+                for k in std::iter::once(&0.0).chain(ISO_12642_BLACK) {
+                    values.extend(cmy().map(|[c, m, y]| vec![c, m, y, *k]));
+                }
+                values.extend(ISO_12642_BLACK_RAMP.iter().map(|k| vec![0.0, 0.0, 0.0, *k]));
+            },
+        }
+
+        self.extend_unique(values);
+        self
+    }
+
+    pub fn values(mut self, values: &[Vec<Float>]) -> TargetGenerator {
+    //! Add a list of device values as they are, duplicates included
+        self.values.extend(values.iter().cloned());
+        self
+    }
+
+    fn extend_unique(&mut self, values: Vec<Vec<Float>>) {
+        // Adding 0.0 turns -0.0 into 0.0, so both get the same key
+        let key = |values: &[Float]| -> Vec<u32> {
+            values.iter().map(|value| (value + 0.0).to_bits()).collect()
+        };
+        let mut seen: HashSet<Vec<u32>> = self.values.iter().map(|values| key(values)).collect();
+        for value in values {
+            if seen.insert(key(&value)) {
+                self.values.push(value);
+            }
+        }
+    }
+
+    pub fn build(&self) -> Result<Cgats> {
+    //! The target as CGATS device values with SAMPLE_IDs, alphanumeric
+    //! SAMPLE_NAMEs of their chart position and an LGOROWLENGTH.
+    //! Returns an Error if the target is empty or a patch has the wrong number of channels.
+        if self.values.is_empty() {
+            return Err(Error::NoData);
+        }
+        if self.values.iter().any(|values| values.len() != self.space.channels()) {
+            return Err(Error::FormatDataMismatch);
+        }
+
+        let row_length = self.row_length
+            .unwrap_or_else(|| (self.values.len() as Float).sqrt().ceil() as usize)
+            .max(1);
+        let layout = ChartLayout::new(self.values.len(), row_length, ScanOrder::RowMajor)?;
+
+        let mut cgats = Cgats::new_with_vendor(Vendor::Cgats);
+        cgats.fields = vec![Field::SAMPLE_ID, Field::SAMPLE_NAME];
+        cgats.fields.extend(self.space.fields());
+
+        cgats.meta = DataVec::from(
+            [
+                vec!["CGATS.17".to_string()],
+                vec!["ORIGINATOR".to_string(), "\"cgats\"".to_string()],
+                vec!["DESCRIPTOR".to_string(), format!("\"{} patch {} target\"", self.values.len(), self.space)],
+                vec!["LGOROWLENGTH".to_string(), row_length.to_string()],
+                vec!["NUMBER_OF_FIELDS".to_string(), cgats.fields.len().to_string()],
+                vec!["NUMBER_OF_SETS".to_string(), self.values.len().to_string()],
+            ].iter().cloned().map(DataLine::from).collect()
+        );
+
+        for (key, device) in self.values.iter().enumerate() {
+            let name = layout.position(key).expect("Key is in the layout").name();
+            let values = vec![CgatsValue::from_float((key + 1) as Float), CgatsValue { string: name, float: None }]
+                .into_iter()
+                .chain(device.iter().map(|value| CgatsValue::from_float(round_to(*value, 2))))
+                .collect();
+            cgats.data_map.insert(key, Sample { values });
+        }
+
+        Ok(cgats)
+    }
+}
+
+fn levels(steps: usize) -> Vec<Float> {
+    // `steps` levels from 0 to 100 %
+    match steps {
+        0 => Vec::new(),
+        1 => vec![100.0],
+        _ => (0..steps)
+            .map(|step| step as Float * 100.0 / (steps - 1) as Float)
+            .collect(),
+    }
+}
+
+#[test]
+fn generate_targets() -> Result<()> {
+    let grid = TargetGenerator::new(DeviceSpace::Cmyk).grid(3).build()?;
+    assert_eq!(grid.sample_count(), 81);
+    assert_eq!(grid.keyword("LGOROWLENGTH").as_deref(), Some("9"));
+    assert_eq!(grid.data_map[&80].values[1].string, "I9");
+    assert_eq!(grid.get_float(1, &Field::CMYK_K), Some(50.0));
+
+    // Paper white and the solids are not repeated by the ramps
+    let p2p = TargetGenerator::new(DeviceSpace::Cmyk).ramps(11).row_length(12).build()?;
+    assert_eq!(p2p.sample_count(), 1 + 4 * 10 + 10);
+    assert_eq!(p2p.layout()?.columns(), 12);
+    let signed = TargetGenerator::new(DeviceSpace::Cmyk).values(&[vec![-0.0; 4]]).ramps(11);
+    assert_eq!(signed.len(), p2p.sample_count());
+
+    let rgb = TargetGenerator::new(DeviceSpace::Rgb)
+        .values(&[vec![100.0, 100.0, 100.0], vec![100.0, 100.0, 100.0]])
+        .iso_12642()
+        .build()?;
+    assert_eq!(rgb.fields[2..], [Field::RGB_R, Field::RGB_G, Field::RGB_B]);
+    assert_eq!(rgb.sample_count(), 2 + 215);
+
+    let iso = TargetGenerator::new(DeviceSpace::Cmyk).iso_12642().build()?;
+    assert_eq!(iso.sample_count(), 4 * 216 + 11);
+
+    // Reading the target back gives the same device values
+    let reread = Cgats::from_reader(iso.format().as_bytes())?;
+    assert_eq!(TargetGenerator::from_cgats(&reread)?.values, TargetGenerator::from_cgats(&iso)?.values);
+
+    assert!(TargetGenerator::new(DeviceSpace::Cmyk).build().is_err());
+    assert!(TargetGenerator::new(DeviceSpace::Cmyk).values(&[vec![0.0; 3]]).build().is_err());
+
+    Ok(())
+}
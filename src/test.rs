@@ -3,7 +3,7 @@ use mktemp::Temp;
 use std::path::PathBuf;
 use std::fs::File;
 
-pub const GOOD_FILES: [&str; 11] = [
+pub const GOOD_FILES: [&str; 15] = [
     "test_files/cgats0.txt",
     "test_files/cgats1.tsv",
     "test_files/cgats2.tsv",
@@ -15,6 +15,10 @@ pub const GOOD_FILES: [&str; 11] = [
     "test_files/colorburst1.lin",
     "test_files/colorburst2.lin",
     "test_files/curve0.txt",
+    "test_files/argyll0.ti3",
+    "test_files/xrite0.txt",
+    "test_files/konica0.txt",
+    "test_files/techkon0.txt",
 ];
 
 pub const BAD_FILES: [&str; 4] = [
@@ -0,0 +1,395 @@
+use super::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+// Tolerance for treating a device value as 0%
+const ZERO_TINT: Float = 0.01;
+
+// Number of steps per channel in a ColorBurst LinFile: 0, 5, 10 ... 100
+pub const COLORBURST_STEPS: usize = 21;
+
+/// Process color channels of a CMYK data set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Channel {
+    Cyan,
+    Magenta,
+    Yellow,
+    Black,
+}
+
+impl Channel {
+    pub const CMYK: [Channel; 4] = [Channel::Cyan, Channel::Magenta, Channel::Yellow, Channel::Black];
+
+    pub fn field(self) -> Field {
+    //! The DATA_FORMAT field holding the device value for this channel
+        match self {
+            Channel::Cyan    => Field::CMYK_C,
+            Channel::Magenta => Field::CMYK_M,
+            Channel::Yellow  => Field::CMYK_Y,
+            Channel::Black   => Field::CMYK_K,
+        }
+    }
+
+    pub fn density_field(self) -> Field {
+    //! The complementary status density filter for this channel
+        match self {
+            Channel::Cyan    => Field::D_RED,
+            Channel::Magenta => Field::D_GREEN,
+            Channel::Yellow  => Field::D_BLUE,
+            Channel::Black   => Field::D_VIS,
+        }
+    }
+
+    fn xyz_index(self) -> usize {
+    // The tristimulus value that stands in for the density filter
+        match self {
+            Channel::Cyan    => 0,
+            Channel::Magenta => 1,
+            Channel::Yellow  => 2,
+            Channel::Black   => 1,
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Channel> {
+        match s.to_lowercase().as_str() {
+            "c" | "cyan"    | "cmyk_c" => Ok(Channel::Cyan),
+            "m" | "magenta" | "cmyk_m" => Ok(Channel::Magenta),
+            "y" | "yellow"  | "cmyk_y" => Ok(Channel::Yellow),
+            "k" | "black"   | "cmyk_k" => Ok(Channel::Black),
+            _ => Err(Error::Other(format!("Unknown channel: '{}'", s))),
+        }
+    }
+}
+
+/// How the tone value of a patch is determined from its measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMethod {
+    /// Murray-Davies, from the density of the complementary filter (ISO 12647)
+    #[default]
+    MurrayDavies,
+    /// Spot Colour Tone Value, from colorimetry (ISO 20654)
+    Sctv,
+}
+
+impl FromStr for ToneMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ToneMethod> {
+        match s.to_lowercase().as_str() {
+            "md" | "murray-davies" | "murraydavies" | "density" => Ok(ToneMethod::MurrayDavies),
+            "sctv" | "iso20654" | "iso 20654" => Ok(ToneMethod::Sctv),
+            _ => Err(Error::Other(format!("Unknown tone value method: '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for ToneMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// The measured tone value of a single ramp step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneStep {
+    pub nominal: Float,
+    pub tone_value: Float,
+    pub tvi: Float,
+}
+
+/// The print characteristic curve of one channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneCurve {
+    pub channel: Channel,
+    pub method: ToneMethod,
+    pub steps: Vec<ToneStep>,
+    curve: Curve,
+}
+
+impl ToneCurve {
+    fn new(channel: Channel, method: ToneMethod, steps: Vec<ToneStep>) -> Result<ToneCurve> {
+        let curve = Curve::new(steps.iter().map(|s| (s.nominal, s.tone_value)).collect())?;
+        Ok(ToneCurve { channel, method, steps, curve })
+    }
+
+    pub fn tone_value(&self, nominal: Float) -> Float {
+    //! Interpolated tone value at any nominal percentage
+        self.curve.interpolate(nominal)
+    }
+
+    pub fn tvi(&self, nominal: Float) -> Float {
+    //! Interpolated tone value increase (dot gain) at any nominal percentage
+        self.tone_value(nominal) - nominal
+    }
+
+    pub fn curve(&self) -> &Curve {
+    //! The nominal -> tone value Curve
+        &self.curve
+    }
+}
+
+impl fmt::Display for ToneCurve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} - ({} steps)", self.channel, self.steps.len())?;
+        for step in &self.steps {
+            writeln!(f, "\t{:>6.2}%: TV {:>6.2}  TVI {:>6.2}", step.nominal, step.tone_value, step.tvi)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tone value curves of every single-channel ramp found in a data set
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneAnalysis {
+    pub method: ToneMethod,
+    pub curves: Vec<ToneCurve>,
+}
+
+impl ToneAnalysis {
+    pub fn channel(&self, channel: Channel) -> Option<&ToneCurve> {
+    //! Returns the ToneCurve of a Channel if a ramp was found
+        self.curves.iter().find(|c| c.channel == channel)
+    }
+}
+
+impl fmt::Display for ToneAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Tone Value Method: {}\n", self.method)?;
+        for curve in &self.curves {
+            writeln!(f, "{}", curve)?;
+        }
+        Ok(())
+    }
+}
+
+impl Cgats {
+    pub fn has_cmyk(&self) -> bool {
+    //! Test if the CGATS object contains CMYK device values
+        Channel::CMYK.iter().all(|ch| self.fields.contains(&ch.field()))
+    }
+
+    pub fn channel_ramp(&self, channel: Channel) -> Result<Vec<(Float, Vec<usize>)>> {
+    //! Find the samples that only print the given Channel, grouped by tint.
+    //! Paper white (all channels at 0%) is included as the first step.
+    //! Returns an Error if there is no CMYK or no ramp with paper and solid.
+        if self.is_colorburst() {
+            return self.colorburst_ramp(channel);
+        }
+
+        if !self.has_cmyk() {
+            return Err(Error::IncompleteData);
+        }
+
+        let mut ramp: Vec<(Float, Vec<usize>)> = Vec::new();
+
+        for key in self.data_map.keys() {
+            let mut tint = None;
+            let mut others_blank = true;
+
+            for ch in Channel::CMYK.iter() {
+                let value = match self.get_float(*key, &ch.field()) {
+                    Some(value) => value,
+                    None => { others_blank = false; break },
+                };
+
+                if *ch == channel {
+                    tint = Some(value);
+                } else if value.abs() > ZERO_TINT {
+                    others_blank = false;
+                    break;
+                }
+            }
+
+            if let (true, Some(tint)) = (others_blank, tint) {
+                match ramp.iter_mut().find(|(t, _)| (t - tint).abs() < ZERO_TINT) {
+                    Some((_, keys)) => keys.push(*key),
+                    None => ramp.push((tint, vec![*key])),
+                }
+            }
+        }
+
+        ramp.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("Tint is NaN!"));
+
+        let has_paper = ramp.first().map(|(t, _)| t.abs() < ZERO_TINT).unwrap_or(false);
+        let has_solid = ramp.last().map(|(t, _)| (t - 100.0).abs() < ZERO_TINT).unwrap_or(false);
+
+        if !has_paper || !has_solid || ramp.len() < 3 {
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(ramp)
+    }
+
+    fn colorburst_ramp(&self, channel: Channel) -> Result<Vec<(Float, Vec<usize>)>> {
+    // ColorBurst LinFiles have no device values: each channel is a block of
+    // COLORBURST_STEPS samples from 0% to 100%, in CMYK order
+        let index = Channel::CMYK.iter()
+            .position(|ch| *ch == channel)
+            .expect("Channel is in CMYK!");
+
+        let keys: Vec<usize> = self.data_map.keys()
+            .skip(index * COLORBURST_STEPS)
+            .take(COLORBURST_STEPS)
+            .cloned()
+            .collect();
+
+        if keys.len() < COLORBURST_STEPS {
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(keys.into_iter().enumerate()
+            .map(|(step, key)| (step as Float * 100.0 / (COLORBURST_STEPS - 1) as Float, vec![key]))
+            .collect())
+    }
+
+    pub fn tone_analysis(&self, method: ToneMethod) -> Result<ToneAnalysis> {
+    //! Calculate tone value and TVI curves for every CMYK channel with a ramp.
+    //! Returns an Error if no channel has a complete ramp.
+        let curves: Vec<ToneCurve> = Channel::CMYK.iter()
+            .filter_map(|ch| self.tone_curve(*ch, method).ok())
+            .collect();
+
+        if curves.is_empty() {
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(ToneAnalysis { method, curves })
+    }
+
+    pub fn tone_curve(&self, channel: Channel, method: ToneMethod) -> Result<ToneCurve> {
+    //! Calculate the tone value and TVI curve of a single Channel
+        let ramp = self.channel_ramp(channel)?;
+
+        let mut measured = Vec::with_capacity(ramp.len());
+        for (tint, keys) in ramp.iter() {
+            let values = keys.iter()
+                .map(|key| self.tone_measurement(*key, channel, method))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::IncompleteData)?;
+            measured.push((*tint, mean_vector(&values)));
+        }
+
+        let paper = &measured[0].1;
+        let solid = &measured[measured.len() - 1].1;
+
+        let steps = measured.iter()
+            .map(|(nominal, value)| {
+                let tone_value = match method {
+                    ToneMethod::MurrayDavies => murray_davies(paper[0], value[0], solid[0]),
+                    ToneMethod::Sctv => sctv(paper, value, solid),
+                };
+                ToneStep { nominal: *nominal, tone_value, tvi: tone_value - nominal }
+            }).collect();
+
+        ToneCurve::new(channel, method, steps)
+    }
+
+    fn tone_measurement(&self, key: usize, channel: Channel, method: ToneMethod) -> Option<Vec<Float>> {
+    // Density of the channel filter for Murray-Davies, XYZ based V values for SCTV
+        match method {
+            ToneMethod::MurrayDavies => Some(vec![self.channel_density(key, channel)?]),
+            ToneMethod::Sctv => {
+                let xyz = self.sample_xyz(key)?;
+                Some(xyz.iter().zip(color::D50.iter())
+                    .map(|(v, n)| 116.0 * color::lab_f(v / n) - 16.0)
+                    .collect())
+            },
+        }
+    }
+
+    pub fn channel_density(&self, key: usize, channel: Channel) -> Option<Float> {
+    //! Returns the measured status density for a Channel, or a colorimetric
+    //! density from the complementary tristimulus value if there is none
+        match self.get_float(key, &channel.density_field()) {
+            Some(density) => Some(density),
+            None => {
+                let index = channel.xyz_index();
+                let xyz = self.sample_xyz(key)?;
+                Some(color::density(xyz[index] / color::D50[index]))
+            },
+        }
+    }
+}
+
+fn mean_vector(values: &[Vec<Float>]) -> Vec<Float> {
+    let len = values[0].len();
+    (0..len).map(|i|
+        values.iter().map(|v| v[i]).sum::<Float>() / values.len() as Float
+    ).collect()
+}
+
+fn murray_davies(paper: Float, tint: Float, solid: Float) -> Float {
+//! Murray-Davies tone value from densities
+    let numerator = 1.0 - (10.0 as Float).powf(-(tint - paper));
+    let denominator = 1.0 - (10.0 as Float).powf(-(solid - paper));
+    100.0 * numerator / denominator
+}
+
+fn sctv(paper: &[Float], tint: &[Float], solid: &[Float]) -> Float {
+//! ISO 20654 Spot Colour Tone Value from V values
+    let distance = |a: &[Float], b: &[Float]| a.iter().zip(b.iter())
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<Float>()
+        .sqrt();
+    100.0 * distance(tint, paper) / distance(solid, paper)
+}
+
+#[test]
+fn tone_analysis() -> Result<()> {
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+
+    for method in &[ToneMethod::MurrayDavies, ToneMethod::Sctv] {
+        let tone = crpc6.tone_analysis(*method)?;
+        println!("{}", tone);
+        assert_eq!(tone.curves.len(), 4);
+
+        for curve in &tone.curves {
+            assert_eq!(curve.steps.first().map(|s| s.tone_value), Some(0.0));
+            assert!((curve.tone_value(100.0) - 100.0).abs() < 0.001);
+            assert!(curve.curve().is_increasing());
+
+            if *method == ToneMethod::MurrayDavies {
+                assert!(curve.tvi(37.0) > 0.0);
+                assert!(curve.tvi(50.0) > 10.0 && curve.tvi(50.0) < 25.0);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn colorburst_tone_value() -> Result<()> {
+    let colorburst = Cgats::from_file("test_files/colorburst0.txt")?;
+    let tone = colorburst.tone_analysis(ToneMethod::MurrayDavies)?;
+
+    assert_eq!(tone.curves.len(), 4);
+    assert_eq!(tone.channel(Channel::Black).map(|c| c.steps.len()), Some(COLORBURST_STEPS));
+    assert!(tone.channel(Channel::Cyan).map(|c| c.tvi(50.0) > 0.0).unwrap_or(false));
+
+    Ok(())
+}
+
+#[test]
+fn density_tone_value() -> Result<()> {
+    let cgats = Cgats::from_file("test_files/cgats1.tsv")?;
+    assert!(cgats.channel_ramp(Channel::Cyan).is_err());
+    assert!(cgats.tone_analysis(ToneMethod::MurrayDavies).is_err());
+
+    assert_eq!(murray_davies(0.1, 0.1, 1.5), 0.0);
+    assert_eq!(murray_davies(0.1, 1.5, 1.5), 100.0);
+    assert!((murray_davies(0.0, 0.5, 1.5) - 70.6).abs() < 0.1);
+
+    Ok(())
+}
@@ -3,43 +3,51 @@ use super::*;
 use std::str::FromStr;
 use std::fmt;
 
-const KEYWORDS: &[&str] = &["argyll", "cti1", "cgats", "colorburst", "curve"];
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Vendor {
     Argyll,
     Cgats,
     ColorBurst,
     Curve,
+    XRite,
+    Barbieri,
+    KonicaMinolta,
+    Techkon,
     Other(String),
 }
 
-impl<'a> FromStr for Vendor {
+impl FromStr for Vendor {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Vendor> {
+        Vendor::from_name(s, &DialectRegistry::global())
+    }
+}
+
+impl Vendor {
+    pub fn from_name(s: &str, registry: &DialectRegistry) -> Result<Vendor> {
+    //! The Vendor named by a string, using the vendor names of a dialect registry
         if s.is_empty() {
             return Err(Error::UnknownVendor);
         }
 
-        let s_lower = s.to_lowercase();
-
-        for keyword in KEYWORDS.iter() {
-            if s_lower.contains(keyword) {
-                let vendor = match *keyword {
-                    "argyll" | "cti1" => Vendor::Argyll,
-                    "cgats"           => Vendor::Cgats,
-                    "colorburst"      => Vendor::ColorBurst,
-                    "curve"           => Vendor::Curve,
-                    _ => unreachable!("Vendor keyword not in list! [vendor::KEYWORDS]"),
-                };
+        // The built-in and user-registered dialects know their vendor names
+        if let Some(dialect) = registry.find_name(s) {
+            return Ok(dialect.vendor());
+        }
 
-                return Ok(vendor);
-            }
+        if s.to_lowercase().contains("cgats") {
+            return Ok(Vendor::Cgats);
         }
 
         Ok(Vendor::Other(s.to_owned()))
     }
+
+    pub fn is_generic(&self) -> bool {
+    //! A plain CGATS.17 identifier or an unknown first line, which leaves
+    //! the vendor to the header keywords
+        matches!(self, Vendor::Cgats | Vendor::Other(_))
+    }
 }
 
 impl fmt::Display for Vendor {
@@ -54,6 +62,9 @@ fn from_str() {
     assert_eq!(Vendor::from_str("CGATS.17"), Ok(Vendor::Cgats));
     assert_eq!(Vendor::from_str("File Created by Curve3"), Ok(Vendor::Curve));
     assert_eq!(Vendor::from_str("CTI1"), Ok(Vendor::Argyll));
+    assert_eq!(Vendor::from_str("CTI3"), Ok(Vendor::Argyll));
+    assert_eq!(Vendor::from_str("\"X-Rite ColorPort 2.0\""), Ok(Vendor::XRite));
+    assert_eq!(Vendor::from_str("KONICA MINOLTA"), Ok(Vendor::KonicaMinolta));
     assert_eq!(Vendor::from_str("derp"), Ok(Vendor::Other("derp".to_owned())));
     assert_eq!(Vendor::from_str(""), Err(Error::UnknownVendor));
 }
@@ -0,0 +1,402 @@
+use super::*;
+
+use deltae::color::LabValue;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::str::FromStr;
+
+// Tolerance for treating a device value as 0% or 100%
+const TINT_TOLERANCE: Float = 0.01;
+
+/// A quantity that a tolerance profile limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Criterion {
+    /// Average Delta E of all samples
+    AverageDe,
+    /// Largest Delta E of all samples
+    MaxDe,
+    /// 95th percentile Delta E of all samples
+    P95De,
+    /// Largest Delta E of the paper white samples
+    PaperDe,
+    /// Largest Delta E of the CMYK solids
+    PrimariesDe,
+    /// Largest chromatic difference (ΔCh) of the three color gray samples
+    GrayDch,
+}
+
+impl Criterion {
+    pub const ALL: [Criterion; 6] = [
+        Criterion::AverageDe,
+        Criterion::MaxDe,
+        Criterion::P95De,
+        Criterion::PaperDe,
+        Criterion::PrimariesDe,
+        Criterion::GrayDch,
+    ];
+}
+
+impl fmt::Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = match self {
+            Criterion::AverageDe   => "AVERAGE_DE",
+            Criterion::MaxDe       => "MAX_DE",
+            Criterion::P95De       => "P95_DE",
+            Criterion::PaperDe     => "PAPER_DE",
+            Criterion::PrimariesDe => "PRIMARIES_DE",
+            Criterion::GrayDch     => "GRAY_DCH",
+        };
+        f.pad(keyword)
+    }
+}
+
+impl FromStr for Criterion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Criterion> {
+        Criterion::ALL.iter()
+            .find(|criterion| criterion.to_string().eq_ignore_ascii_case(s.trim()))
+            .copied()
+            .ok_or_else(|| Error::Other(format!("Unknown tolerance criterion: '{}'", s)))
+    }
+}
+
+/// Named limits for each criterion, evaluated with one Delta E formula
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToleranceProfile {
+    name:       String,
+    formula:    DeltaEFormula,
+    tolerances: Vec<(Criterion, Float)>,
+}
+
+impl ToleranceProfile {
+    pub fn new(name: &str) -> ToleranceProfile {
+    //! An empty profile evaluated with CIEDE2000
+        ToleranceProfile {
+            name: name.to_string(),
+            formula: DeltaEFormula::default(),
+            tolerances: Vec::new(),
+        }
+    }
+
+    pub fn with_formula(mut self, formula: DeltaEFormula) -> ToleranceProfile {
+        self.formula = formula;
+        self
+    }
+
+    pub fn with_tolerance(mut self, criterion: Criterion, tolerance: Float) -> ToleranceProfile {
+    //! Set the largest passing value of a criterion, replacing any previous limit
+        match self.tolerances.iter_mut().find(|(c, _)| *c == criterion) {
+            Some(limit) => limit.1 = tolerance,
+            None => self.tolerances.push((criterion, tolerance)),
+        }
+        self
+    }
+
+    pub fn iso_12647_7() -> ToleranceProfile {
+    //! ISO 12647-7:2016 proofing control strip
+        ToleranceProfile::new("ISO12647-7")
+            .with_tolerance(Criterion::AverageDe, 2.5)
+            .with_tolerance(Criterion::MaxDe, 5.0)
+            .with_tolerance(Criterion::PaperDe, 3.0)
+            .with_tolerance(Criterion::PrimariesDe, 5.0)
+            .with_tolerance(Criterion::GrayDch, 3.5)
+    }
+
+    pub fn iso_12647_2() -> ToleranceProfile {
+    //! ISO 12647-2:2013 production print
+        ToleranceProfile::new("ISO12647-2")
+            .with_tolerance(Criterion::AverageDe, 4.0)
+            .with_tolerance(Criterion::P95De, 6.0)
+            .with_tolerance(Criterion::PaperDe, 3.0)
+            .with_tolerance(Criterion::PrimariesDe, 5.0)
+            .with_tolerance(Criterion::GrayDch, 3.0)
+    }
+
+    pub fn builtins() -> Vec<ToleranceProfile> {
+        vec![ToleranceProfile::iso_12647_7(), ToleranceProfile::iso_12647_2()]
+    }
+
+    pub fn builtin(name: &str) -> Option<ToleranceProfile> {
+    //! Find a built-in profile by name, ignoring case, spaces and dashes
+        let simplify = |s: &str| s.to_lowercase().replace(['-', ' ', '_'], "");
+        ToleranceProfile::builtins().into_iter()
+            .find(|profile| simplify(&profile.name) == simplify(name))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Vec<ToleranceProfile>> {
+    //! Read user-defined profiles from a file. Each profile starts with a `PROFILE "name"`
+    //! line, followed by an optional `FORMULA` and one line per criterion, e.g. `MAX_DE 5`.
+    //! Lines starting with `#` are comments.
+        ToleranceProfile::parse(&read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Vec<ToleranceProfile>> {
+    //! Read user-defined profiles in the format of `from_file`
+        let mut profiles: Vec<ToleranceProfile> = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, value) = match line.find(char::is_whitespace) {
+                Some(index) => (&line[..index], line[index..].trim().trim_matches('"')),
+                None => (line, ""),
+            };
+
+            let error = |message: &str| Error::Other(format!("Line {}: {}: '{}'", number + 1, message, line));
+
+            if keyword.eq_ignore_ascii_case("PROFILE") {
+                profiles.push(ToleranceProfile::new(value));
+                continue;
+            }
+
+            let profile = profiles.pop().ok_or_else(|| error("Expected PROFILE first"))?;
+            let profile = if keyword.eq_ignore_ascii_case("FORMULA") {
+                profile.with_formula(DeltaEFormula::from_str(value)?)
+            } else {
+                let criterion = Criterion::from_str(keyword)?;
+                let tolerance = value.parse::<Float>().map_err(|_| error("Invalid tolerance"))?;
+                profile.with_tolerance(criterion, tolerance)
+            };
+            profiles.push(profile);
+        }
+
+        Ok(profiles)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn formula(&self) -> DeltaEFormula {
+        self.formula
+    }
+
+    pub fn tolerances(&self) -> &[(Criterion, Float)] {
+        &self.tolerances
+    }
+
+    pub fn verify(&self, reference: &Cgats, sample: &Cgats) -> Result<Verification> {
+    //! Compare a measured sample with its reference. Paper white, solids and grays
+    //! are found by the CMYK values of the reference (or the sample).
+    //! Returns an Error if the data sets cannot be compared.
+        let cgv = CgatsVec::from(vec![reference.clone(), sample.clone()]);
+        let report = DeReport::new(&cgv.deltae_formula(self.formula)?)?;
+
+        let device = if reference.has_cmyk() { reference } else { sample };
+        let pairs = |keys: Vec<usize>| -> Vec<(LabValue, LabValue)> {
+            keys.into_iter()
+                .filter_map(|key| Some((reference.sample_lab(key)?, sample.sample_lab(key)?)))
+                .collect()
+        };
+
+        let de = |keys: Vec<usize>| largest(pairs(keys).iter().map(|(r, s)| self.formula.delta_e(r, s)));
+        let paper = device.cmyk_keys(|cmyk| cmyk.iter().all(|v| is_tint(*v, 0.0)));
+        let primaries = device.cmyk_keys(|cmyk| {
+            cmyk.iter().filter(|v| is_tint(**v, 100.0)).count() == 1
+                && cmyk.iter().filter(|v| is_tint(**v, 0.0)).count() == 3
+        });
+        let gray = pairs(gray_keys(device)).iter()
+            .map(|(r, s)| (r.a - s.a).hypot(r.b - s.b))
+            .collect::<Vec<_>>();
+
+        let mut values = report_values(&report);
+        values.push((Criterion::PaperDe, de(paper)));
+        values.push((Criterion::PrimariesDe, de(primaries)));
+        values.push((Criterion::GrayDch, largest(gray.into_iter())));
+
+        Ok(self.evaluate(values))
+    }
+
+    pub fn verify_delta(&self, de_cgats: &Cgats) -> Result<Verification> {
+    //! Evaluate a Delta E CGATS object. Without the compared data sets, only
+    //! AVERAGE_DE, MAX_DE and P95_DE can be evaluated.
+    //! Returns an Error if the data set has no DE field or no data.
+        Ok(self.evaluate(report_values(&DeReport::new(de_cgats)?)))
+    }
+
+    fn evaluate(&self, values: Vec<(Criterion, Option<Float>)>) -> Verification {
+        let results = self.tolerances.iter().map(|(criterion, tolerance)| {
+            let value = values.iter()
+                .find(|(c, _)| c == criterion)
+                .and_then(|(_, value)| *value);
+
+            let verdict = match value {
+                Some(value) if value <= *tolerance => Verdict::Pass,
+                Some(_) => Verdict::Fail,
+                None => Verdict::NotEvaluated,
+            };
+
+            CriterionResult { criterion: *criterion, tolerance: *tolerance, value, verdict }
+        }).collect();
+
+        Verification {
+            profile: self.name.clone(),
+            formula: self.formula,
+            results,
+        }
+    }
+}
+
+/// The outcome of one criterion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// The data has no samples for this criterion
+    NotEvaluated,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = match self {
+            Verdict::Pass         => "PASS",
+            Verdict::Fail         => "FAIL",
+            Verdict::NotEvaluated => "N/A",
+        };
+        write!(f, "{}", verdict)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriterionResult {
+    pub criterion: Criterion,
+    pub tolerance: Float,
+    pub value:     Option<Float>,
+    pub verdict:   Verdict,
+}
+
+impl fmt::Display for CriterionResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.value.map(|v| format!("{:0.4}", v)).unwrap_or_else(|| "-".to_string());
+        write!(f, "\t{:>12}: {:>8} <= {:0.4}\t{}", self.criterion, value, self.tolerance, self.verdict)
+    }
+}
+
+/// The verdict of every criterion of a tolerance profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub profile: String,
+    pub formula: DeltaEFormula,
+    pub results: Vec<CriterionResult>,
+}
+
+impl Verification {
+    pub fn passed(&self) -> bool {
+    //! Every criterion passed. Criteria that could not be evaluated do not pass.
+        self.results.iter().all(|result| result.verdict == Verdict::Pass)
+    }
+
+    pub fn result(&self, criterion: Criterion) -> Option<&CriterionResult> {
+        self.results.iter().find(|result| result.criterion == criterion)
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "VERIFICATION: {} ({})", self.profile, self.formula)?;
+        for result in &self.results {
+            writeln!(f, "{}", result)?;
+        }
+        writeln!(f, "RESULT: {}", if self.passed() { Verdict::Pass } else { Verdict::Fail })
+    }
+}
+
+impl Cgats {
+    fn cmyk_keys<F: Fn(&[Float]) -> bool>(&self, select: F) -> Vec<usize> {
+    // Keys of the samples whose CMYK values are selected
+        self.data_map.keys().copied().filter(|key| {
+            Channel::CMYK.iter()
+                .map(|ch| self.get_float(*key, &ch.field()))
+                .collect::<Option<Vec<_>>>()
+                .map(|cmyk| select(&cmyk))
+                .unwrap_or(false)
+        }).collect()
+    }
+}
+
+fn report_values(report: &DeReport) -> Vec<(Criterion, Option<Float>)> {
+    vec![
+        (Criterion::AverageDe, Some(report.overall().mean)),
+        (Criterion::MaxDe, Some(report.overall().max)),
+        (Criterion::P95De, Some(report.percentile(95.0))),
+    ]
+}
+
+fn gray_keys(cgats: &Cgats) -> Vec<usize> {
+// The three color gray ramp without paper white
+    cgats.gray_ramp()
+        .map(|ramp| ramp.into_iter().skip(1).flat_map(|(_, keys)| keys).collect())
+        .unwrap_or_default()
+}
+
+fn largest<I: Iterator<Item = Float>>(values: I) -> Option<Float> {
+    values.fold(None, |max, value| Some(max.map_or(value, |max: Float| max.max(value))))
+}
+
+fn is_tint(value: Float, tint: Float) -> bool {
+    (value - tint).abs() < TINT_TOLERANCE
+}
+
+#[test]
+fn builtin_profiles() -> Result<()> {
+    let crpc5 = Cgats::from_file("reference/ISO15339-CRPC5.txt")?;
+    let crpc6 = Cgats::from_file("reference/ISO15339-CRPC6.txt")?;
+
+    let same = ToleranceProfile::iso_12647_7().verify(&crpc6, &crpc6)?;
+    println!("{}", same);
+    assert!(same.passed());
+    assert_eq!(same.results.len(), 5);
+    assert!(same.results.iter().all(|result| result.value == Some(0.0)));
+
+    let different = ToleranceProfile::builtin("iso 12647-2").expect("No ISO 12647-2 profile!")
+        .verify(&crpc6, &crpc5)?;
+    println!("{}", different);
+    assert!(!different.passed());
+    assert_eq!(different.result(Criterion::AverageDe).expect("No AVERAGE_DE!").verdict, Verdict::Pass);
+    assert_eq!(different.result(Criterion::PaperDe).expect("No PAPER_DE!").verdict, Verdict::Fail);
+    assert_eq!(different.result(Criterion::PrimariesDe).expect("No PRIMARIES_DE!").verdict, Verdict::Pass);
+
+    let delta = CgatsVec::from(vec![crpc6.clone(), crpc6]).deltae_formula(DeltaEFormula::default())?;
+    let verification = ToleranceProfile::iso_12647_7().verify_delta(&delta)?;
+    assert_eq!(verification.result(Criterion::AverageDe).expect("No AVERAGE_DE!").verdict, Verdict::Pass);
+    assert_eq!(verification.result(Criterion::GrayDch).expect("No GRAY_DCH!").verdict, Verdict::NotEvaluated);
+    assert!(!verification.passed());
+
+    Ok(())
+}
+
+#[test]
+fn profile_file() -> Result<()> {
+    let profiles = ToleranceProfile::parse("
+        # Shop tolerances
+        PROFILE \"Proof\"
+        FORMULA 2000:2:1:1
+        AVERAGE_DE 1.5
+        MAX_DE 3
+
+        PROFILE Press
+        FORMULA 1976
+        p95_de 5
+        PRIMARIES_DE 4
+    ")?;
+
+    assert_eq!(profiles.len(), 2);
+    assert_eq!(profiles[0], ToleranceProfile::new("Proof")
+        .with_formula(DeltaEFormula::De2000 { kl: 2.0, kc: 1.0, kh: 1.0 })
+        .with_tolerance(Criterion::AverageDe, 1.5)
+        .with_tolerance(Criterion::MaxDe, 3.0));
+    assert_eq!(profiles[1].name(), "Press");
+    assert_eq!(profiles[1].formula(), DeltaEFormula::De1976);
+    assert_eq!(profiles[1].tolerances(), &[(Criterion::P95De, 5.0), (Criterion::PrimariesDe, 4.0)]);
+
+    assert!(ToleranceProfile::parse("MAX_DE 3").is_err());
+    assert!(ToleranceProfile::parse("PROFILE x\nMAX_DE three").is_err());
+    assert!(ToleranceProfile::parse("PROFILE x\nMIN_DE 3").is_err());
+
+    Ok(())
+}